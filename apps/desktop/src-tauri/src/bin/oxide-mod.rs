@@ -0,0 +1,208 @@
+//! Authoring tool for Oxide mods
+//!
+//! Scaffolds `mod.json`, validates and lints an unpacked mod, hashes its files and
+//! packs it into a reproducible archive that `install_mod` accepts. Publishers
+//! can also generate a signing key and sign the archives they release.
+
+use clap::{Parser, Subcommand};
+use oxide_desktop::mod_engine::package::{self, LintSeverity, DESCRIPTOR_FILE};
+use oxide_desktop::mod_engine::ModDescriptor;
+use oxide_desktop::signing::PublisherKey;
+use oxide_desktop::OxideError;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "oxide-mod", version, about = "Build and validate Oxide mods")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a mod.json in a mod directory
+    Init {
+        #[arg(long)]
+        mod_id: String,
+        #[arg(long)]
+        game_id: String,
+        #[arg(long)]
+        author: String,
+        #[arg(long, default_value = "0.1.0")]
+        version: String,
+        #[arg(long, default_value = "")]
+        description: String,
+        /// Mod directory
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Replace an existing mod.json
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check mod.json against the descriptor schema
+    Validate {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Validate mod.json and check the file layout
+    Lint {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Print the SHA-256 of every file that would be packed
+    Hash {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Build a reproducible archive ready for install_mod
+    Pack {
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+        /// Archive to write, defaults to <mod_id>-<version>.zip in the current directory
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate an ed25519 signing key for a publisher
+    Keygen {
+        #[arg(long)]
+        publisher: String,
+        /// Key file to write, keep it private
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Write a detached <artifact>.sig signature for an archive or mod directory
+    Sign {
+        artifact: PathBuf,
+        /// Key file created by keygen
+        #[arg(short, long)]
+        key: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> Result<ExitCode, OxideError> {
+    match command {
+        Command::Init {
+            mod_id,
+            game_id,
+            author,
+            version,
+            description,
+            dir,
+            force,
+        } => {
+            let path = dir.join(DESCRIPTOR_FILE);
+            if path.exists() && !force {
+                return Err(OxideError::General(format!(
+                    "{} already exists, pass --force to replace it",
+                    path.display()
+                )));
+            }
+
+            let descriptor = ModDescriptor {
+                mod_id,
+                game_id,
+                dependencies: Vec::new(),
+                description,
+                version,
+                author,
+                load_after: Vec::new(),
+                load_before: Vec::new(),
+            };
+            let problems = package::validate_descriptor(&descriptor);
+            if !problems.is_empty() {
+                return Err(OxideError::General(problems.join("; ")));
+            }
+
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(&path, serde_json::to_string_pretty(&descriptor)?)?;
+            println!("Created {}", path.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Validate { dir } => {
+            let descriptor = package::read_descriptor(&dir)?;
+            println!("{} {} is valid", descriptor.mod_id, descriptor.version);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Lint { dir } => {
+            let issues = package::lint_layout(&dir)?;
+            for issue in &issues {
+                let severity = match issue.severity {
+                    LintSeverity::Error => "error",
+                    LintSeverity::Warning => "warning",
+                };
+                match &issue.path {
+                    Some(path) => println!("{}: {}: {}", severity, path, issue.message),
+                    None => println!("{}: {}", severity, issue.message),
+                }
+            }
+
+            let errors = issues
+                .iter()
+                .filter(|issue| issue.severity == LintSeverity::Error)
+                .count();
+            println!("{} error(s), {} warning(s)", errors, issues.len() - errors);
+            Ok(if errors == 0 {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        Command::Hash { dir } => {
+            for entry in package::hash_files(&dir)? {
+                println!("{}  {}", entry.sha256, entry.path);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Pack { dir, output } => {
+            let descriptor = package::read_descriptor(&dir)?;
+            let output =
+                output.unwrap_or_else(|| PathBuf::from(package::archive_name(&descriptor)));
+
+            let summary = package::pack(&dir, &output)?;
+            println!(
+                "Packed {} file(s) into {}",
+                summary.files.len(),
+                summary.archive.display()
+            );
+            println!("sha256 {}", summary.sha256);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Keygen { publisher, output } => {
+            if output.exists() {
+                return Err(OxideError::General(format!(
+                    "{} already exists",
+                    output.display()
+                )));
+            }
+
+            let key = PublisherKey::generate(&publisher);
+            key.save(&output)?;
+            println!("Wrote signing key {} to {}", key.key_id, output.display());
+            println!("Public key for trust stores: {}", key.public_key);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Sign { artifact, key } => {
+            let key: PublisherKey = serde_json::from_str(&std::fs::read_to_string(&key)?)?;
+            let signature = key.sign(&artifact)?;
+            println!(
+                "Signed {} as {} ({})",
+                artifact.display(),
+                key.publisher,
+                key.key_id
+            );
+            println!("Wrote {}", signature.display());
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
@@ -1,124 +1,29 @@
+use oxide_desktop::models::{Game, GamesData};
+use oxide_desktop::search_index::suggest::Suggestion;
+use oxide_desktop::search_index::{
+    self, analytics, IndexUpdate, SearchFacets, SearchFilters, SearchHit, SearchRequest,
+    SearchSort,
+};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Game {
-    pub id: String,
-    pub title: String,
-    pub slug: String,
-    pub description: Option<String>,
-    pub developer: Option<String>,
-    pub publisher: Option<String>,
-    pub media: GameMedia,
-    pub pricing: GamePricing,
-    pub details: GameDetails,
-    pub stats: GameStats,
-    pub features: GameFeatures,
-    #[serde(rename = "discountPercent")]
-    pub discount_percent: Option<f64>,
-    #[serde(rename = "isHot")]
-    pub is_hot: Option<bool>,
-    #[serde(rename = "isNew")]
-    pub is_new: Option<bool>,
-    #[serde(rename = "isOnSale")]
-    pub is_on_sale: Option<bool>,
-    #[serde(rename = "isAiRecommended")]
-    pub is_ai_recommended: Option<bool>,
-    #[serde(rename = "aiScore")]
-    pub ai_score: Option<f64>,
-    #[serde(rename = "matchReason")]
-    pub match_reason: Option<String>,
-    #[serde(rename = "isInstalled")]
-    pub is_installed: Option<bool>,
-    #[serde(rename = "isInLibrary")]
-    pub is_in_library: Option<bool>,
-    #[serde(rename = "isWishlisted")]
-    pub is_wishlisted: Option<bool>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GameMedia {
-    #[serde(rename = "coverImage")]
-    pub cover_image: String,
-    pub screenshots: Vec<String>,
-    #[serde(rename = "iconUrl")]
-    pub icon_url: String,
-    pub title: String,
-    #[serde(rename = "trailerUrl")]
-    pub trailer_url: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GamePricing {
-    #[serde(rename = "basePrice")]
-    pub base_price: f64,
-    #[serde(rename = "currentPrice")]
-    pub current_price: f64,
-    pub currency: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GameDetails {
-    pub platforms: Vec<String>,
-    pub genres: Vec<String>,
-    pub tags: Vec<String>,
-    #[serde(rename = "releaseDate")]
-    pub release_date: Option<String>,
-    #[serde(rename = "lastUpdated")]
-    pub last_updated: Option<String>,
-    pub version: Option<String>,
-    #[serde(rename = "downloadSize")]
-    pub download_size: Option<String>,
-    #[serde(rename = "systemRequirements")]
-    pub system_requirements: Option<SystemRequirements>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SystemRequirements {
-    pub minimum: HashMap<String, String>,
-    pub recommended: HashMap<String, String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GameStats {
-    pub rating: f64,
-    #[serde(rename = "reviewCount")]
-    pub review_count: u32,
-    #[serde(rename = "downloadCount")]
-    pub download_count: u32,
-    #[serde(rename = "wishlistCount")]
-    pub wishlist_count: u32,
-    #[serde(rename = "playTime")]
-    pub play_time: Option<PlayTime>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PlayTime {
-    pub average: f64,
-    pub median: f64,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GameFeatures {
-    pub controller: bool,
-    #[serde(rename = "cloudPlay")]
-    pub cloud_play: bool,
-    pub offline: bool,
-    #[serde(rename = "multiPlayer")]
-    pub multi_player: bool,
-    #[serde(rename = "singlePlayer")]
-    pub single_player: bool,
-    pub achievements: bool,
-    pub workshop: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GameFilters {
     pub query: Option<String>,
     pub genres: Option<Vec<String>>,
     pub platforms: Option<Vec<String>>,
+    pub features: Option<Vec<String>>,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    pub min_rating: Option<f64>,
+    /// Release date bounds as `YYYY-MM-DD`
+    pub released_after: Option<String>,
+    pub released_before: Option<String>,
     pub sort_by: Option<String>,
     pub page: Option<u32>,
     pub limit: Option<u32>,
@@ -130,107 +35,142 @@ pub struct GameResponse {
     pub total: u32,
     pub page: u32,
     pub has_next: bool,
+    pub facets: SearchFacets,
 }
 
+/// A search result with its relevance score
 #[derive(Debug, Serialize, Deserialize)]
-pub struct GamesData {
-    pub data: Vec<Game>,
+pub struct ScoredGame {
+    #[serde(flatten)]
+    pub game: Game,
+    /// Only set when results are sorted by relevance
+    pub score: Option<f32>,
 }
 
-// Mock data loader
-fn load_mock_games() -> Result<Vec<Game>, String> {
-    let data_path = "data/complete-games.json";
-    
-    if !Path::new(data_path).exists() {
-        return Err("Mock data file not found".to_string());
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub data: Vec<ScoredGame>,
+    pub total: u32,
+    pub page: u32,
+    pub has_next: bool,
+    pub facets: SearchFacets,
+}
+
+/// The game catalog as parsed from its file
+#[derive(Clone)]
+pub(crate) struct Catalog {
+    /// SHA-256 of the catalog file, the search index skips a catalog it has seen
+    pub version: String,
+    pub games: Arc<Vec<Game>>,
+}
+
+/// Size and modification time of the catalog file when it was last parsed
+type CatalogStamp = (u64, Option<SystemTime>);
+
+static CATALOG: Mutex<Option<(CatalogStamp, Catalog)>> = Mutex::new(None);
+
+// Mock data loader, the file is only read again after it changed
+pub(crate) fn load_catalog() -> Result<Catalog, String> {
+    let data_path = Path::new("data/complete-games.json");
+    let metadata = fs::metadata(data_path).map_err(|_| "Mock data file not found".to_string())?;
+    let stamp = (metadata.len(), metadata.modified().ok());
+
+    let mut cached = CATALOG
+        .lock()
+        .map_err(|_| "Catalog lock poisoned".to_string())?;
+    if let Some((cached_stamp, catalog)) = cached.as_ref() {
+        if *cached_stamp == stamp {
+            return Ok(catalog.clone());
+        }
     }
 
-    let contents = fs::read_to_string(data_path)
+    let contents = fs::read(data_path)
         .map_err(|e| format!("Failed to read mock data: {}", e))?;
 
-    let games_data: GamesData = serde_json::from_str(&contents)
+    let games_data: GamesData = serde_json::from_slice(&contents)
         .map_err(|e| format!("Failed to parse mock data: {}", e))?;
 
-    Ok(games_data.data)
+    let catalog = Catalog {
+        version: format!("{:x}", Sha256::digest(&contents)),
+        games: Arc::new(games_data.data),
+    };
+    *cached = Some((stamp, catalog.clone()));
+    Ok(catalog)
+}
+
+pub(crate) fn load_mock_games() -> Result<Vec<Game>, String> {
+    Ok(load_catalog()?.games.to_vec())
+}
+
+/// Match search hits back to catalog games, keeping the hit order
+fn resolve_hits(games: &[Game], hits: Vec<SearchHit>) -> Vec<ScoredGame> {
+    let by_id: HashMap<&str, &Game> = games.iter().map(|game| (game.id.as_str(), game)).collect();
+    hits.into_iter()
+        .filter_map(|hit| {
+            by_id.get(hit.game_id.as_str()).map(|game| ScoredGame {
+                game: (*game).clone(),
+                score: hit.score,
+            })
+        })
+        .collect()
 }
 
 // Get all games with filtering
 #[tauri::command]
 pub async fn get_games(filters: Option<GameFilters>) -> Result<GameResponse, String> {
-    let mut games = load_mock_games()?;
-    
-    // Apply filters
-    if let Some(f) = filters {
-        // Search filter
-        if let Some(query) = f.query {
-            let query_lower = query.to_lowercase();
-            games.retain(|game| {
-                game.title.to_lowercase().contains(&query_lower) ||
-                game.details.tags.iter().any(|tag| tag.to_lowercase().contains(&query_lower)) ||
-                game.details.genres.iter().any(|genre| genre.to_lowercase().contains(&query_lower))
-            });
-        }
-        
-        // Genre filter
-        if let Some(genres) = f.genres {
-            games.retain(|game| {
-                genres.iter().any(|genre| game.details.genres.contains(genre))
-            });
-        }
-        
-        // Platform filter
-        if let Some(platforms) = f.platforms {
-            games.retain(|game| {
-                platforms.iter().any(|platform| game.details.platforms.contains(platform))
-            });
-        }
-        
-        // Sort games
-        if let Some(sort_by) = f.sort_by {
-            match sort_by.as_str() {
-                "newest" => games.sort_by(|a, b| {
-                    let a_date = a.details.release_date.as_deref().unwrap_or("1970-01-01");
-                    let b_date = b.details.release_date.as_deref().unwrap_or("1970-01-01");
-                    b_date.cmp(a_date)
-                }),
-                "rating" => games.sort_by(|a, b| b.stats.rating.partial_cmp(&a.stats.rating).unwrap_or(std::cmp::Ordering::Equal)),
-                "price-low" => games.sort_by(|a, b| a.pricing.current_price.partial_cmp(&b.pricing.current_price).unwrap_or(std::cmp::Ordering::Equal)),
-                "price-high" => games.sort_by(|a, b| b.pricing.current_price.partial_cmp(&a.pricing.current_price).unwrap_or(std::cmp::Ordering::Equal)),
-                "ai-score" => games.sort_by(|a, b| {
-                    let a_score = a.ai_score.unwrap_or(0.0);
-                    let b_score = b.ai_score.unwrap_or(0.0);
-                    b_score.partial_cmp(&a_score).unwrap_or(std::cmp::Ordering::Equal)
-                }),
-                _ => games.sort_by(|a, b| b.stats.download_count.cmp(&a.stats.download_count)),
-            }
-        } else {
-            games.sort_by(|a, b| b.stats.download_count.cmp(&a.stats.download_count));
-        }
-        
-        // Apply pagination
-        let page = f.page.unwrap_or(1);
-        let limit = f.limit.unwrap_or(20);
-        let start = ((page - 1) * limit) as usize;
-        let end = (start + limit as usize).min(games.len());
-        
-        let total = games.len() as u32;
-        let paginated_games = games[start..end].to_vec();
-        let has_next = end < games.len();
-        
-        return Ok(GameResponse {
-            data: paginated_games,
-            total,
-            page,
-            has_next,
-        });
-    }
-    
-    // Return all games if no filters
+    let Catalog { version, games } = load_catalog()?;
+    search_index::index_games(&games, &version)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Without filters the whole catalog is returned on one page
+    let limit = match &filters {
+        Some(f) => f.limit.unwrap_or(20),
+        None => games.len() as u32,
+    };
+    let f = filters.unwrap_or_default();
+    let page = f.page.unwrap_or(1).max(1);
+    let offset = ((page - 1) * limit) as usize;
+
+    let sort = match f.sort_by.as_deref() {
+        Some("newest") => SearchSort::Newest,
+        Some("rating") => SearchSort::Rating,
+        Some("price-low") => SearchSort::PriceLow,
+        Some("price-high") => SearchSort::PriceHigh,
+        Some("ai-score") => SearchSort::AiScore,
+        Some("relevance") => SearchSort::Relevance,
+        _ => SearchSort::Popular,
+    };
+
+    let request = SearchRequest {
+        query: f.query.unwrap_or_default(),
+        filters: SearchFilters {
+            genres: f.genres.unwrap_or_default(),
+            platforms: f.platforms.unwrap_or_default(),
+            features: f.features.unwrap_or_default(),
+            min_price: f.min_price,
+            max_price: f.max_price,
+            min_rating: f.min_rating,
+            released_after: f.released_after,
+            released_before: f.released_before,
+        },
+        sort,
+        offset,
+        limit: limit as usize,
+    };
+    let results = search_index::search_games(request)
+        .await
+        .map_err(|e| e.to_string())?;
+
     Ok(GameResponse {
-        data: games.clone(),
-        total: games.len() as u32,
-        page: 1,
-        has_next: false,
+        data: resolve_hits(&games, results.hits)
+            .into_iter()
+            .map(|scored| scored.game)
+            .collect(),
+        total: results.total as u32,
+        page,
+        has_next: offset + (limit as usize) < results.total,
+        facets: results.facets,
     })
 }
 
@@ -320,34 +260,94 @@ pub async fn get_games_by_category(category_slug: String, page: Option<u32>, lim
             total: 0,
             page: page.unwrap_or(1),
             has_next: false,
+            facets: SearchFacets::default(),
         });
     }
     
     let filters = GameFilters {
-        query: None,
         genres: Some(vec![genre.unwrap()]),
-        platforms: None,
-        sort_by: None,
         page,
         limit,
+        ..GameFilters::default()
     };
     
     get_games(Some(filters)).await
 }
 
-// Search games
+// Search games through the search index, best matches first unless sorted otherwise
 #[tauri::command]
-pub async fn search_games(query: String, page: Option<u32>, limit: Option<u32>) -> Result<GameResponse, String> {
-    let filters = GameFilters {
-        query: Some(query),
-        genres: None,
-        platforms: None,
-        sort_by: None,
-        page,
-        limit,
+pub async fn search_games(
+    query: String,
+    filters: Option<SearchFilters>,
+    sort: Option<SearchSort>,
+    page: Option<u32>,
+    limit: Option<u32>,
+) -> Result<SearchResponse, String> {
+    let Catalog { version, games } = load_catalog()?;
+
+    // Only does any work when the catalog changed since the index was last updated
+    search_index::index_games(&games, &version)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let page = page.unwrap_or(1).max(1);
+    let limit = limit.unwrap_or(20);
+    let offset = ((page - 1) * limit) as usize;
+
+    let request = SearchRequest {
+        query,
+        filters: filters.unwrap_or_default(),
+        sort: sort.unwrap_or_default(),
+        offset,
+        limit: limit as usize,
     };
-    
-    get_games(Some(filters)).await
+    let results = search_index::search_games(request.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Only the first page is a new search, later pages belong to the same one
+    if page == 1 {
+        let logged = analytics::record_search(
+            &request.query,
+            results.total,
+            !request.filters.is_empty(),
+        )
+        .await;
+        if let Err(e) = logged {
+            eprintln!("Failed to log search: {}", e);
+        }
+    }
+
+    Ok(SearchResponse {
+        data: resolve_hits(&games, results.hits),
+        total: results.total as u32,
+        page,
+        has_next: offset + (limit as usize) < results.total,
+        facets: results.facets,
+    })
+}
+
+// Autocomplete suggestions for the search box
+#[tauri::command]
+pub async fn suggest(prefix: String, limit: Option<u32>) -> Result<Vec<Suggestion>, String> {
+    // Suggestions are built with the index, later keystrokes skip the catalog
+    if !search_index::has_suggestions() {
+        let Catalog { version, games } = load_catalog()?;
+        search_index::index_games(&games, &version)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    search_index::suggest(&prefix, limit.unwrap_or(8) as usize).map_err(|e| e.to_string())
+}
+
+// Rebuild the search index from the catalog
+#[tauri::command]
+pub async fn rebuild_search_index() -> Result<IndexUpdate, String> {
+    let Catalog { version, games } = load_catalog()?;
+    search_index::rebuild_index(&games, &version)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 // For production, replace these functions with API calls:
@@ -1 +1,5 @@
-pub mod games; 
\ No newline at end of file
+pub mod games;
+pub mod mods;
+pub mod search;
+pub mod sync;
+pub mod trust;
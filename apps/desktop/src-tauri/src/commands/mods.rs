@@ -0,0 +1,362 @@
+use oxide_desktop::mod_engine::{
+    self,
+    conflicts::{self, ConflictRules, ModConflict},
+    deploy::{self, DeploySummary, DeploymentMode, DeploymentSettings, ModifiedFile, PurgeReport},
+    load_order::{self, LoadOrder},
+    profiles::{self, ModProfiles, ProfileImportReport},
+    updates::{self, ModUpdate, RepositorySettings},
+    ModDescriptor,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter};
+
+/// Emitted while a mod archive is extracted, payload is `InstallProgress`
+const INSTALL_PROGRESS_EVENT: &str = "mod-install-progress";
+/// Emitted whenever the installed, enabled or ordered mods of a game change
+const MODS_CHANGED_EVENT: &str = "mods-changed";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModsChanged {
+    pub game_id: String,
+    pub reason: &'static str,
+}
+
+fn emit_mods_changed(app: &AppHandle, game_id: &str, reason: &'static str) {
+    let payload = ModsChanged {
+        game_id: game_id.to_string(),
+        reason,
+    };
+    if let Err(e) = app.emit(MODS_CHANGED_EVENT, payload) {
+        eprintln!("Failed to emit {}: {}", MODS_CHANGED_EVENT, e);
+    }
+
+    // The unified search lists installed mods
+    if matches!(
+        reason,
+        "installed" | "uninstalled" | "upgraded" | "rolled_back"
+    ) {
+        tauri::async_runtime::spawn(async {
+            if let Err(e) = super::search::index_mods().await {
+                eprintln!("Failed to index mods: {}", e);
+            }
+        });
+    }
+}
+
+// Install a mod archive
+#[tauri::command]
+pub async fn install_mod(
+    app: AppHandle,
+    game_id: String,
+    mod_path: String,
+) -> Result<String, String> {
+    let progress_app = app.clone();
+    let message =
+        mod_engine::install_mod_with_progress(game_id.clone(), mod_path, move |progress| {
+            let _ = progress_app.emit(INSTALL_PROGRESS_EVENT, progress);
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "installed");
+    Ok(message)
+}
+
+// Uninstall a mod
+#[tauri::command]
+pub async fn uninstall_mod(
+    app: AppHandle,
+    game_id: String,
+    mod_id: String,
+) -> Result<String, String> {
+    let message = mod_engine::uninstall_mod(game_id.clone(), mod_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "uninstalled");
+    Ok(message)
+}
+
+// List installed mods in load order
+#[tauri::command]
+pub async fn list_mods(game_id: String) -> Result<Vec<ModDescriptor>, String> {
+    mod_engine::list_mods(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Get the mods enabled in the active profile
+#[tauri::command]
+pub async fn load_mod_config(game_id: String) -> Result<HashMap<String, ModDescriptor>, String> {
+    mod_engine::load_mod_config(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Conflicts
+#[tauri::command]
+pub async fn get_mod_conflicts(game_id: String) -> Result<Vec<ModConflict>, String> {
+    conflicts::get_mod_conflicts(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_conflict_rules(game_id: String) -> Result<ConflictRules, String> {
+    conflicts::get_conflict_rules(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_mod_winner(
+    app: AppHandle,
+    game_id: String,
+    winner: String,
+    loser: String,
+) -> Result<ConflictRules, String> {
+    let rules = conflicts::set_mod_winner(game_id.clone(), winner, loser)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "conflict_rules");
+    Ok(rules)
+}
+
+#[tauri::command]
+pub async fn set_file_winner(
+    app: AppHandle,
+    game_id: String,
+    path: String,
+    winner: Option<String>,
+) -> Result<ConflictRules, String> {
+    let rules = conflicts::set_file_winner(game_id.clone(), path, winner)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "conflict_rules");
+    Ok(rules)
+}
+
+// Load order
+#[tauri::command]
+pub async fn get_load_order(game_id: String) -> Result<LoadOrder, String> {
+    load_order::get_load_order(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn sort_load_order(app: AppHandle, game_id: String) -> Result<LoadOrder, String> {
+    let order = load_order::sort_load_order(game_id.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "load_order");
+    Ok(order)
+}
+
+#[tauri::command]
+pub async fn set_load_order(
+    app: AppHandle,
+    game_id: String,
+    order: Vec<String>,
+) -> Result<LoadOrder, String> {
+    let order = load_order::set_load_order(game_id.clone(), order)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "load_order");
+    Ok(order)
+}
+
+// Profiles
+#[tauri::command]
+pub async fn list_mod_profiles(game_id: String) -> Result<ModProfiles, String> {
+    profiles::list_mod_profiles(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_mod_profile(
+    app: AppHandle,
+    game_id: String,
+    name: String,
+    copy_from: Option<String>,
+) -> Result<ModProfiles, String> {
+    let profiles = profiles::create_mod_profile(game_id.clone(), name, copy_from)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "profile_created");
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub async fn delete_mod_profile(
+    app: AppHandle,
+    game_id: String,
+    name: String,
+) -> Result<ModProfiles, String> {
+    let profiles = profiles::delete_mod_profile(game_id.clone(), name)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "profile_deleted");
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub async fn switch_mod_profile(
+    app: AppHandle,
+    game_id: String,
+    name: String,
+) -> Result<ModProfiles, String> {
+    let profiles = profiles::switch_mod_profile(game_id.clone(), name)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "profile_switched");
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub async fn set_mod_enabled(
+    app: AppHandle,
+    game_id: String,
+    mod_id: String,
+    enabled: bool,
+) -> Result<ModProfiles, String> {
+    let profiles = profiles::set_mod_enabled(game_id.clone(), mod_id, enabled)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, if enabled { "enabled" } else { "disabled" });
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub async fn export_mod_profile(
+    game_id: String,
+    name: String,
+    destination: String,
+) -> Result<String, String> {
+    profiles::export_mod_profile(game_id, name, destination)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_mod_profile(
+    app: AppHandle,
+    game_id: String,
+    source: String,
+) -> Result<ProfileImportReport, String> {
+    let report = profiles::import_mod_profile(game_id.clone(), source)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "profile_imported");
+    Ok(report)
+}
+
+// Deployment
+#[tauri::command]
+pub async fn deploy_mods(app: AppHandle, game_id: String) -> Result<DeploySummary, String> {
+    let summary = deploy::deploy_mods(game_id.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "deployed");
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn purge_mods(app: AppHandle, game_id: String) -> Result<PurgeReport, String> {
+    let report = deploy::purge_mods(game_id.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "purged");
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn get_modified_files(game_id: String) -> Result<Vec<ModifiedFile>, String> {
+    deploy::get_modified_files(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_deployment_settings(game_id: String) -> Result<DeploymentSettings, String> {
+    deploy::get_deployment_settings(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_deployment_mode(
+    app: AppHandle,
+    game_id: String,
+    mode: DeploymentMode,
+) -> Result<DeploySummary, String> {
+    let summary = deploy::set_deployment_mode(game_id.clone(), mode)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "deployed");
+    Ok(summary)
+}
+
+// Updates
+#[tauri::command]
+pub async fn check_mod_updates(game_id: String) -> Result<Vec<ModUpdate>, String> {
+    updates::check_mod_updates(game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn upgrade_mod(
+    app: AppHandle,
+    game_id: String,
+    mod_id: String,
+) -> Result<String, String> {
+    let message = updates::upgrade_mod(game_id.clone(), mod_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "upgraded");
+    Ok(message)
+}
+
+#[tauri::command]
+pub async fn rollback_mod(
+    app: AppHandle,
+    game_id: String,
+    mod_id: String,
+) -> Result<String, String> {
+    let message = updates::rollback_mod(game_id.clone(), mod_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    emit_mods_changed(&app, &game_id, "rolled_back");
+    Ok(message)
+}
+
+#[tauri::command]
+pub async fn get_mod_repository() -> Result<RepositorySettings, String> {
+    updates::get_mod_repository()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_mod_repository(index_url: String) -> Result<RepositorySettings, String> {
+    updates::set_mod_repository(index_url)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,151 @@
+use super::games::{load_catalog, Catalog};
+use oxide_desktop::mod_engine;
+use oxide_desktop::models::{AchievementsData, DevelopersData, Friend};
+use oxide_desktop::search_index::{
+    self,
+    analysis::SearchSettings,
+    analytics::{self, AnalyticsSettings, SearchReport},
+    entities::{Entity, EntityGroup, EntityKind},
+};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::Path;
+
+// Mock data loader for the records the unified search covers
+fn load_mock_data<T: DeserializeOwned>(file_name: &str) -> Result<T, String> {
+    let data_path = format!("data/{}", file_name);
+
+    if !Path::new(&data_path).exists() {
+        return Err(format!("Mock data file not found: {}", data_path));
+    }
+
+    let contents = fs::read_to_string(&data_path)
+        .map_err(|e| format!("Failed to read {}: {}", data_path, e))?;
+
+    serde_json::from_str(&contents).map_err(|e| format!("Failed to parse {}: {}", data_path, e))
+}
+
+/// Bring every kind of record in the index up to date, unchanged kinds are skipped
+///
+/// Run at startup, searches only read the index.
+pub(crate) async fn index_all() -> Result<(), String> {
+    let Catalog { version, games } = load_catalog()?;
+    search_index::index_games(&games, &version)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let developers: DevelopersData = load_mock_data("developers.json")?;
+    let achievements: AchievementsData = load_mock_data("achievements.json")?;
+    let friends: Vec<Friend> = load_mock_data("friends.json")?;
+
+    let records: [(EntityKind, Vec<Entity>); 3] = [
+        (
+            EntityKind::Developer,
+            developers.data.iter().map(Entity::from).collect(),
+        ),
+        (
+            EntityKind::Achievement,
+            // Hidden achievements would spoil the game
+            achievements
+                .data
+                .iter()
+                .filter(|achievement| !achievement.is_hidden)
+                .map(Entity::from)
+                .collect(),
+        ),
+        (
+            EntityKind::Friend,
+            friends.iter().map(Entity::from).collect(),
+        ),
+    ];
+    for (kind, entities) in records {
+        search_index::index_entities(kind, &entities)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    index_mods().await
+}
+
+/// Bring the installed mods in the index up to date, after mods were installed or removed
+pub(crate) async fn index_mods() -> Result<(), String> {
+    let mods = mod_engine::list_all_mods()
+        .await
+        .map_err(|e| e.to_string())?;
+    let entities: Vec<Entity> = mods.iter().map(Entity::from).collect();
+    search_index::index_entities(EntityKind::Mod, &entities)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Search games, developers, installed mods, achievements and friends from one box
+#[tauri::command]
+pub async fn search_all(query: String, limit: Option<u32>) -> Result<Vec<EntityGroup>, String> {
+    search_index::search_all(query, limit.unwrap_or(5) as usize)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Search language and synonyms
+#[tauri::command]
+pub async fn get_search_settings() -> Result<SearchSettings, String> {
+    search_index::get_search_settings().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_search_locale(locale: Option<String>) -> Result<SearchSettings, String> {
+    let settings = search_index::set_search_locale(locale).map_err(|e| e.to_string())?;
+    // The new language's index starts empty, fill it before the next unified search
+    index_all().await?;
+    Ok(settings)
+}
+
+#[tauri::command]
+pub async fn set_search_synonyms(synonyms: Vec<Vec<String>>) -> Result<SearchSettings, String> {
+    search_index::set_search_synonyms(synonyms).map_err(|e| e.to_string())
+}
+
+// Search analytics, recorded locally and only when enabled
+#[tauri::command]
+pub async fn record_search_click(
+    query: String,
+    game_id: String,
+    position: u32,
+) -> Result<(), String> {
+    analytics::record_click(&query, game_id, position as usize)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_search_report() -> Result<SearchReport, String> {
+    analytics::search_report().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_search_report(destination: String) -> Result<String, String> {
+    analytics::export_search_report(destination)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_search_analytics_settings() -> Result<AnalyticsSettings, String> {
+    analytics::get_analytics_settings()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_search_analytics_enabled(enabled: bool) -> Result<AnalyticsSettings, String> {
+    analytics::set_analytics_enabled(enabled)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_search_log() -> Result<(), String> {
+    analytics::clear_search_log()
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,256 @@
+use oxide_desktop::store::PlaySession;
+use oxide_desktop::sync::backend::{self, SyncBackendConfig};
+use oxide_desktop::sync::crypto::{self, EncryptionStatus};
+use oxide_desktop::sync::saves::{
+    self, SaveConflict, SaveSnapshot, SaveSyncOutcome, SaveSyncSettings, SnapshotReason,
+};
+use oxide_desktop::sync::scheduler::{self, SyncRun, SyncSchedule};
+use oxide_desktop::sync::{self, ConflictSide, SyncConflict, SyncStatus};
+use tauri::{AppHandle, Emitter};
+
+/// Emitted after every background sync, payload is `SyncRun`
+const SYNC_COMPLETED_EVENT: &str = "sync-completed";
+
+/// Sync in the background for as long as the app runs
+pub fn start_sync_scheduler(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(scheduler::run_scheduler(move |run: &SyncRun| {
+        if let Err(e) = app.emit(SYNC_COMPLETED_EVENT, run) {
+            eprintln!("Failed to emit {}: {}", SYNC_COMPLETED_EVENT, e);
+        }
+    }));
+}
+
+// Merge the local library with the cloud copy
+#[tauri::command]
+pub async fn sync_library() -> Result<String, String> {
+    sync::sync_library().await.map_err(|e| e.to_string())
+}
+
+// Get when the library was last synced
+#[tauri::command]
+pub async fn get_sync_status() -> Result<SyncStatus, String> {
+    sync::get_sync_status().await.map_err(|e| e.to_string())
+}
+
+// Retry syncs queued while the cloud was unreachable
+#[tauri::command]
+pub async fn retry_pending_sync() -> Result<SyncStatus, String> {
+    sync::retry_pending_sync().await.map_err(|e| e.to_string())
+}
+
+// Get conflicting edits left to review
+#[tauri::command]
+pub async fn get_sync_conflicts() -> Result<Vec<SyncConflict>, String> {
+    sync::get_sync_conflicts().await.map_err(|e| e.to_string())
+}
+
+// Keep the local or remote side of a conflicting edit
+#[tauri::command]
+pub async fn resolve_sync_conflict(
+    conflict_id: String,
+    choice: ConflictSide,
+) -> Result<Vec<SyncConflict>, String> {
+    sync::resolve_sync_conflict(&conflict_id, choice)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Get when the library is synced in the background
+#[tauri::command]
+pub async fn get_sync_schedule() -> Result<SyncSchedule, String> {
+    scheduler::get_sync_schedule()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Turn background sync on or off and set how often it runs
+#[tauri::command]
+pub async fn set_sync_schedule(schedule: SyncSchedule) -> Result<SyncSchedule, String> {
+    scheduler::set_sync_schedule(schedule)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Get where the library and saves are synced to
+#[tauri::command]
+pub async fn get_sync_backend() -> Result<SyncBackendConfig, String> {
+    backend::get_sync_backend().await.map_err(|e| e.to_string())
+}
+
+// Choose where the library and saves are synced to
+#[tauri::command]
+pub async fn set_sync_backend(config: SyncBackendConfig) -> Result<String, String> {
+    backend::set_sync_backend(config)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Get whether synced data is encrypted and unlocked on this machine
+#[tauri::command]
+pub async fn get_sync_encryption() -> Result<EncryptionStatus, String> {
+    crypto::get_sync_encryption()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Encrypt synced data with a passphrase, returns the recovery key to show once
+#[tauri::command]
+pub async fn enable_sync_encryption(passphrase: String) -> Result<String, String> {
+    crypto::enable_sync_encryption(&passphrase)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Unlock encrypted synced data on this machine
+#[tauri::command]
+pub async fn unlock_sync_encryption(passphrase: String) -> Result<EncryptionStatus, String> {
+    crypto::unlock_sync_encryption(&passphrase)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Unlock with the recovery key and set a new passphrase
+#[tauri::command]
+pub async fn recover_sync_encryption(
+    recovery_key: String,
+    new_passphrase: String,
+) -> Result<EncryptionStatus, String> {
+    crypto::recover_sync_encryption(&recovery_key, &new_passphrase)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Change the sync encryption passphrase
+#[tauri::command]
+pub async fn change_sync_passphrase(
+    current_passphrase: String,
+    new_passphrase: String,
+) -> Result<(), String> {
+    crypto::change_sync_passphrase(&current_passphrase, &new_passphrase)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Encrypt newly synced data with a fresh key
+#[tauri::command]
+pub async fn rotate_sync_key() -> Result<EncryptionStatus, String> {
+    crypto::rotate_sync_key().await.map_err(|e| e.to_string())
+}
+
+// Write a new recovery key to a file
+#[tauri::command]
+pub async fn export_recovery_key(
+    passphrase: String,
+    destination: String,
+) -> Result<String, String> {
+    crypto::export_recovery_key(&passphrase, &destination)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Get the save path patterns of a game
+#[tauri::command]
+pub async fn get_save_paths(game_id: String) -> Result<Vec<String>, String> {
+    saves::get_save_paths(&game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Set the save path patterns of a game, an empty list uses the game manifest's
+#[tauri::command]
+pub async fn set_save_paths(game_id: String, patterns: Vec<String>) -> Result<(), String> {
+    saves::set_save_paths(&game_id, patterns)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// List the save snapshots of a game, newest first
+#[tauri::command]
+pub async fn list_save_snapshots(game_id: String) -> Result<Vec<SaveSnapshot>, String> {
+    saves::list_save_snapshots(&game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Snapshot a game's saves now
+#[tauri::command]
+pub async fn take_save_snapshot(game_id: String) -> Result<Option<SaveSnapshot>, String> {
+    saves::take_save_snapshot(&game_id, SnapshotReason::Manual)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Put the saves of an earlier snapshot back
+#[tauri::command]
+pub async fn restore_save_snapshot(
+    game_id: String,
+    snapshot_id: String,
+) -> Result<Option<SaveSnapshot>, String> {
+    saves::restore_save_snapshot(&game_id, &snapshot_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Sync a game's saves with the cloud
+#[tauri::command]
+pub async fn sync_saves(game_id: String) -> Result<SaveSyncOutcome, String> {
+    saves::sync_saves(&game_id).await.map_err(|e| e.to_string())
+}
+
+// Get the games whose saves changed on this machine and in the cloud
+#[tauri::command]
+pub async fn get_save_conflicts() -> Result<Vec<SaveConflict>, String> {
+    saves::get_save_conflicts().await.map_err(|e| e.to_string())
+}
+
+// Keep this machine's or the cloud's saves of a game
+#[tauri::command]
+pub async fn resolve_save_conflict(
+    game_id: String,
+    choice: ConflictSide,
+) -> Result<SaveSyncOutcome, String> {
+    saves::resolve_save_conflict(&game_id, choice)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Snapshot a game's saves before it starts
+#[tauri::command]
+pub async fn begin_game_session(game_id: String) -> Result<Option<SaveSnapshot>, String> {
+    saves::begin_game_session(&game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Snapshot and sync a game's saves after it exits
+#[tauri::command]
+pub async fn end_game_session(game_id: String) -> Result<SaveSyncOutcome, String> {
+    saves::end_game_session(&game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Get when a game was played, most recent session first
+#[tauri::command]
+pub async fn get_play_sessions(game_id: String) -> Result<Vec<PlaySession>, String> {
+    saves::get_play_sessions(&game_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Get the cloud save settings
+#[tauri::command]
+pub async fn get_save_sync_settings() -> Result<SaveSyncSettings, String> {
+    saves::get_save_sync_settings()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Set how many save snapshots are kept per game
+#[tauri::command]
+pub async fn set_save_versions_kept(keep_versions: usize) -> Result<SaveSyncSettings, String> {
+    saves::set_save_versions_kept(keep_versions)
+        .await
+        .map_err(|e| e.to_string())
+}
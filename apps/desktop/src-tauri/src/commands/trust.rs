@@ -0,0 +1,34 @@
+use oxide_desktop::signing::{self, SignaturePolicy, TrustStore};
+
+// Get the trusted publisher keys and signature policy
+#[tauri::command]
+pub async fn get_trust_store() -> Result<TrustStore, String> {
+    signing::get_trust_store().await.map_err(|e| e.to_string())
+}
+
+// Trust a publisher's public key
+#[tauri::command]
+pub async fn trust_publisher_key(
+    publisher: String,
+    public_key: String,
+) -> Result<TrustStore, String> {
+    signing::trust_publisher_key(publisher, public_key)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Stop trusting a publisher key
+#[tauri::command]
+pub async fn revoke_publisher_key(publisher: String, key_id: String) -> Result<TrustStore, String> {
+    signing::revoke_publisher_key(publisher, key_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Set how unsigned mods and game builds are handled
+#[tauri::command]
+pub async fn set_signature_policy(policy: SignaturePolicy) -> Result<TrustStore, String> {
+    signing::set_signature_policy(policy)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -1,11 +1,17 @@
+use crate::signing::{self, signature_path};
+use crate::store::{self, InstallRecord};
 use crate::OxideError;
-use reqwest::Client;
-use std::path::PathBuf;
+use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
 use tokio::fs::{self, File};
 use tokio::io::AsyncWriteExt;
 use futures_util::StreamExt;
 
 /// Download a game from a URL to a local directory
+///
+/// The build's detached signature is fetched from `<url>.sig` and checked
+/// against the trust store before the download is kept.
 pub async fn download_game(
     _window: tauri::Window,
     game_id: String,
@@ -13,16 +19,12 @@ pub async fn download_game(
 ) -> Result<String, OxideError> {
     let client = Client::new();
     let response = client.get(&_url).send().await?;
-    let total_size = response.content_length().unwrap_or(0);
 
     // Create games directory
-    let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-        OxideError::FileOperationError("Failed to get home directory".to_string())
-    })?;
-    let game_dir = home_dir.join("Oxide").join("Games").join(&game_id);
+    let game_dir = game_install_dir(&game_id)?;
 
     if !game_dir.exists() {
-        fs::create_dir_all(&game_dir).map_err(|e| {
+        fs::create_dir_all(&game_dir).await.map_err(|e| {
             OxideError::FileOperationError(format!("Failed to create game directory: {}", e))
         })?;
     }
@@ -31,26 +33,83 @@ pub async fn download_game(
     let file_name = _url.split("/").last().unwrap_or("game.zip");
     let dest_path = game_dir.join(file_name);
 
+    // The build is verified against the bytes as they arrive rather than the
+    // file on disk, which could change before it is checked
     let mut out = File::create(&dest_path).await?;
     let mut stream = response.bytes_stream();
-    let mut downloaded: u64 = 0;
+    let mut hasher = Sha256::new();
 
     while let Some(item) = stream.next().await {
         let chunk = item?;
         out.write_all(&chunk).await?;
-        downloaded += chunk.len() as u64;
-        // progress_handler(downloaded, total_size); // This line was removed as per the edit hint
+        hasher.update(&chunk);
+    }
+    out.flush().await?;
+    let digest = hasher.finalize();
+
+    // A signature left over from an earlier download does not cover this build
+    let signature_file = signature_path(&dest_path);
+    if signature_file.exists() {
+        fs::remove_file(&signature_file).await?;
     }
+    if let Err(e) = fetch_signature(&client, &_url, &signature_file).await {
+        fs::remove_file(&dest_path).await?;
+        if signature_file.exists() {
+            fs::remove_file(&signature_file).await?;
+        }
+        return Err(e);
+    }
+
+    let what = format!("game {}", game_id);
+    let status = match signing::check_digest(&dest_path, &digest, &what).await {
+        Ok(status) => status,
+        Err(e) => {
+            fs::remove_file(&dest_path).await?;
+            if signature_file.exists() {
+                fs::remove_file(&signature_file).await?;
+            }
+            return Err(e);
+        }
+    };
 
-    Ok(format!("Game downloaded to: {}", dest_path.display()))
+    store::record_install(&InstallRecord {
+        game_id: game_id.clone(),
+        install_dir: game_dir,
+        artifact: dest_path.clone(),
+        installed_at: chrono::Utc::now(),
+    })?;
+
+    match status.warning() {
+        Some(warning) => Ok(format!(
+            "Game downloaded to: {} (warning: {})",
+            dest_path.display(),
+            warning
+        )),
+        None => Ok(format!("Game downloaded to: {}", dest_path.display())),
+    }
+}
+
+/// Fetch the detached signature of a build, if the server has one
+async fn fetch_signature(
+    client: &Client,
+    url: &str,
+    signature_file: &Path,
+) -> Result<(), OxideError> {
+    let signature = client.get(format!("{}.sig", url)).send().await?;
+    if signature.status().is_success() {
+        fs::write(signature_file, signature.bytes().await?).await?;
+    } else if signature.status() != StatusCode::NOT_FOUND {
+        return Err(OxideError::General(format!(
+            "Failed to fetch build signature: {}",
+            signature.status()
+        )));
+    }
+    Ok(())
 }
 
 /// Get the executable path for a given game ID
 pub async fn get_game_executable(game_id: &str) -> Result<PathBuf, OxideError> {
-    let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-        OxideError::FileOperationError("Failed to get home directory".to_string())
-    })?;
-    let game_dir = home_dir.join("Oxide").join("Games").join(game_id);
+    let game_dir = installed_game_dir(game_id)?;
 
     // This is a placeholder. In a real scenario, you'd have a manifest
     // file for each game indicating the main executable.
@@ -66,6 +125,19 @@ pub async fn get_game_executable(game_id: &str) -> Result<PathBuf, OxideError> {
     }
 }
 
+/// Directory a game is installed into
+pub(crate) fn game_install_dir(game_id: &str) -> Result<PathBuf, OxideError> {
+    Ok(crate::home_dir()?.join("Oxide").join("Games").join(game_id))
+}
+
+/// Directory a game was installed into, where it would be when it was not
+pub(crate) fn installed_game_dir(game_id: &str) -> Result<PathBuf, OxideError> {
+    match store::get_install(game_id)? {
+        Some(record) => Ok(record.install_dir),
+        None => game_install_dir(game_id),
+    }
+}
+
 /// Get download progress for a game (placeholder implementation)
 pub async fn get_download_progress(_game_id: String) -> Result<f64, OxideError> {
     // Placeholder for progress tracking
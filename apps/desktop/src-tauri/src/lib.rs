@@ -1,6 +1,51 @@
 use serde::{Serialize, Serializer};
+use std::path::PathBuf;
 use thiserror::Error;
 
+pub mod game_downloader;
+pub mod mod_engine;
+pub mod models;
+pub mod persist;
+pub mod search_index;
+pub mod signing;
+pub mod store;
+pub mod sync;
+
+pub const API_BASE_URL: &str = "https://api.oxide.games";
+
+/// The user's home directory
+pub fn home_dir() -> Result<PathBuf, OxideError> {
+    // Tests never touch the real state of the machine they run on
+    #[cfg(test)]
+    let home_dir = Some(test_support::home_dir().to_path_buf());
+    #[cfg(not(test))]
+    let home_dir = dirs::home_dir();
+    home_dir.ok_or_else(|| {
+        OxideError::FileOperationError("Could not determine home directory".to_string())
+    })
+}
+
+/// Directory holding Oxide's local state, `~/.Oxide`
+pub fn oxide_dir() -> Result<PathBuf, OxideError> {
+    Ok(home_dir()?.join(".Oxide"))
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::path::Path;
+    use std::sync::OnceLock;
+
+    /// Temporary home directory shared by every test in the process
+    ///
+    /// State such as the database is process wide, so tests share one home and
+    /// keep apart by using their own game ids.
+    pub(crate) fn home_dir() -> &'static Path {
+        static HOME: OnceLock<tempfile::TempDir> = OnceLock::new();
+        HOME.get_or_init(|| tempfile::tempdir().expect("create test home"))
+            .path()
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum OxideError {
     #[error("Initialization Error: {0}")]
@@ -11,6 +56,28 @@ pub enum OxideError {
     FileOperationError(String),
     #[error("Not found: {0}")]
     NotFound(String),
+    #[error("Mod installation failed: {0}")]
+    ModInstallationFailed(String),
+    #[error("Signature verification failed: {0}")]
+    Signature(String),
+    #[error("Search index error: {0}")]
+    SearchIndex(String),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    #[error("Sync backend error: {0}")]
+    Backend(String),
+    #[error("Search index error: {0}")]
+    Tantivy(#[from] tantivy::TantivyError),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Archive error: {0}")]
+    Archive(#[from] zip::result::ZipError),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
 }
 
 impl Serialize for OxideError {
@@ -3,6 +3,10 @@
 
 mod commands;
 use commands::games::*;
+use commands::mods::*;
+use commands::search::*;
+use commands::sync::*;
+use commands::trust::*;
 
 use std::fs;
 use std::path::Path;
@@ -134,6 +138,84 @@ fn main() {
             get_new_games,
             get_games_by_category,
             search_games,
+            suggest,
+            rebuild_search_index,
+            search_all,
+            get_search_settings,
+            set_search_locale,
+            set_search_synonyms,
+            // Search analytics commands
+            record_search_click,
+            get_search_report,
+            export_search_report,
+            get_search_analytics_settings,
+            set_search_analytics_enabled,
+            clear_search_log,
+            // Mod commands
+            install_mod,
+            uninstall_mod,
+            list_mods,
+            load_mod_config,
+            get_mod_conflicts,
+            get_conflict_rules,
+            set_mod_winner,
+            set_file_winner,
+            get_load_order,
+            sort_load_order,
+            set_load_order,
+            list_mod_profiles,
+            create_mod_profile,
+            delete_mod_profile,
+            switch_mod_profile,
+            set_mod_enabled,
+            export_mod_profile,
+            import_mod_profile,
+            deploy_mods,
+            purge_mods,
+            get_modified_files,
+            get_deployment_settings,
+            set_deployment_mode,
+            check_mod_updates,
+            upgrade_mod,
+            rollback_mod,
+            get_mod_repository,
+            set_mod_repository,
+            // Signature trust commands
+            get_trust_store,
+            trust_publisher_key,
+            revoke_publisher_key,
+            set_signature_policy,
+            // Sync commands
+            sync_library,
+            get_sync_status,
+            retry_pending_sync,
+            get_sync_schedule,
+            set_sync_schedule,
+            get_sync_conflicts,
+            resolve_sync_conflict,
+            get_sync_backend,
+            set_sync_backend,
+            get_sync_encryption,
+            enable_sync_encryption,
+            unlock_sync_encryption,
+            recover_sync_encryption,
+            change_sync_passphrase,
+            rotate_sync_key,
+            export_recovery_key,
+            // Cloud save commands
+            get_save_paths,
+            set_save_paths,
+            list_save_snapshots,
+            take_save_snapshot,
+            restore_save_snapshot,
+            sync_saves,
+            get_save_conflicts,
+            resolve_save_conflict,
+            begin_game_session,
+            end_game_session,
+            get_play_sessions,
+            get_save_sync_settings,
+            set_save_versions_kept,
             // File upload commands
             upload_file,
             get_upload_path,
@@ -182,8 +264,24 @@ fn main() {
                 }
             }
             
+            // Sync in the background, and retry syncs queued while offline
+            start_sync_scheduler(app.handle());
+
+            // Searches only read the index, it is brought up to date here
+            tauri::async_runtime::spawn(async {
+                if let Err(e) = index_all().await {
+                    eprintln!("Failed to update the search index: {}", e);
+                }
+            });
+            
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            // Push the last changes before quitting
+            if let tauri::RunEvent::Exit = event {
+                tauri::async_runtime::block_on(oxide_desktop::sync::scheduler::sync_on_exit());
+            }
+        });
 } 
\ No newline at end of file
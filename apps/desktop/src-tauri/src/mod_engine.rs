@@ -1,12 +1,32 @@
-use crate::OxideError;
+use crate::signing;
+use crate::{store, OxideError};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::fs as std_fs;
-use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub mod archive;
+pub mod conflicts;
+pub mod deploy;
+pub mod load_order;
+pub mod package;
+pub mod profiles;
+pub mod updates;
+
+use archive::ModArchive;
+use conflicts::ModFileManifest;
+
+/// Unpacked mods waiting to be moved into the store
+const STAGING_DIR: &str = ".staging";
+/// Private copy of the archive being installed, inside its staging directory
+const STAGED_ARCHIVE: &str = "archive";
+/// Files unpacked from the source, inside its staging directory
+const STAGED_FILES: &str = "files";
+/// Progress interval when an archive does not list its size up front
+const UNSIZED_REPORT_STEP: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModDescriptor {
     pub mod_id: String,
     pub game_id: String,
@@ -14,18 +34,52 @@ pub struct ModDescriptor {
     pub description: String,
     pub version: String,
     pub author: String,
+    /// Mods this one should load after when present, without requiring them
+    #[serde(default)]
+    pub load_after: Vec<String>,
+    /// Mods this one should load before when present
+    #[serde(default)]
+    pub load_before: Vec<String>,
+}
+
+/// Extraction progress of a mod archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallProgress {
+    pub game_id: String,
+    pub mod_id: String,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
 }
 
 /// Install a mod for a specific game
+///
+/// `mod_path` may be a zip, 7z or tar archive, or an unpacked mod folder. A
+/// detached signature next to it is checked against the trust store.
 pub async fn install_mod(game_id: String, mod_path: String) -> Result<String, OxideError> {
-    let home_dir =
-        home_dir().ok_or_else(|| OxideError::General("Could not find home directory".into()))?;
-    let mods_dir = home_dir.join(".Oxide").join("Mods").join(&game_id);
+    install_mod_with_progress(game_id, mod_path, |_| {}).await
+}
+
+/// Install a mod, reporting extraction progress through `on_progress`
+///
+/// Progress is reported after each percent of the uncompressed size and once
+/// extraction completes, so large archives do not flood the callback. Tarballs
+/// do not list their size up front, their totals stay zero and progress is
+/// reported every few megabytes instead.
+pub async fn install_mod_with_progress<F>(
+    game_id: String,
+    mod_path: String,
+    on_progress: F,
+) -> Result<String, OxideError>
+where
+    F: Fn(InstallProgress),
+{
+    let mods_dir = game_mods_dir(&game_id)?;
 
     // Create mods directory if it does not exist
     fs::create_dir_all(&mods_dir).await?;
 
-    // Extract mod (assuming it is a zip file)
     let mod_file = PathBuf::from(&mod_path);
     if !mod_file.exists() {
         return Err(OxideError::ModInstallationFailed(format!(
@@ -34,48 +88,114 @@ pub async fn install_mod(game_id: String, mod_path: String) -> Result<String, Ox
         )));
     }
 
-    // Read and extract the zip file
-    let file = std_fs::File::open(&mod_file)?;
-    let mut archive = zip::ZipArchive::new(file)?;
-
-    let mut mod_descriptor: Option<ModDescriptor> = None;
+    // The mod is checked and unpacked from a private copy next to the store, so
+    // the bytes verified are the bytes installed and moving it in is a rename
+    let staging = mods_dir
+        .join(STAGING_DIR)
+        .join(format!("{:016x}", rand::random::<u64>()));
+    let result = install_staged(&game_id, &mod_file, &staging, on_progress).await;
+    if staging.exists() {
+        if let Err(e) = fs::remove_dir_all(&staging).await {
+            eprintln!("Failed to remove {}: {}", staging.display(), e);
+        }
+    }
+    result
+}
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
+/// Unpack a mod into `staging`, check it and move it into the mod store
+async fn install_staged<F>(
+    game_id: &str,
+    mod_file: &Path,
+    staging: &Path,
+    on_progress: F,
+) -> Result<String, OxideError>
+where
+    F: Fn(InstallProgress),
+{
+    // Folders are copied file by file while unpacking, archives up front
+    let (source, archive_signature) = if mod_file.is_dir() {
+        (mod_file.to_path_buf(), None)
+    } else {
+        let copy = staging.join(STAGED_ARCHIVE);
+        let (from, to) = (mod_file.to_path_buf(), copy.clone());
+        let digest = blocking(move || archive::copy_hashed(&from, &to)).await?;
+        // Nothing is read from an archive before its signature is accepted
+        let what = format!("mod archive {}", mod_file.display());
+        let signature = signing::check_digest(mod_file, &digest, &what).await?;
+        (copy, Some(signature))
+    };
 
-        let outpath = match file.enclosed_name() {
-            Some(path) => mods_dir.join(path),
-            None => continue,
-        };
+    let listed = source.clone();
+    let (files_total, bytes_total) = blocking(move || archive::listed_size(&listed))
+        .await?
+        .unwrap_or_default();
+    let mut progress = InstallProgress {
+        game_id: game_id.to_string(),
+        mod_id: String::new(),
+        files_done: 0,
+        files_total,
+        bytes_done: 0,
+        bytes_total,
+    };
+    let report_step = match bytes_total {
+        0 => UNSIZED_REPORT_STEP,
+        total => (total / 100).max(1),
+    };
+    on_progress(progress.clone());
 
-        // Check if this is the mod descriptor
-        if file.name() == "mod.json" {
-            let mut contents = String::new();
-            file.read_to_string(&mut contents)?;
-            mod_descriptor = Some(serde_json::from_str(&contents)?);
-        }
-
-        if file.is_dir() {
-            std_fs::create_dir_all(&outpath)?;
-        } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    std_fs::create_dir_all(p)?;
-                }
+    // Unpacking runs off the async runtime, progress is handed back to report here
+    let (sender, mut updates) = tokio::sync::mpsc::unbounded_channel();
+    let files_dir = staging.join(STAGED_FILES);
+    let unpacking = blocking(move || {
+        let mut last_reported = 0;
+        let unpacked = archive::unpack(&source, &files_dir, |size| {
+            progress.files_done += 1;
+            progress.bytes_done += size;
+            if progress.bytes_done - last_reported >= report_step {
+                last_reported = progress.bytes_done;
+                let _ = sender.send(progress.clone());
             }
-            let mut outfile = std_fs::File::create(&outpath)?;
-            std::io::copy(&mut file, &mut outfile)?;
+        })?;
+        Ok((unpacked, progress))
+    });
+    let forwarding = async {
+        while let Some(update) = updates.recv().await {
+            on_progress(update);
         }
+    };
+    let (unpacked, ()) = tokio::join!(unpacking, forwarding);
+    let (unpacked, mut progress) = unpacked?;
+    let descriptor = unpacked.descriptor;
+    progress.mod_id = descriptor.mod_id.clone();
+    on_progress(progress);
+
+    let problems = package::validate_descriptor(&descriptor);
+    if !problems.is_empty() {
+        return Err(OxideError::ModInstallationFailed(format!(
+            "Invalid mod descriptor: {}",
+            problems.join("; ")
+        )));
+    }
+    if descriptor.game_id != game_id {
+        return Err(OxideError::ModInstallationFailed(format!(
+            "Mod {} is made for game {}, not {}",
+            descriptor.mod_id, descriptor.game_id, game_id
+        )));
     }
 
-    // Validate mod descriptor
-    let descriptor = mod_descriptor.ok_or_else(|| {
-        OxideError::ModInstallationFailed("Mod descriptor (mod.json) not found".to_string())
-    })?;
+    // A folder's listing digest is only known once every file was copied
+    let signature = match archive_signature {
+        Some(signature) => signature,
+        None => {
+            let digest = signing::listing_digest(&unpacked.source_files);
+            let what = format!("mod {}", descriptor.mod_id);
+            signing::check_digest(mod_file, &digest, &what).await?
+        }
+    };
 
     // Check dependencies
     for dep in &descriptor.dependencies {
-        if !is_mod_installed(&game_id, dep).await? {
+        if !is_mod_installed(game_id, dep).await? {
             return Err(OxideError::ModInstallationFailed(format!(
                 "Missing dependency: {}",
                 dep
@@ -83,96 +203,269 @@ pub async fn install_mod(game_id: String, mod_path: String) -> Result<String, Ox
         }
     }
 
-    // Save mod metadata
-    let metadata_path = mods_dir.join(format!("{}_metadata.json", descriptor.mod_id));
-    let metadata_content = serde_json::to_string_pretty(&descriptor)?;
-    fs::write(metadata_path, metadata_content).await?;
+    // Mods are unpacked into their own store directory and deployed from there
+    let store_dir = deploy::mod_store_dir(game_id, &descriptor.mod_id)?;
+    if store_dir.exists() {
+        fs::remove_dir_all(&store_dir).await?;
+    }
+    if let Some(parent) = store_dir.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::rename(&unpacked.root, &store_dir).await?;
+
+    let manifest = ModFileManifest {
+        mod_id: descriptor.mod_id.clone(),
+        files: unpacked.files,
+    };
+    conflicts::save_file_manifest(game_id, &manifest).await?;
+
+    store::save_mod(game_id, &descriptor)?;
+
+    let disabled_dependency = profiles::enable_installed_mod(game_id, &descriptor).await?;
+    load_order::refresh_load_order(game_id).await?;
+    deploy::redeploy_active_profile(game_id).await?;
 
-    Ok(format!(
+    let mut message = format!(
         "Mod {} installed successfully for game {}",
         descriptor.mod_id, game_id
-    ))
+    );
+    if let Some(dep) = disabled_dependency {
+        message.push_str(&format!(
+            " (not enabled: {} is disabled in the active profile)",
+            dep
+        ));
+    }
+    if let Some(warning) = signature.warning() {
+        message.push_str(&format!(" (warning: {})", warning));
+    }
+    Ok(message)
+}
+
+/// Run archive work, copying and unpacking, off the async runtime
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, OxideError> + Send + 'static,
+) -> Result<T, OxideError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| OxideError::General(format!("Mod archive task failed: {}", e)))?
 }
 
 /// Uninstall a mod
 pub async fn uninstall_mod(game_id: String, mod_id: String) -> Result<String, OxideError> {
-    let home_dir =
-        home_dir().ok_or_else(|| OxideError::General("Could not find home directory".into()))?;
-    let mod_dir = home_dir
-        .join(".Oxide")
-        .join("Mods")
-        .join(&game_id)
-        .join(&mod_id);
-    let metadata_path = home_dir
-        .join(".Oxide")
-        .join("Mods")
-        .join(&game_id)
-        .join(format!("{}_metadata.json", mod_id));
+    let mods_dir = game_mods_dir(&game_id)?;
+    let mod_dir = mods_dir.join(&mod_id);
+    let store_dir = deploy::mod_store_dir(&game_id, &mod_id)?;
+
+    profiles::forget_mod(&game_id, &mod_id).await?;
+    conflicts::remove_file_manifest(&game_id, &mod_id).await?;
+
+    if store_dir.exists() {
+        fs::remove_dir_all(&store_dir).await?;
+    }
 
     if mod_dir.exists() {
         fs::remove_dir_all(&mod_dir).await?;
     }
 
-    if metadata_path.exists() {
-        fs::remove_file(&metadata_path).await?;
-    }
+    store::remove_mod(&game_id, &mod_id)?;
+    load_order::refresh_load_order(&game_id).await?;
+    // Files the mod provided are no longer part of the desired deployment
+    deploy::redeploy_active_profile(&game_id).await?;
 
     Ok(format!("Mod {} uninstalled successfully", mod_id))
 }
 
-/// List all installed mods for a game
+/// List all installed mods for a game, in load order
 pub async fn list_mods(game_id: String) -> Result<Vec<ModDescriptor>, OxideError> {
-    let home_dir =
-        home_dir().ok_or_else(|| OxideError::General("Could not find home directory".into()))?;
-    let mods_dir = home_dir.join(".Oxide").join("Mods").join(&game_id);
-
-    if !mods_dir.exists() {
-        return Ok(Vec::new());
-    }
-
-    let mut mods = Vec::new();
-    let mut entries = fs::read_dir(&mods_dir).await?;
-
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("json") {
-            if let Some(file_name) = path.file_name().and_then(|s| s.to_str()) {
-                if file_name.ends_with("_metadata.json") {
-                    let contents = fs::read_to_string(&path).await?;
-                    let descriptor: ModDescriptor = serde_json::from_str(&contents)?;
-                    mods.push(descriptor);
-                }
-            }
-        }
-    }
+    let mut mods = read_installed_mods(&game_id).await?;
+    let order = load_order::current_load_order(&game_id).await?.order;
+
+    let position: HashMap<&str, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i))
+        .collect();
+    mods.sort_by_key(|m| {
+        position
+            .get(m.mod_id.as_str())
+            .copied()
+            .unwrap_or(usize::MAX)
+    });
 
     Ok(mods)
 }
 
+/// List the installed mods of every game, grouped by game
+pub async fn list_all_mods() -> Result<Vec<ModDescriptor>, OxideError> {
+    store::all_installed_mods()
+}
+
+/// Read the metadata of every installed mod, sorted by mod id
+pub(crate) async fn read_installed_mods(game_id: &str) -> Result<Vec<ModDescriptor>, OxideError> {
+    store::installed_mods(game_id)
+}
+
 /// Check if a mod is installed
 async fn is_mod_installed(game_id: &str, mod_id: &str) -> Result<bool, OxideError> {
-    let home_dir =
-        home_dir().ok_or_else(|| OxideError::General("Could not find home directory".into()))?;
-    let metadata_path = home_dir
-        .join(".Oxide")
-        .join("Mods")
-        .join(game_id)
-        .join(format!("{}_metadata.json", mod_id));
-
-    Ok(metadata_path.exists())
+    Ok(store::get_mod(game_id, mod_id)?.is_some())
 }
 
-/// Load mod configuration for a game
+/// Load mod configuration for a game, limited to the mods enabled in its active profile
 pub async fn load_mod_config(
     game_id: String,
 ) -> Result<HashMap<String, ModDescriptor>, OxideError> {
+    let enabled = profiles::active_enabled_mods(&game_id).await?;
     let mods = list_mods(game_id).await?;
     let mut config = HashMap::new();
 
     for mod_descriptor in mods {
-        config.insert(mod_descriptor.mod_id.clone(), mod_descriptor);
+        if enabled.contains(&mod_descriptor.mod_id) {
+            config.insert(mod_descriptor.mod_id.clone(), mod_descriptor);
+        }
     }
 
     Ok(config)
 }
 
+/// Root directory holding the mods of every game
+pub(crate) fn mods_root() -> Result<PathBuf, OxideError> {
+    Ok(crate::oxide_dir()?.join("Mods"))
+}
+
+/// Directory holding the installed mods of a game
+pub(crate) fn game_mods_dir(game_id: &str) -> Result<PathBuf, OxideError> {
+    Ok(mods_root()?.join(game_id))
+}
+
+/// Read `mod.json` from a mod archive or folder without extracting anything
+pub(crate) fn read_archive_descriptor(mod_file: &Path) -> Result<ModDescriptor, OxideError> {
+    Ok(ModArchive::open(mod_file)?.descriptor)
+}
+
+/// Hex encoded SHA-256 digest of a file's contents
+pub(crate) fn sha256_hex(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_mod(dir: &Path, game_id: &str) {
+        std::fs::create_dir_all(dir.join("wrapper/textures")).unwrap();
+        let descriptor = serde_json::json!({
+            "mod_id": "hd-textures",
+            "game_id": game_id,
+            "dependencies": [],
+            "description": "",
+            "version": "1.0.0",
+            "author": "tester",
+        });
+        std::fs::write(dir.join("wrapper/mod.json"), descriptor.to_string()).unwrap();
+        std::fs::write(dir.join("wrapper/textures/sky.dds"), "sky").unwrap();
+    }
+
+    #[tokio::test]
+    async fn installs_from_wrapped_folder_in_one_pass() {
+        let source = tempfile::tempdir().unwrap();
+        write_mod(source.path(), "install-wrapped");
+
+        install_mod(
+            "install-wrapped".to_string(),
+            source.path().to_string_lossy().to_string(),
+        )
+        .await
+        .unwrap();
+
+        let store_dir = deploy::mod_store_dir("install-wrapped", "hd-textures").unwrap();
+        assert!(store_dir.join("textures/sky.dds").is_file());
+        assert!(!store_dir.join("mod.json").exists());
+        assert!(!game_mods_dir("install-wrapped")
+            .unwrap()
+            .join(STAGING_DIR)
+            .read_dir()
+            .unwrap()
+            .any(|_| true));
+    }
+
+    #[tokio::test]
+    async fn rejects_mod_made_for_another_game() {
+        let source = tempfile::tempdir().unwrap();
+        write_mod(source.path(), "install-other-game");
+
+        let error = install_mod(
+            "install-target-game".to_string(),
+            source.path().to_string_lossy().to_string(),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("install-other-game"),
+            "{}",
+            error
+        );
+        assert!(store::get_mod("install-target-game", "hd-textures")
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn reports_progress_from_start_to_completion() {
+        let source = tempfile::tempdir().unwrap();
+        write_mod(source.path(), "install-progress");
+        // Large enough to need several reports, each after a percent of the total
+        let payload = vec![7u8; 256 * 1024];
+        for i in 0..8 {
+            std::fs::write(
+                source.path().join(format!("wrapper/textures/{}.dds", i)),
+                &payload,
+            )
+            .unwrap();
+        }
+
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = reports.clone();
+        install_mod_with_progress(
+            "install-progress".to_string(),
+            source.path().to_string_lossy().to_string(),
+            move |progress| collected.lock().unwrap().push(progress),
+        )
+        .await
+        .unwrap();
+
+        let reports = reports.lock().unwrap();
+        let first = reports.first().unwrap();
+        let last = reports.last().unwrap();
+        // mod.json, sky.dds and the eight textures
+        assert_eq!((first.files_done, first.files_total), (0, 10));
+        assert_eq!(first.bytes_total, last.bytes_total);
+        assert!(first.bytes_total > 8 * payload.len() as u64);
+        assert_eq!(last.mod_id, "hd-textures");
+        assert_eq!(last.files_done, last.files_total);
+        assert_eq!(last.bytes_done, last.bytes_total);
+
+        assert!(reports.len() > 2);
+        assert!(reports.len() <= 102, "{} reports", reports.len());
+        assert!(reports
+            .windows(2)
+            .all(|pair| pair[0].bytes_done <= pair[1].bytes_done));
+        assert!(reports.iter().all(|p| p.game_id == "install-progress"));
+    }
+
+    #[tokio::test]
+    async fn checks_archive_signature_before_unpacking() {
+        let dir = tempfile::tempdir().unwrap();
+        // Not an archive at all, unpacking it would fail with another error
+        let archive = dir.path().join("broken.zip");
+        std::fs::write(&archive, "not a zip").unwrap();
+        std::fs::write(signing::signature_path(&archive), "not a signature").unwrap();
+
+        let error = install_mod(
+            "install-bad-signature".to_string(),
+            archive.to_string_lossy().to_string(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, OxideError::Signature(_)), "{}", error);
+    }
+}
@@ -0,0 +1,466 @@
+//! Reading mods from archives and unpacked folders
+//!
+//! The format is detected from the first bytes of the file rather than its
+//! extension. Zip, 7z, plain tar and gzip, xz or bzip2 compressed tarballs are
+//! supported, as are directories. Archives that wrap the mod in a single
+//! top-level folder are installed from that folder.
+
+use super::conflicts::{normalize_path, ModFileEntry};
+use super::package::{is_junk, DESCRIPTOR_FILE};
+use super::ModDescriptor;
+use crate::OxideError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Directory macOS adds to zips it creates, never part of a mod
+const MACOS_METADATA_DIR: &str = "__MACOSX";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Zip,
+    SevenZip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarBz2,
+    /// An unpacked mod folder
+    Directory,
+}
+
+/// A regular file inside a mod source
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// Path relative to the mod root, `/` separated
+    pub path: String,
+    pub size: u64,
+}
+
+/// A mod archive or folder whose descriptor and layout have been read
+#[derive(Debug, Clone)]
+pub struct ModArchive {
+    pub format: ArchiveFormat,
+    pub descriptor: ModDescriptor,
+    /// Files of the mod relative to its root, `mod.json` excluded
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// A mod unpacked by `unpack`, not installed yet
+#[derive(Debug, Clone)]
+pub struct UnpackedMod {
+    pub format: ArchiveFormat,
+    pub descriptor: ModDescriptor,
+    /// Directory holding the mod's files, `mod.json` removed
+    pub root: PathBuf,
+    /// Files of the mod relative to `root`
+    pub files: Vec<ModFileEntry>,
+    /// Every file unpacked by its path in the source, `mod.json` included
+    pub source_files: Vec<ModFileEntry>,
+}
+
+impl ModArchive {
+    /// Detect the format of `path`, locate `mod.json` and list the mod's files
+    pub fn open(path: &Path) -> Result<Self, OxideError> {
+        let format = detect_format(path)?;
+
+        let mut files = Vec::new();
+        let mut descriptors = HashMap::new();
+        for_each_file(path, format, |name, size, reader| {
+            if name.rsplit('/').next() == Some(DESCRIPTOR_FILE) {
+                let mut contents = String::new();
+                reader.read_to_string(&mut contents)?;
+                descriptors.insert(name.to_string(), contents);
+            }
+            files.push(ArchiveEntry {
+                path: name.to_string(),
+                size,
+            });
+            Ok(())
+        })?;
+        files.retain(|entry| !is_ignored(&entry.path));
+
+        let paths: Vec<&str> = files.iter().map(|entry| entry.path.as_str()).collect();
+        let (root, descriptor) = find_descriptor(&paths, &descriptors)?;
+
+        let entries = files
+            .into_iter()
+            .filter_map(|entry| {
+                let path = entry.path.strip_prefix(root.as_str())?.to_string();
+                (path != DESCRIPTOR_FILE).then_some(ArchiveEntry {
+                    path,
+                    size: entry.size,
+                })
+            })
+            .collect();
+
+        Ok(ModArchive {
+            format,
+            descriptor,
+            entries,
+        })
+    }
+
+    /// Total uncompressed size of the mod's files
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+}
+
+/// Unpack a mod source below `staging` in a single pass
+///
+/// Every file is written under its path in the source and hashed on the way.
+/// `mod.json` is picked up as it streams by, so archives are decompressed only
+/// once. `on_file` receives the size of each file written.
+pub fn unpack<F>(source: &Path, staging: &Path, mut on_file: F) -> Result<UnpackedMod, OxideError>
+where
+    F: FnMut(u64),
+{
+    let format = detect_format(source)?;
+
+    let mut files = Vec::new();
+    let mut descriptors = HashMap::new();
+    for_each_file(source, format, |name, _, reader| {
+        if is_ignored(name) {
+            return Ok(());
+        }
+        let destination = staging.join(name);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = HashingWriter {
+            inner: File::create(&destination)?,
+            hasher: Sha256::new(),
+        };
+        let size = io::copy(reader, &mut writer)?;
+        writer.flush()?;
+
+        if name.rsplit('/').next() == Some(DESCRIPTOR_FILE) {
+            descriptors.insert(name.to_string(), fs::read_to_string(&destination)?);
+        }
+        files.push(ModFileEntry {
+            path: name.to_string(),
+            sha256: format!("{:x}", writer.hasher.finalize()),
+            size,
+        });
+        on_file(size);
+        Ok(())
+    })?;
+
+    let paths: Vec<&str> = files.iter().map(|entry| entry.path.as_str()).collect();
+    let (root, descriptor) = find_descriptor(&paths, &descriptors)?;
+
+    // The descriptor is stored as metadata, not as a deployed file
+    let root_dir = staging.join(&root);
+    fs::remove_file(root_dir.join(DESCRIPTOR_FILE))?;
+    let mod_files = files
+        .iter()
+        .filter_map(|entry| {
+            let path = entry.path.strip_prefix(root.as_str())?.to_string();
+            (path != DESCRIPTOR_FILE).then(|| ModFileEntry {
+                path,
+                ..entry.clone()
+            })
+        })
+        .collect();
+
+    Ok(UnpackedMod {
+        format,
+        descriptor,
+        root: root_dir,
+        files: mod_files,
+        source_files: files,
+    })
+}
+
+/// Number and total size of the files a mod source lists up front, `mod.json` included
+///
+/// Tarballs only reveal their entries while being read through, `None` then.
+pub fn listed_size(source: &Path) -> Result<Option<(usize, u64)>, OxideError> {
+    let format = detect_format(source)?;
+    match format {
+        ArchiveFormat::Directory | ArchiveFormat::Zip => {
+            // Sizes come from directory entries, nothing is read or decompressed
+            let mut listed = (0, 0);
+            for_each_file(source, format, |name, size, _| {
+                if !is_ignored(name) {
+                    listed.0 += 1;
+                    listed.1 += size;
+                }
+                Ok(())
+            })?;
+            Ok(Some(listed))
+        }
+        ArchiveFormat::SevenZip => {
+            let archive = sevenz_rust::Archive::open(source).map_err(|e| {
+                OxideError::ModInstallationFailed(format!("Invalid 7z archive: {}", e))
+            })?;
+            let files = archive.files.iter().filter(|entry| {
+                !entry.is_directory()
+                    && sanitize_entry_path(entry.name()).is_some_and(|name| !is_ignored(&name))
+            });
+            Ok(Some(files.fold((0, 0), |(count, size), entry| {
+                (count + 1, size + entry.size())
+            })))
+        }
+        ArchiveFormat::Tar
+        | ArchiveFormat::TarGz
+        | ArchiveFormat::TarXz
+        | ArchiveFormat::TarBz2 => Ok(None),
+    }
+}
+
+/// Copy a file, returning the SHA-256 digest of the bytes copied
+pub fn copy_hashed(source: &Path, destination: &Path) -> Result<Vec<u8>, OxideError> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = HashingWriter {
+        inner: File::create(destination)?,
+        hasher: Sha256::new(),
+    };
+    io::copy(&mut File::open(source)?, &mut writer)?;
+    writer.inner.sync_all()?;
+    Ok(writer.hasher.finalize().to_vec())
+}
+
+/// Hex encoded SHA-256 digest of a file, read in chunks rather than loaded whole
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut writer = HashingWriter {
+        inner: io::sink(),
+        hasher: Sha256::new(),
+    };
+    io::copy(&mut File::open(path)?, &mut writer)?;
+    Ok(format!("{:x}", writer.hasher.finalize()))
+}
+
+/// Writer hashing everything written through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Identify a mod source by its magic bytes
+pub fn detect_format(path: &Path) -> Result<ArchiveFormat, OxideError> {
+    if path.is_dir() {
+        return Ok(ArchiveFormat::Directory);
+    }
+
+    let mut header = Vec::with_capacity(512);
+    File::open(path)?.take(512).read_to_end(&mut header)?;
+
+    let format = if header.starts_with(b"PK\x03\x04")
+        || header.starts_with(b"PK\x05\x06")
+        || header.starts_with(b"PK\x07\x08")
+    {
+        ArchiveFormat::Zip
+    } else if header.starts_with(b"7z\xBC\xAF\x27\x1C") {
+        ArchiveFormat::SevenZip
+    } else if header.starts_with(b"\x1F\x8B") {
+        ArchiveFormat::TarGz
+    } else if header.starts_with(b"\xFD7zXZ\x00") {
+        ArchiveFormat::TarXz
+    } else if header.starts_with(b"BZh") {
+        ArchiveFormat::TarBz2
+    } else if header.get(257..262) == Some(b"ustar") {
+        ArchiveFormat::Tar
+    } else if header.starts_with(b"Rar!\x1A\x07") {
+        return Err(OxideError::ModInstallationFailed(
+            "RAR archives are not supported, repack the mod as zip or 7z".to_string(),
+        ));
+    } else {
+        return Err(OxideError::ModInstallationFailed(format!(
+            "{} is not a supported mod archive",
+            path.display()
+        )));
+    };
+    Ok(format)
+}
+
+/// Call `visit` with the sanitized path, size and contents of every regular file
+fn for_each_file<F>(path: &Path, format: ArchiveFormat, mut visit: F) -> Result<(), OxideError>
+where
+    F: FnMut(&str, u64, &mut dyn Read) -> Result<(), OxideError>,
+{
+    match format {
+        ArchiveFormat::Directory => walk_directory(path, path, &mut visit),
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            for i in 0..archive.len() {
+                let mut file = archive.by_index(i)?;
+                if file.is_dir() {
+                    continue;
+                }
+                let name = match file.enclosed_name() {
+                    Some(name) => normalize_path(&name),
+                    None => continue,
+                };
+                let size = file.size();
+                visit(&name, size, &mut file)?;
+            }
+            Ok(())
+        }
+        ArchiveFormat::SevenZip => {
+            let invalid = |e: sevenz_rust::Error| {
+                OxideError::ModInstallationFailed(format!("Invalid 7z archive: {}", e))
+            };
+            let mut archive = sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty())
+                .map_err(invalid)?;
+
+            // The callback can only return the crate's error type, keep ours aside
+            let mut failure = None;
+            let result = archive.for_each_entries(|entry, reader| {
+                if failure.is_some() {
+                    return Ok(false);
+                }
+                if entry.is_directory() {
+                    return Ok(true);
+                }
+                if let Some(name) = sanitize_entry_path(entry.name()) {
+                    if let Err(e) = visit(&name, entry.size(), reader) {
+                        failure = Some(e);
+                        return Ok(false);
+                    }
+                }
+                // Entries of a solid block share one stream, skip what was not read
+                io::copy(reader, &mut io::sink())?;
+                Ok(true)
+            });
+            match failure {
+                Some(e) => Err(e),
+                None => result.map_err(invalid),
+            }
+        }
+        ArchiveFormat::Tar => walk_tar(File::open(path)?, &mut visit),
+        ArchiveFormat::TarGz => walk_tar(
+            flate2::read::MultiGzDecoder::new(BufReader::new(File::open(path)?)),
+            &mut visit,
+        ),
+        ArchiveFormat::TarXz => walk_tar(
+            xz2::read::XzDecoder::new_multi_decoder(BufReader::new(File::open(path)?)),
+            &mut visit,
+        ),
+        ArchiveFormat::TarBz2 => walk_tar(
+            bzip2::read::MultiBzDecoder::new(BufReader::new(File::open(path)?)),
+            &mut visit,
+        ),
+    }
+}
+
+fn walk_tar<R, F>(reader: R, visit: &mut F) -> Result<(), OxideError>
+where
+    R: Read,
+    F: FnMut(&str, u64, &mut dyn Read) -> Result<(), OxideError>,
+{
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = match sanitize_entry_path(&entry.path()?.to_string_lossy()) {
+            Some(name) => name,
+            None => continue,
+        };
+        let size = entry.size();
+        visit(&name, size, &mut entry)?;
+    }
+    Ok(())
+}
+
+fn walk_directory<F>(root: &Path, dir: &Path, visit: &mut F) -> Result<(), OxideError>
+where
+    F: FnMut(&str, u64, &mut dyn Read) -> Result<(), OxideError>,
+{
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        // Links could point anywhere on disk, only real files are installed
+        if file_type.is_dir() {
+            walk_directory(root, &path, visit)?;
+        } else if file_type.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let mut file = File::open(&path)?;
+            let size = file.metadata()?.len();
+            visit(&normalize_path(relative), size, &mut file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Normalize an entry name, rejecting absolute paths and `..` components
+fn sanitize_entry_path(name: &str) -> Option<String> {
+    let name = name.replace('\\', "/");
+    let mut parts = Vec::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Locate and parse the `mod.json` of a mod, returning the prefix of its folder
+fn find_descriptor(
+    paths: &[&str],
+    descriptors: &HashMap<String, String>,
+) -> Result<(String, ModDescriptor), OxideError> {
+    let root = find_root(paths, descriptors).ok_or_else(|| {
+        OxideError::ModInstallationFailed("Mod descriptor (mod.json) not found".to_string())
+    })?;
+    let descriptor = serde_json::from_str(&descriptors[&format!("{}{}", root, DESCRIPTOR_FILE)])?;
+    Ok((root, descriptor))
+}
+
+/// Find the folder holding `mod.json`
+///
+/// The archive root wins. Otherwise single top-level folders are descended
+/// for as long as every file lives inside one.
+fn find_root(paths: &[&str], descriptors: &HashMap<String, String>) -> Option<String> {
+    let mut root = String::new();
+    loop {
+        if descriptors.contains_key(&format!("{}{}", root, DESCRIPTOR_FILE)) {
+            return Some(root);
+        }
+
+        let mut top_level = BTreeSet::new();
+        for path in paths {
+            let relative = path.strip_prefix(root.as_str())?;
+            match relative.split_once('/') {
+                Some((dir, _)) => top_level.insert(dir),
+                // A file next to the folders means there is no single wrapper
+                None => return None,
+            };
+        }
+
+        let mut dirs = top_level.into_iter();
+        match (dirs.next(), dirs.next()) {
+            (Some(dir), None) => root = format!("{}{}/", root, dir),
+            _ => return None,
+        }
+    }
+}
+
+fn is_ignored(path: &str) -> bool {
+    path.split('/').next() == Some(MACOS_METADATA_DIR) || is_junk(path)
+}
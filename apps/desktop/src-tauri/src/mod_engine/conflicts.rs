@@ -0,0 +1,482 @@
+use super::{deploy, game_mods_dir, is_mod_installed, load_order, profiles};
+use crate::{persist, OxideError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+const RULES_FILE: &str = "conflict_rules.json";
+const MANIFEST_SUFFIX: &str = "_files.json";
+
+/// A single file written by a mod, relative to the game's mods directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModFileEntry {
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// Every file a mod owns, recorded at install time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModFileManifest {
+    pub mod_id: String,
+    pub files: Vec<ModFileEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// All mods ship byte-identical content for the path
+    Identical,
+    /// At least two mods ship different content for the path
+    Differing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    FileRule,
+    ModRule,
+    /// No rule applies, the mod loading last wins
+    LoadOrder,
+    Unresolved,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictOwner {
+    pub mod_id: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// A path claimed by more than one installed mod
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModConflict {
+    pub path: String,
+    pub kind: ConflictKind,
+    pub owners: Vec<ConflictOwner>,
+    pub winner: Option<String>,
+    pub resolution: ConflictResolution,
+}
+
+/// "winner always overrides loser" for every file the two mods share
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModWinnerRule {
+    pub winner: String,
+    pub loser: String,
+}
+
+/// User defined winner rules for a game, persisted next to the installed mods
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConflictRules {
+    #[serde(default)]
+    pub mod_rules: Vec<ModWinnerRule>,
+    /// Per-file overrides, keyed by normalized path
+    #[serde(default)]
+    pub file_rules: BTreeMap<String, String>,
+}
+
+/// Report every file owned by more than one installed mod of a game
+///
+/// Mods disabled in the active profile are listed as owners but never win, so
+/// the reported winner is the file deployed into the game directory.
+pub async fn get_mod_conflicts(game_id: String) -> Result<Vec<ModConflict>, OxideError> {
+    let manifests = load_file_manifests(&game_id).await?;
+    let rules = load_conflict_rules(&game_id).await?;
+    let enabled = profiles::active_enabled_mods(&game_id).await?;
+    let order = load_order::current_load_order(&game_id).await?.order;
+
+    let mut by_path: BTreeMap<String, Vec<ConflictOwner>> = BTreeMap::new();
+    for manifest in manifests {
+        for entry in manifest.files {
+            by_path.entry(entry.path).or_default().push(ConflictOwner {
+                mod_id: manifest.mod_id.clone(),
+                sha256: entry.sha256,
+                size: entry.size,
+            });
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for (path, owners) in by_path {
+        if owners.len() < 2 {
+            continue;
+        }
+
+        let kind = if owners.iter().all(|o| o.sha256 == owners[0].sha256) {
+            ConflictKind::Identical
+        } else {
+            ConflictKind::Differing
+        };
+
+        let contenders: Vec<&str> = owners
+            .iter()
+            .map(|o| o.mod_id.as_str())
+            .filter(|mod_id| enabled.contains(*mod_id))
+            .collect();
+        let (winner, resolution) = match resolve_winner(&rules, &order, &path, &contenders) {
+            Some((winner, resolution)) => (Some(winner), resolution),
+            None => (None, ConflictResolution::Unresolved),
+        };
+
+        conflicts.push(ModConflict {
+            path,
+            kind,
+            owners,
+            winner,
+            resolution,
+        });
+    }
+
+    Ok(conflicts)
+}
+
+/// Get the winner rules configured for a game
+pub async fn get_conflict_rules(game_id: String) -> Result<ConflictRules, OxideError> {
+    load_conflict_rules(&game_id).await
+}
+
+/// Make `winner` override `loser` for every file both mods provide and redeploy
+pub async fn set_mod_winner(
+    game_id: String,
+    winner: String,
+    loser: String,
+) -> Result<ConflictRules, OxideError> {
+    if winner == loser {
+        return Err(OxideError::General(
+            "A mod cannot win a conflict against itself".to_string(),
+        ));
+    }
+    ensure_installed(&game_id, &winner).await?;
+    ensure_installed(&game_id, &loser).await?;
+
+    let mut rules = load_conflict_rules(&game_id).await?;
+    // A newer rule replaces any rule between the same pair, in either direction
+    rules.mod_rules.retain(|rule| {
+        !(rule.winner == winner && rule.loser == loser
+            || rule.winner == loser && rule.loser == winner)
+    });
+    rules.mod_rules.push(ModWinnerRule { winner, loser });
+
+    save_conflict_rules(&game_id, &rules).await?;
+    deploy::redeploy_active_profile(&game_id).await?;
+    Ok(rules)
+}
+
+/// Pick the mod that provides a single file, or clear the override with `None`, and redeploy
+pub async fn set_file_winner(
+    game_id: String,
+    path: String,
+    winner: Option<String>,
+) -> Result<ConflictRules, OxideError> {
+    let mut rules = load_conflict_rules(&game_id).await?;
+    let path = normalize_path(Path::new(&path));
+
+    match winner {
+        Some(winner) => {
+            ensure_installed(&game_id, &winner).await?;
+            rules.file_rules.insert(path, winner);
+        }
+        None => {
+            rules.file_rules.remove(&path);
+        }
+    }
+
+    save_conflict_rules(&game_id, &rules).await?;
+    deploy::redeploy_active_profile(&game_id).await?;
+    Ok(rules)
+}
+
+/// Decide which of `contenders` provides `path`
+///
+/// File rules take precedence over mod rules; without an applicable rule the
+/// contender latest in `load_order` wins, with mods not yet ordered loading last.
+pub(crate) fn resolve_winner(
+    rules: &ConflictRules,
+    load_order: &[String],
+    path: &str,
+    contenders: &[&str],
+) -> Option<(String, ConflictResolution)> {
+    if let Some(winner) = rules.file_rules.get(path) {
+        if contenders.contains(&winner.as_str()) {
+            return Some((winner.clone(), ConflictResolution::FileRule));
+        }
+    }
+
+    let beats = |winner: &str, loser: &str| {
+        rules
+            .mod_rules
+            .iter()
+            .any(|rule| rule.winner == winner && rule.loser == loser)
+    };
+
+    let by_rule = contenders.iter().find(|candidate| {
+        contenders
+            .iter()
+            .filter(|other| other != candidate)
+            .all(|other| beats(candidate, other))
+    });
+    if let Some(winner) = by_rule {
+        return Some((winner.to_string(), ConflictResolution::ModRule));
+    }
+
+    contenders
+        .iter()
+        .max_by_key(|candidate| {
+            load_order
+                .iter()
+                .position(|id| id == *candidate)
+                .unwrap_or(usize::MAX)
+        })
+        .map(|winner| (winner.to_string(), ConflictResolution::LoadOrder))
+}
+
+/// Fail unless `mod_id` is installed, so rules never name unknown mods
+async fn ensure_installed(game_id: &str, mod_id: &str) -> Result<(), OxideError> {
+    if !is_mod_installed(game_id, mod_id).await? {
+        return Err(OxideError::NotFound(format!(
+            "Mod {} is not installed",
+            mod_id
+        )));
+    }
+    Ok(())
+}
+
+/// Normalize an archive path so the same file compares equal across mods
+pub(crate) fn normalize_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) async fn load_file_manifests(game_id: &str) -> Result<Vec<ModFileManifest>, OxideError> {
+    let mods_dir = game_mods_dir(game_id)?;
+    if !mods_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut manifests = Vec::new();
+    let mut entries = fs::read_dir(&mods_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_manifest = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|name| name.ends_with(MANIFEST_SUFFIX));
+        if path.is_file() && is_manifest {
+            manifests.extend(persist::read_json::<ModFileManifest>(&path).await?);
+        }
+    }
+
+    // Directory order is unspecified, keep reports stable
+    manifests.sort_by(|a: &ModFileManifest, b| a.mod_id.cmp(&b.mod_id));
+    Ok(manifests)
+}
+
+/// File recording which files an installed mod owns
+pub(crate) fn manifest_path(game_id: &str, mod_id: &str) -> Result<PathBuf, OxideError> {
+    Ok(game_mods_dir(game_id)?.join(format!("{}{}", mod_id, MANIFEST_SUFFIX)))
+}
+
+pub(crate) async fn save_file_manifest(
+    game_id: &str,
+    manifest: &ModFileManifest,
+) -> Result<(), OxideError> {
+    persist::write_json(&manifest_path(game_id, &manifest.mod_id)?, manifest).await
+}
+
+pub(crate) async fn remove_file_manifest(game_id: &str, mod_id: &str) -> Result<(), OxideError> {
+    persist::remove_json(&manifest_path(game_id, mod_id)?).await
+}
+
+pub(crate) async fn load_conflict_rules(game_id: &str) -> Result<ConflictRules, OxideError> {
+    let path = game_mods_dir(game_id)?.join(RULES_FILE);
+    Ok(persist::read_json(&path).await?.unwrap_or_default())
+}
+
+async fn save_conflict_rules(game_id: &str, rules: &ConflictRules) -> Result<(), OxideError> {
+    persist::write_json(&game_mods_dir(game_id)?.join(RULES_FILE), rules).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mod_engine::{install_mod, package};
+
+    /// Install a mod shipping `files` from a temporary folder
+    async fn install(game_id: &str, mod_id: &str, files: &[(&str, &str)]) {
+        let source = tempfile::tempdir().unwrap();
+        let descriptor = serde_json::json!({
+            "mod_id": mod_id,
+            "game_id": game_id,
+            "dependencies": [],
+            "description": "",
+            "version": "1.0.0",
+            "author": "tester",
+        });
+        std::fs::write(
+            source.path().join(package::DESCRIPTOR_FILE),
+            descriptor.to_string(),
+        )
+        .unwrap();
+        for (path, contents) in files {
+            let file = source.path().join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, contents).unwrap();
+        }
+        install_mod(
+            game_id.to_string(),
+            source.path().to_string_lossy().to_string(),
+        )
+        .await
+        .unwrap();
+    }
+
+    fn conflict<'a>(conflicts: &'a [ModConflict], path: &str) -> &'a ModConflict {
+        conflicts.iter().find(|c| c.path == path).unwrap()
+    }
+
+    #[test]
+    fn file_rule_beats_mod_rule_beats_load_order() {
+        let order = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let mut rules = ConflictRules::default();
+        let contenders = ["a", "b", "c"];
+
+        assert_eq!(
+            resolve_winner(&rules, &order, "x.dds", &contenders),
+            Some(("c".to_string(), ConflictResolution::LoadOrder))
+        );
+
+        rules.mod_rules = vec![
+            ModWinnerRule {
+                winner: "a".to_string(),
+                loser: "b".to_string(),
+            },
+            ModWinnerRule {
+                winner: "a".to_string(),
+                loser: "c".to_string(),
+            },
+        ];
+        assert_eq!(
+            resolve_winner(&rules, &order, "x.dds", &contenders),
+            Some(("a".to_string(), ConflictResolution::ModRule))
+        );
+
+        rules
+            .file_rules
+            .insert("x.dds".to_string(), "b".to_string());
+        assert_eq!(
+            resolve_winner(&rules, &order, "x.dds", &contenders),
+            Some(("b".to_string(), ConflictResolution::FileRule))
+        );
+        // A rule naming a mod that does not provide the file is ignored
+        assert_eq!(
+            resolve_winner(&rules, &order, "x.dds", &["a", "c"]),
+            Some(("a".to_string(), ConflictResolution::ModRule))
+        );
+        assert_eq!(resolve_winner(&rules, &order, "x.dds", &[]), None);
+    }
+
+    #[test]
+    fn unordered_mods_load_last() {
+        let order = vec!["a".to_string()];
+        assert_eq!(
+            resolve_winner(&ConflictRules::default(), &order, "x.dds", &["new", "a"]),
+            Some(("new".to_string(), ConflictResolution::LoadOrder))
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_conflicts_and_file_winners_override_mod_winners() {
+        let game_id = "conflicts-report";
+        install(
+            game_id,
+            "base",
+            &[
+                ("textures/sky.dds", "base sky"),
+                ("readme.txt", "same"),
+                ("base.esp", ""),
+            ],
+        )
+        .await;
+        install(
+            game_id,
+            "patch",
+            &[("textures/sky.dds", "patched sky"), ("readme.txt", "same")],
+        )
+        .await;
+
+        let conflicts = get_mod_conflicts(game_id.to_string()).await.unwrap();
+        assert_eq!(conflicts.len(), 2);
+        let sky = conflict(&conflicts, "textures/sky.dds");
+        assert_eq!(sky.kind, ConflictKind::Differing);
+        assert_eq!(sky.owners.len(), 2);
+        assert_eq!(sky.resolution, ConflictResolution::LoadOrder);
+        assert_eq!(
+            conflict(&conflicts, "readme.txt").kind,
+            ConflictKind::Identical
+        );
+
+        let order = load_order::current_load_order(game_id).await.unwrap().order;
+        let (first, last) = (order[0].clone(), order[1].clone());
+        assert_eq!(sky.winner.as_deref(), Some(last.as_str()));
+
+        set_mod_winner(game_id.to_string(), first.clone(), last.clone())
+            .await
+            .unwrap();
+        set_file_winner(
+            game_id.to_string(),
+            "textures/sky.dds".to_string(),
+            Some(last.clone()),
+        )
+        .await
+        .unwrap();
+
+        let conflicts = get_mod_conflicts(game_id.to_string()).await.unwrap();
+        let sky = conflict(&conflicts, "textures/sky.dds");
+        assert_eq!(sky.winner.as_deref(), Some(last.as_str()));
+        assert_eq!(sky.resolution, ConflictResolution::FileRule);
+        let readme = conflict(&conflicts, "readme.txt");
+        assert_eq!(readme.winner.as_deref(), Some(first.as_str()));
+        assert_eq!(readme.resolution, ConflictResolution::ModRule);
+
+        // Clearing the file rule hands the file back to the mod rule
+        set_file_winner(game_id.to_string(), "textures/sky.dds".to_string(), None)
+            .await
+            .unwrap();
+        let conflicts = get_mod_conflicts(game_id.to_string()).await.unwrap();
+        let sky = conflict(&conflicts, "textures/sky.dds");
+        assert_eq!(sky.winner.as_deref(), Some(first.as_str()));
+        assert_eq!(sky.resolution, ConflictResolution::ModRule);
+    }
+
+    #[tokio::test]
+    async fn winner_rules_require_installed_mods() {
+        let game_id = "conflicts-unknown-winner";
+        install(game_id, "base", &[("base.esp", "")]).await;
+
+        let error = set_file_winner(
+            game_id.to_string(),
+            "base.esp".to_string(),
+            Some("missing".to_string()),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, OxideError::NotFound(_)), "{}", error);
+        assert!(matches!(
+            set_mod_winner(
+                game_id.to_string(),
+                "missing".to_string(),
+                "base".to_string()
+            )
+            .await,
+            Err(OxideError::NotFound(_))
+        ));
+        assert!(load_conflict_rules(game_id)
+            .await
+            .unwrap()
+            .file_rules
+            .is_empty());
+    }
+}
@@ -0,0 +1,821 @@
+//! Non-destructive mod deployment
+//!
+//! Installed mods live in a per-mod store under the game's mods directory and are
+//! materialized into the game directory as a link farm. Every deployed file is
+//! recorded in a deployment manifest, game files that get shadowed are backed up,
+//! and purging restores the game to its pristine state. The load order of the
+//! deployed mods is written next to them, where the game reads it.
+
+use super::{archive, conflicts, game_mods_dir, load_order, profiles};
+use crate::game_downloader::installed_game_dir;
+use crate::{persist, OxideError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+const STORE_DIR: &str = ".store";
+/// Original game files replaced by a deployed mod file
+const VANILLA_DIR: &str = ".vanilla";
+/// Deployed files the game changed in place, kept when they are undeployed
+const OVERWRITE_DIR: &str = ".overwrite";
+const DEPLOYMENT_FILE: &str = "deployment.json";
+const SETTINGS_FILE: &str = "deploy_settings.json";
+/// Extensions of files games commonly rewrite in place, e.g. settings and saves
+const REWRITTEN_EXTENSIONS: &[&str] = &[
+    "cfg", "conf", "ini", "json", "log", "sav", "save", "toml", "txt", "xml", "yaml", "yml",
+];
+
+/// How mod files are materialized in the game directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentMode {
+    /// Hard links, falling back to symlinks and then copies
+    #[default]
+    Hardlink,
+    /// Symbolic links, falling back to copies
+    Symlink,
+    /// Plain copies
+    Copy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentSettings {
+    pub mode: DeploymentMode,
+    /// Files with these extensions are always copied, never linked, so a game
+    /// rewriting them cannot change the mod store through the link
+    #[serde(default = "default_copied_extensions")]
+    pub copied_extensions: Vec<String>,
+}
+
+impl Default for DeploymentSettings {
+    fn default() -> Self {
+        Self {
+            mode: DeploymentMode::default(),
+            copied_extensions: default_copied_extensions(),
+        }
+    }
+}
+
+fn default_copied_extensions() -> Vec<String> {
+    REWRITTEN_EXTENSIONS
+        .iter()
+        .map(|ext| ext.to_string())
+        .collect()
+}
+
+/// A file placed in the game directory by a mod
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployedFile {
+    pub mod_id: String,
+    /// Method actually used, which may differ from the mode after a fallback
+    pub method: DeploymentMode,
+    pub sha256: String,
+    /// Whether a game file was moved aside to make room for this one
+    pub vanilla_backup: bool,
+}
+
+/// Manifest of every file deployed into a game directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeploymentManifest {
+    pub target_dir: PathBuf,
+    pub files: BTreeMap<String, DeployedFile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeploySummary {
+    pub deployed: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Deployed files the game modified, moved to the overwrite directory
+    pub preserved: Vec<String>,
+    /// Files not deployed because their mod store copy changed, e.g. edited
+    /// through a link; reinstalling the mod deploys them again
+    #[serde(default)]
+    pub store_modified: Vec<String>,
+    /// Files not deployed because they are missing from the mod store;
+    /// reinstalling the mod deploys them again
+    #[serde(default)]
+    pub store_missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PurgeReport {
+    pub removed: usize,
+    pub restored: usize,
+    pub preserved: Vec<String>,
+}
+
+/// A deployed file whose content no longer matches what the mod shipped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModifiedFile {
+    pub path: String,
+    pub mod_id: String,
+    pub expected_sha256: String,
+    /// `None` when the file was deleted
+    pub actual_sha256: Option<String>,
+    /// Whether the change reached the mod store through a link, the mod then
+    /// needs reinstalling before the file can be deployed again
+    #[serde(default)]
+    pub store_modified: bool,
+}
+
+/// Deploy the active profile into the game directory
+pub async fn deploy_mods(game_id: String) -> Result<DeploySummary, OxideError> {
+    deploy_active_profile(&game_id).await
+}
+
+/// Remove every deployed mod file and restore the original game files
+pub async fn purge_mods(game_id: String) -> Result<PurgeReport, OxideError> {
+    let mods_dir = game_mods_dir(&game_id)?;
+    let manifest = load_manifest(&game_id).await?;
+    let mut report = PurgeReport::default();
+
+    for (path, file) in &manifest.files {
+        let deployed_path = manifest.target_dir.join(path);
+        if undeploy_file(&mods_dir, &deployed_path, path, file).await? {
+            report.preserved.push(path.clone());
+        }
+        report.removed += 1;
+        if file.vanilla_backup {
+            report.restored += 1;
+        }
+    }
+
+    // Without a manifest nothing was deployed, and there is no directory to clean
+    if !manifest.target_dir.as_os_str().is_empty() {
+        remove_load_order_export(&mods_dir, &manifest.target_dir).await?;
+    }
+    remove_manifest(&game_id).await?;
+    Ok(report)
+}
+
+/// List deployed files that the game or the user changed in place
+pub async fn get_modified_files(game_id: String) -> Result<Vec<ModifiedFile>, OxideError> {
+    let manifest = load_manifest(&game_id).await?;
+    let mut modified = Vec::new();
+
+    for (path, file) in &manifest.files {
+        let actual = current_hash(&manifest.target_dir.join(path)).await?;
+        if actual.as_deref() != Some(file.sha256.as_str()) {
+            let source = mod_store_dir(&game_id, &file.mod_id)?.join(path);
+            let store_modified = file.method != DeploymentMode::Copy
+                && current_hash(&source).await?.as_deref() != Some(file.sha256.as_str());
+            modified.push(ModifiedFile {
+                path: path.clone(),
+                mod_id: file.mod_id.clone(),
+                expected_sha256: file.sha256.clone(),
+                actual_sha256: actual,
+                store_modified,
+            });
+        }
+    }
+
+    Ok(modified)
+}
+
+/// Get how mods are deployed for a game
+pub async fn get_deployment_settings(game_id: String) -> Result<DeploymentSettings, OxideError> {
+    load_settings(&game_id).await
+}
+
+/// Change the deployment mode and redeploy every file with it
+pub async fn set_deployment_mode(
+    game_id: String,
+    mode: DeploymentMode,
+) -> Result<DeploySummary, OxideError> {
+    deployment_target(&game_id)?;
+
+    let mut settings = load_settings(&game_id).await?;
+    settings.mode = mode;
+    let settings_path = game_mods_dir(&game_id)?.join(SETTINGS_FILE);
+    persist::write_json(&settings_path, &settings).await?;
+
+    let purged = purge_mods(game_id.clone()).await?;
+    let mut summary = deploy_active_profile(&game_id).await?;
+    summary.preserved.extend(purged.preserved);
+    Ok(summary)
+}
+
+/// Directory an installed mod is unpacked into before deployment
+pub(crate) fn mod_store_dir(game_id: &str, mod_id: &str) -> Result<PathBuf, OxideError> {
+    Ok(game_mods_dir(game_id)?.join(STORE_DIR).join(mod_id))
+}
+
+/// Redeploy after the enabled mods, their order or the winner rules changed
+///
+/// Returns `None` without deploying anything while the game is not installed;
+/// its mods are deployed once it is and `deploy_mods` runs.
+pub(crate) async fn redeploy_active_profile(
+    game_id: &str,
+) -> Result<Option<DeploySummary>, OxideError> {
+    if !installed_game_dir(game_id)?.exists() {
+        return Ok(None);
+    }
+    deploy_active_profile(game_id).await.map(Some)
+}
+
+/// Make the deployed files match the mods enabled in the active profile
+///
+/// Each path is provided by a single enabled mod, chosen by the conflict rules and
+/// load order. Files of mods that are no longer enabled are removed, files missing
+/// from the mod store are reported rather than deployed. Fails when the game
+/// itself is not installed, as there is nowhere to deploy to.
+pub(crate) async fn deploy_active_profile(game_id: &str) -> Result<DeploySummary, OxideError> {
+    let target_dir = deployment_target(game_id)?;
+
+    let mods_dir = game_mods_dir(game_id)?;
+    let settings = load_settings(game_id).await?;
+    let enabled = profiles::active_enabled_mods(game_id).await?;
+    let rules = conflicts::load_conflict_rules(game_id).await?;
+    let order = load_order::current_load_order(game_id).await?.order;
+
+    let mut owners: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    for manifest in conflicts::load_file_manifests(game_id).await? {
+        if !enabled.contains(&manifest.mod_id) {
+            continue;
+        }
+        for entry in manifest.files {
+            owners
+                .entry(entry.path)
+                .or_default()
+                .push((manifest.mod_id.clone(), entry.sha256));
+        }
+    }
+
+    // Path -> (winning mod, content hash)
+    let mut desired = BTreeMap::new();
+    for (path, mods) in owners {
+        let contenders: Vec<&str> = mods.iter().map(|(id, _)| id.as_str()).collect();
+        if let Some((winner, _)) = conflicts::resolve_winner(&rules, &order, &path, &contenders) {
+            if let Some((_, sha256)) = mods.into_iter().find(|(id, _)| *id == winner) {
+                desired.insert(path, (winner, sha256));
+            }
+        }
+    }
+
+    let mut manifest = load_manifest(game_id).await?;
+    let mut summary = DeploySummary::default();
+    let applied = apply_deployment(
+        game_id,
+        &target_dir,
+        &settings,
+        desired,
+        &mut manifest,
+        &mut summary,
+    )
+    .await;
+
+    // Saved on every exit path, so the manifest always lists what is on disk
+    save_manifest(game_id, &manifest).await?;
+    applied?;
+    export_load_order(game_id, &mods_dir, &target_dir, &order, &enabled).await?;
+    Ok(summary)
+}
+
+/// Bring the files in the game directory in line with `desired`, path -> (mod, hash)
+///
+/// `manifest` is updated after every file, so when this fails part way it still
+/// describes exactly what was deployed.
+async fn apply_deployment(
+    game_id: &str,
+    target_dir: &Path,
+    settings: &DeploymentSettings,
+    desired: BTreeMap<String, (String, String)>,
+    manifest: &mut DeploymentManifest,
+    summary: &mut DeploySummary,
+) -> Result<(), OxideError> {
+    let mods_dir = game_mods_dir(game_id)?;
+    // Files deployed into a directory the game no longer lives in are all removed
+    let same_target = manifest.target_dir == target_dir;
+
+    let previous: Vec<(String, DeployedFile)> = manifest.files.clone().into_iter().collect();
+    for (path, file) in previous {
+        let deployed_path = manifest.target_dir.join(&path);
+        let keep = same_target
+            && desired
+                .get(&path)
+                .is_some_and(|(mod_id, sha256)| *mod_id == file.mod_id && *sha256 == file.sha256);
+        if keep && current_hash(&deployed_path).await?.as_deref() == Some(file.sha256.as_str()) {
+            summary.unchanged += 1;
+            continue;
+        }
+
+        let preserved = undeploy_file(&mods_dir, &deployed_path, &path, &file).await?;
+        manifest.files.remove(&path);
+        if preserved {
+            summary.preserved.push(path.clone());
+        }
+        if !desired.contains_key(&path) {
+            summary.removed += 1;
+        }
+    }
+    manifest.target_dir = target_dir.to_path_buf();
+
+    for (path, (mod_id, sha256)) in desired {
+        if manifest.files.contains_key(&path) {
+            continue;
+        }
+
+        let source = mod_store_dir(game_id, &mod_id)?.join(&path);
+        if !source.is_file() {
+            summary.store_missing.push(path);
+            continue;
+        }
+        // A game editing a linked file in place edits the store copy, which must
+        // not be linked again as if it were what the mod shipped
+        if current_hash(&source).await?.as_deref() != Some(sha256.as_str()) {
+            summary.store_modified.push(path);
+            continue;
+        }
+
+        let deployed_path = target_dir.join(&path);
+        if let Some(parent) = deployed_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // Whatever is still here belongs to the game, keep it for purging
+        let vanilla_backup = fs::symlink_metadata(&deployed_path).await.is_ok();
+        if vanilla_backup && back_up_game_file(&mods_dir, &deployed_path, &path).await? {
+            summary.preserved.push(path.clone());
+        }
+
+        let mode = if is_rewritten(&path, settings) {
+            DeploymentMode::Copy
+        } else {
+            settings.mode
+        };
+        let method = link_file(&source, &deployed_path, mode).await?;
+        manifest.files.insert(
+            path,
+            DeployedFile {
+                mod_id,
+                method,
+                sha256,
+                vanilla_backup,
+            },
+        );
+        summary.deployed += 1;
+    }
+
+    Ok(())
+}
+
+/// Move a game file out of the way of a deployed file
+///
+/// The original is kept in the vanilla directory. Should a backup of the path be
+/// there already, e.g. after an interrupted deployment, it is left alone and the
+/// file is kept in the overwrite directory instead; true is returned then.
+async fn back_up_game_file(
+    mods_dir: &Path,
+    deployed_path: &Path,
+    path: &str,
+) -> Result<bool, OxideError> {
+    let backup = mods_dir.join(VANILLA_DIR).join(path);
+    if fs::symlink_metadata(&backup).await.is_err() {
+        move_file(deployed_path, &backup).await?;
+        return Ok(false);
+    }
+    let overwrite = free_overwrite_path(mods_dir, path).await;
+    move_file(deployed_path, &overwrite).await?;
+    Ok(true)
+}
+
+/// Write the load order of the enabled mods into the game directory
+///
+/// A load order file the game shipped itself is moved aside, like any other
+/// replaced game file, and put back on purge.
+async fn export_load_order(
+    game_id: &str,
+    mods_dir: &Path,
+    target_dir: &Path,
+    order: &[String],
+    enabled: &HashSet<String>,
+) -> Result<(), OxideError> {
+    let contents = load_order::load_order_export(game_id, order, enabled);
+    let path = target_dir.join(load_order::LOAD_ORDER_EXPORT_FILE);
+
+    match fs::read(&path).await {
+        Ok(existing) if existing == contents.as_bytes() => return Ok(()),
+        Ok(existing) if !load_order::is_generated_export(&existing) => {
+            back_up_game_file(mods_dir, &path, load_order::LOAD_ORDER_EXPORT_FILE).await?;
+        }
+        _ => {}
+    }
+    fs::write(&path, contents).await?;
+    Ok(())
+}
+
+/// Remove the exported load order and restore the game's own file, if it had one
+async fn remove_load_order_export(mods_dir: &Path, target_dir: &Path) -> Result<(), OxideError> {
+    let path = target_dir.join(load_order::LOAD_ORDER_EXPORT_FILE);
+    if let Ok(existing) = fs::read(&path).await {
+        if load_order::is_generated_export(&existing) {
+            fs::remove_file(&path).await?;
+        }
+    }
+
+    let backup = mods_dir
+        .join(VANILLA_DIR)
+        .join(load_order::LOAD_ORDER_EXPORT_FILE);
+    if backup.exists() && !path.exists() {
+        move_file(&backup, &path).await?;
+    }
+    Ok(())
+}
+
+/// Remove a deployed file and put back the game file it replaced
+///
+/// Returns true when the file had been modified in place and was kept in the
+/// overwrite directory instead of being deleted. Links are copied there rather
+/// than moved, so the kept file shares nothing with the mod store.
+async fn undeploy_file(
+    mods_dir: &Path,
+    deployed_path: &Path,
+    path: &str,
+    file: &DeployedFile,
+) -> Result<bool, OxideError> {
+    let mut preserved = false;
+
+    if fs::symlink_metadata(deployed_path).await.is_ok() {
+        let modified = current_hash(deployed_path).await?.as_deref() != Some(file.sha256.as_str());
+
+        if modified && file.method == DeploymentMode::Copy {
+            let overwrite = free_overwrite_path(mods_dir, path).await;
+            move_file(deployed_path, &overwrite).await?;
+            preserved = true;
+        } else if modified {
+            let overwrite = free_overwrite_path(mods_dir, path).await;
+            if let Some(parent) = overwrite.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            match fs::copy(deployed_path, &overwrite).await {
+                Ok(_) => preserved = true,
+                // Dangling when the store copy is gone, there is nothing to keep then
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            fs::remove_file(deployed_path).await?;
+        } else {
+            fs::remove_file(deployed_path).await?;
+        }
+    }
+
+    if file.vanilla_backup {
+        let backup = mods_dir.join(VANILLA_DIR).join(path);
+        if backup.exists() {
+            move_file(&backup, deployed_path).await?;
+        }
+    }
+
+    Ok(preserved)
+}
+
+/// Where to keep a modified copy of `path` without replacing earlier kept copies
+///
+/// The first copy is kept as the path itself, later ones get `.1`, `.2`, ... appended.
+async fn free_overwrite_path(mods_dir: &Path, path: &str) -> PathBuf {
+    let first = mods_dir.join(OVERWRITE_DIR).join(path);
+    let mut candidate = first.clone();
+    let mut number = 1;
+    while fs::symlink_metadata(&candidate).await.is_ok() {
+        let mut name = first.clone().into_os_string();
+        name.push(format!(".{}", number));
+        candidate = PathBuf::from(name);
+        number += 1;
+    }
+    candidate
+}
+
+/// Whether `path` is a file games are expected to rewrite and must be copied
+fn is_rewritten(path: &str, settings: &DeploymentSettings) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            settings
+                .copied_extensions
+                .iter()
+                .any(|copied| copied.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Directory mods are deployed into, failing when the game is not installed
+fn deployment_target(game_id: &str) -> Result<PathBuf, OxideError> {
+    let target_dir = installed_game_dir(game_id)?;
+    if !target_dir.exists() {
+        return Err(OxideError::NotFound(format!(
+            "Game {} is not installed, no mods were deployed",
+            game_id
+        )));
+    }
+    Ok(target_dir)
+}
+
+/// Materialize `source` at `destination`, falling back to cheaper-to-support methods
+///
+/// Copies go through `fs::copy`, which clones the file on filesystems that
+/// support it (APFS, Btrfs, XFS), so they only take space once the game changes them.
+async fn link_file(
+    source: &Path,
+    destination: &Path,
+    mode: DeploymentMode,
+) -> Result<DeploymentMode, OxideError> {
+    if mode == DeploymentMode::Hardlink && fs::hard_link(source, destination).await.is_ok() {
+        return Ok(DeploymentMode::Hardlink);
+    }
+    if mode != DeploymentMode::Copy && symlink_file(source, destination).await.is_ok() {
+        return Ok(DeploymentMode::Symlink);
+    }
+    fs::copy(source, destination).await?;
+    Ok(DeploymentMode::Copy)
+}
+
+#[cfg(unix)]
+async fn symlink_file(source: &Path, destination: &Path) -> std::io::Result<()> {
+    fs::symlink(source, destination).await
+}
+
+#[cfg(windows)]
+async fn symlink_file(source: &Path, destination: &Path) -> std::io::Result<()> {
+    // Requires developer mode or elevation, callers fall back to copying
+    fs::symlink_file(source, destination).await
+}
+
+/// Hash of the file at `path`, following symlinks, or `None` if it is gone
+async fn current_hash(path: &Path) -> Result<Option<String>, OxideError> {
+    let path = path.to_path_buf();
+    let hashed = tokio::task::spawn_blocking(move || archive::file_sha256(&path))
+        .await
+        .map_err(|e| OxideError::General(format!("Hash task failed: {}", e)))?;
+    match hashed {
+        Ok(sha256) => Ok(Some(sha256)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Move a file, copying when source and destination are on different volumes
+///
+/// Never replaces an existing file, backups must not be lost to a later move.
+async fn move_file(source: &Path, destination: &Path) -> Result<(), OxideError> {
+    if fs::symlink_metadata(destination).await.is_ok() {
+        return Err(OxideError::FileOperationError(format!(
+            "Not moving {} over existing {}",
+            source.display(),
+            destination.display()
+        )));
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    if fs::rename(source, destination).await.is_err() {
+        fs::copy(source, destination).await?;
+        fs::remove_file(source).await?;
+    }
+    Ok(())
+}
+
+async fn load_settings(game_id: &str) -> Result<DeploymentSettings, OxideError> {
+    let path = game_mods_dir(game_id)?.join(SETTINGS_FILE);
+    Ok(persist::read_json(&path).await?.unwrap_or_default())
+}
+
+async fn load_manifest(game_id: &str) -> Result<DeploymentManifest, OxideError> {
+    let path = game_mods_dir(game_id)?.join(DEPLOYMENT_FILE);
+    Ok(persist::read_json(&path).await?.unwrap_or_default())
+}
+
+async fn save_manifest(game_id: &str, manifest: &DeploymentManifest) -> Result<(), OxideError> {
+    persist::write_json(&game_mods_dir(game_id)?.join(DEPLOYMENT_FILE), manifest).await
+}
+
+async fn remove_manifest(game_id: &str) -> Result<(), OxideError> {
+    let path = game_mods_dir(game_id)?.join(DEPLOYMENT_FILE);
+    if path.exists() {
+        fs::remove_file(path).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_downloader::game_install_dir;
+    use crate::mod_engine::{install_mod, package};
+
+    /// Install a mod shipping `files` from a temporary folder
+    async fn install(game_id: &str, mod_id: &str, files: &[(&str, &str)]) {
+        let source = tempfile::tempdir().unwrap();
+        let descriptor = serde_json::json!({
+            "mod_id": mod_id,
+            "game_id": game_id,
+            "dependencies": [],
+            "description": "",
+            "version": "1.0.0",
+            "author": "tester",
+        });
+        std::fs::write(
+            source.path().join(package::DESCRIPTOR_FILE),
+            descriptor.to_string(),
+        )
+        .unwrap();
+        for (path, contents) in files {
+            let file = source.path().join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, contents).unwrap();
+        }
+        install_mod(
+            game_id.to_string(),
+            source.path().to_string_lossy().to_string(),
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Create the game directory so mods get deployed into it
+    fn install_game(game_id: &str) -> PathBuf {
+        let game_dir = game_install_dir(game_id).unwrap();
+        std::fs::create_dir_all(&game_dir).unwrap();
+        game_dir
+    }
+
+    fn exported_mods(game_dir: &Path) -> Vec<String> {
+        std::fs::read_to_string(game_dir.join(load_order::LOAD_ORDER_EXPORT_FILE))
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn exports_enabled_mods_into_game_directory() {
+        let game_id = "deploy-load-order";
+        let game_dir = install_game(game_id);
+        std::fs::write(game_dir.join(load_order::LOAD_ORDER_EXPORT_FILE), "vanilla").unwrap();
+
+        install(game_id, "first", &[("first.esp", "1")]).await;
+        install(game_id, "second", &[("second.esp", "2")]).await;
+        let order = load_order::current_load_order(game_id).await.unwrap().order;
+        assert_eq!(exported_mods(&game_dir), order);
+
+        profiles::set_mod_enabled(game_id.to_string(), order[0].clone(), false)
+            .await
+            .unwrap();
+        assert_eq!(exported_mods(&game_dir), order[1..]);
+
+        purge_mods(game_id.to_string()).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(game_dir.join(load_order::LOAD_ORDER_EXPORT_FILE)).unwrap(),
+            "vanilla"
+        );
+    }
+
+    #[tokio::test]
+    async fn links_mod_files_and_copies_rewritten_ones() {
+        let game_id = "deploy-linking";
+        let game_dir = install_game(game_id);
+        install(
+            game_id,
+            "visuals",
+            &[("textures/sky.dds", "sky"), ("visuals.ini", "[sky]")],
+        )
+        .await;
+
+        let manifest = load_manifest(game_id).await.unwrap();
+        assert_eq!(manifest.target_dir, game_dir);
+        assert_eq!(
+            manifest.files["textures/sky.dds"].method,
+            DeploymentMode::Hardlink
+        );
+        assert_eq!(manifest.files["visuals.ini"].method, DeploymentMode::Copy);
+        assert_eq!(
+            std::fs::read_to_string(game_dir.join("textures/sky.dds")).unwrap(),
+            "sky"
+        );
+
+        let summary = set_deployment_mode(game_id.to_string(), DeploymentMode::Symlink)
+            .await
+            .unwrap();
+        assert_eq!(summary.deployed, 2);
+        let sky = game_dir.join("textures/sky.dds");
+        assert!(std::fs::symlink_metadata(&sky)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(!std::fs::symlink_metadata(game_dir.join("visuals.ini"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+
+        // Nothing changed, nothing is touched
+        let summary = deploy_mods(game_id.to_string()).await.unwrap();
+        assert_eq!((summary.deployed, summary.unchanged), (0, 2));
+    }
+
+    #[tokio::test]
+    async fn purge_restores_vanilla_files() {
+        let game_id = "deploy-purge";
+        let game_dir = install_game(game_id);
+        std::fs::write(game_dir.join("game.esp"), "vanilla").unwrap();
+        install(
+            game_id,
+            "overhaul",
+            &[("game.esp", "modded"), ("extra.esp", "")],
+        )
+        .await;
+
+        assert_eq!(
+            std::fs::read_to_string(game_dir.join("game.esp")).unwrap(),
+            "modded"
+        );
+
+        let report = purge_mods(game_id.to_string()).await.unwrap();
+        assert_eq!((report.removed, report.restored), (2, 1));
+        assert_eq!(
+            std::fs::read_to_string(game_dir.join("game.esp")).unwrap(),
+            "vanilla"
+        );
+        assert!(!game_dir.join("extra.esp").exists());
+        assert!(load_manifest(game_id).await.unwrap().files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn detects_and_preserves_modified_files() {
+        let game_id = "deploy-modified";
+        let game_dir = install_game(game_id);
+        install(
+            game_id,
+            "tweaks",
+            &[("tweaks.ini", "defaults"), ("tweaks.esp", "plugin")],
+        )
+        .await;
+
+        std::fs::write(game_dir.join("tweaks.ini"), "user settings").unwrap();
+        std::fs::remove_file(game_dir.join("tweaks.esp")).unwrap();
+
+        let mut modified = get_modified_files(game_id.to_string()).await.unwrap();
+        modified.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(modified.len(), 2);
+        assert_eq!(modified[0].path, "tweaks.esp");
+        assert_eq!(modified[0].actual_sha256, None);
+        assert_eq!(modified[1].path, "tweaks.ini");
+        assert!(modified[1].actual_sha256.is_some());
+        assert!(!modified[1].store_modified);
+
+        let report = purge_mods(game_id.to_string()).await.unwrap();
+        assert_eq!(report.preserved, ["tweaks.ini"]);
+        let kept = game_mods_dir(game_id)
+            .unwrap()
+            .join(OVERWRITE_DIR)
+            .join("tweaks.ini");
+        assert_eq!(std::fs::read_to_string(kept).unwrap(), "user settings");
+    }
+
+    #[tokio::test]
+    async fn reports_files_missing_from_the_store() {
+        let game_id = "deploy-store-missing";
+        let game_dir = install_game(game_id);
+        install(game_id, "broken", &[("a.esp", "a"), ("b.esp", "b")]).await;
+        purge_mods(game_id.to_string()).await.unwrap();
+
+        std::fs::remove_file(mod_store_dir(game_id, "broken").unwrap().join("a.esp")).unwrap();
+        let summary = deploy_mods(game_id.to_string()).await.unwrap();
+        assert_eq!(summary.store_missing, ["a.esp"]);
+        assert_eq!(summary.deployed, 1);
+        assert!(game_dir.join("b.esp").is_file());
+        assert!(load_manifest(game_id)
+            .await
+            .unwrap()
+            .files
+            .contains_key("b.esp"));
+    }
+
+    #[tokio::test]
+    async fn keeps_an_existing_vanilla_backup() {
+        let game_id = "deploy-existing-backup";
+        let game_dir = install_game(game_id);
+        let mods_dir = game_mods_dir(game_id).unwrap();
+        // Left behind by an interrupted deployment
+        std::fs::create_dir_all(mods_dir.join(VANILLA_DIR)).unwrap();
+        std::fs::write(mods_dir.join(VANILLA_DIR).join("game.esp"), "original").unwrap();
+        std::fs::write(game_dir.join("game.esp"), "leftover").unwrap();
+
+        install(game_id, "replacer", &[("game.esp", "modded")]).await;
+        assert_eq!(
+            std::fs::read_to_string(mods_dir.join(VANILLA_DIR).join("game.esp")).unwrap(),
+            "original"
+        );
+        assert_eq!(
+            std::fs::read_to_string(mods_dir.join(OVERWRITE_DIR).join("game.esp")).unwrap(),
+            "leftover"
+        );
+
+        purge_mods(game_id.to_string()).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(game_dir.join("game.esp")).unwrap(),
+            "original"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use super::{deploy, game_mods_dir, read_installed_mods, ModDescriptor};
+use crate::{persist, OxideError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+const LOAD_ORDER_FILE: &str = "load_order.json";
+/// Plain text export read by games: one mod id per line, `#` starts a comment
+pub(crate) const LOAD_ORDER_EXPORT_FILE: &str = "loadorder.txt";
+/// First line of every export Oxide writes, telling it apart from a game's own file
+const EXPORT_HEADER: &str = "# Load order for ";
+
+/// Persisted load order of a game's mods, first entry loads first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoadOrder {
+    pub order: Vec<String>,
+    /// Set once the user arranged the order by hand
+    #[serde(default)]
+    pub manual: bool,
+}
+
+/// Get the load order of a game, accounting for mods installed or removed since it was saved
+pub async fn get_load_order(game_id: String) -> Result<LoadOrder, OxideError> {
+    current_load_order(&game_id).await
+}
+
+/// Replace the load order with an automatic sort based on dependencies and hints
+pub async fn sort_load_order(game_id: String) -> Result<LoadOrder, OxideError> {
+    let mods = read_installed_mods(&game_id).await?;
+    let current = load_saved_order(&game_id).await?;
+
+    let load_order = LoadOrder {
+        order: sort_mods(&mods, &current.order)?,
+        manual: false,
+    };
+    save_load_order(&game_id, &load_order).await?;
+    deploy::redeploy_active_profile(&game_id).await?;
+
+    Ok(load_order)
+}
+
+/// Manually override the load order
+///
+/// Every installed mod must appear exactly once and dependencies must still load
+/// before the mods that need them. `load_after`/`load_before` hints are advisory
+/// and may be overridden.
+pub async fn set_load_order(game_id: String, order: Vec<String>) -> Result<LoadOrder, OxideError> {
+    let mods = read_installed_mods(&game_id).await?;
+
+    let installed: HashSet<&str> = mods.iter().map(|m| m.mod_id.as_str()).collect();
+    let mut seen = HashSet::new();
+    for mod_id in &order {
+        if !installed.contains(mod_id.as_str()) {
+            return Err(OxideError::NotFound(format!(
+                "Mod {} is not installed",
+                mod_id
+            )));
+        }
+        if !seen.insert(mod_id.as_str()) {
+            return Err(OxideError::General(format!(
+                "Mod {} appears more than once in the load order",
+                mod_id
+            )));
+        }
+    }
+    if let Some(missing) = mods.iter().find(|m| !seen.contains(m.mod_id.as_str())) {
+        return Err(OxideError::General(format!(
+            "Mod {} is missing from the load order",
+            missing.mod_id
+        )));
+    }
+
+    let position: HashMap<&str, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i))
+        .collect();
+    for descriptor in &mods {
+        for dep in &descriptor.dependencies {
+            if let Some(&dep_pos) = position.get(dep.as_str()) {
+                if dep_pos > position[descriptor.mod_id.as_str()] {
+                    return Err(OxideError::General(format!(
+                        "Mod {} must load after its dependency {}",
+                        descriptor.mod_id, dep
+                    )));
+                }
+            }
+        }
+    }
+
+    let load_order = LoadOrder {
+        order,
+        manual: true,
+    };
+    save_load_order(&game_id, &load_order).await?;
+    deploy::redeploy_active_profile(&game_id).await?;
+
+    Ok(load_order)
+}
+
+/// The saved order brought in line with the installed mods, without writing it
+///
+/// Mods that were uninstalled are dropped. Newly installed mods are sorted among
+/// themselves and appended, so an existing (possibly manual) order is preserved.
+pub(crate) async fn current_load_order(game_id: &str) -> Result<LoadOrder, OxideError> {
+    Ok(reconcile_load_order(game_id).await?.0)
+}
+
+/// Bring the saved order in line with the installed mods and persist it
+///
+/// Called after mods were installed or removed; read-only paths use
+/// `current_load_order` instead.
+pub(crate) async fn refresh_load_order(game_id: &str) -> Result<LoadOrder, OxideError> {
+    let (load_order, changed) = reconcile_load_order(game_id).await?;
+    if changed {
+        save_load_order(game_id, &load_order).await?;
+    }
+    Ok(load_order)
+}
+
+/// The reconciled order and whether it differs from the saved one
+async fn reconcile_load_order(game_id: &str) -> Result<(LoadOrder, bool), OxideError> {
+    let mods = read_installed_mods(game_id).await?;
+    let mut load_order = load_saved_order(game_id).await?;
+
+    let previous_len = load_order.order.len();
+    let installed: HashSet<&str> = mods.iter().map(|m| m.mod_id.as_str()).collect();
+    load_order
+        .order
+        .retain(|mod_id| installed.contains(mod_id.as_str()));
+    let mut changed = load_order.order.len() != previous_len;
+
+    let known: HashSet<String> = load_order.order.iter().cloned().collect();
+    let new_mods: Vec<ModDescriptor> = mods
+        .iter()
+        .filter(|m| !known.contains(&m.mod_id))
+        .cloned()
+        .collect();
+    if !new_mods.is_empty() {
+        load_order.order.extend(sort_mods(&new_mods, &[])?);
+        changed = true;
+    }
+
+    Ok((load_order, changed))
+}
+
+/// Re-run dependency resolution after the installed mods changed
+///
+/// Fails when an installed mod lost a dependency. A saved order that no longer
+/// loads dependencies first is replaced by an automatic sort.
+pub(crate) async fn ensure_dependency_order(game_id: &str) -> Result<LoadOrder, OxideError> {
+    let mods = read_installed_mods(game_id).await?;
+    let installed: HashSet<&str> = mods.iter().map(|m| m.mod_id.as_str()).collect();
+    for descriptor in &mods {
+        if let Some(dep) = descriptor
+            .dependencies
+            .iter()
+            .find(|dep| !installed.contains(dep.as_str()))
+        {
+            return Err(OxideError::ModInstallationFailed(format!(
+                "Mod {} is missing dependency {}",
+                descriptor.mod_id, dep
+            )));
+        }
+    }
+
+    let load_order = refresh_load_order(game_id).await?;
+    let position: HashMap<&str, usize> = load_order
+        .order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i))
+        .collect();
+    let out_of_order = mods.iter().any(|descriptor| {
+        descriptor
+            .dependencies
+            .iter()
+            .any(|dep| position[dep.as_str()] > position[descriptor.mod_id.as_str()])
+    });
+
+    if out_of_order {
+        sort_load_order(game_id.to_string()).await
+    } else {
+        Ok(load_order)
+    }
+}
+
+/// Topologically sort mods so dependencies and `load_after` targets come first
+///
+/// Ties keep their position in `previous`, then fall back to mod id, so the result
+/// is deterministic. Hints are added one at a time and any hint that would close
+/// a cycle is dropped; a cycle made of dependencies alone is an error.
+pub(crate) fn sort_mods(
+    mods: &[ModDescriptor],
+    previous: &[String],
+) -> Result<Vec<String>, OxideError> {
+    let ids: HashSet<&str> = mods.iter().map(|m| m.mod_id.as_str()).collect();
+
+    let mut edges = Vec::new();
+    let mut hint_edges = BTreeSet::new();
+    for descriptor in mods {
+        let id = descriptor.mod_id.as_str();
+        for dep in &descriptor.dependencies {
+            if ids.contains(dep.as_str()) {
+                edges.push((dep.as_str(), id));
+            }
+        }
+        for after in &descriptor.load_after {
+            if ids.contains(after.as_str()) {
+                hint_edges.insert((after.as_str(), id));
+            }
+        }
+        for before in &descriptor.load_before {
+            if ids.contains(before.as_str()) {
+                hint_edges.insert((id, before.as_str()));
+            }
+        }
+    }
+
+    let rank: HashMap<&str, usize> = previous
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i))
+        .collect();
+
+    if topological_order(&ids, &edges, &rank).is_none() {
+        return Err(OxideError::General(
+            "Mod dependencies contain a cycle".to_string(),
+        ));
+    }
+    for (from, to) in hint_edges {
+        // from -> to closes a cycle exactly when `from` is already reachable from `to`
+        if from != to && !reachable(&edges, to, from) {
+            edges.push((from, to));
+        }
+    }
+
+    topological_order(&ids, &edges, &rank)
+        .ok_or_else(|| OxideError::General("Mod dependencies contain a cycle".to_string()))
+}
+
+/// Whether `target` can be reached from `start` following `edges`
+fn reachable(edges: &[(&str, &str)], start: &str, target: &str) -> bool {
+    let mut stack = vec![start];
+    let mut seen = HashSet::new();
+    while let Some(id) = stack.pop() {
+        if id == target {
+            return true;
+        }
+        if seen.insert(id) {
+            stack.extend(
+                edges
+                    .iter()
+                    .filter(|(from, _)| *from == id)
+                    .map(|(_, to)| *to),
+            );
+        }
+    }
+    false
+}
+
+/// Kahn's algorithm, always emitting the lowest ranked ready mod next
+fn topological_order(
+    ids: &HashSet<&str>,
+    edges: &[(&str, &str)],
+    rank: &HashMap<&str, usize>,
+) -> Option<Vec<String>> {
+    let mut incoming: HashMap<&str, usize> = ids.iter().map(|id| (*id, 0)).collect();
+    let mut outgoing: HashMap<&str, Vec<&str>> = HashMap::new();
+    let unique: BTreeSet<&(&str, &str)> = edges.iter().collect();
+    for (from, to) in unique {
+        outgoing.entry(from).or_default().push(to);
+        *incoming.entry(to).or_default() += 1;
+    }
+
+    let key = |id: &str| (rank.get(id).copied().unwrap_or(usize::MAX), id.to_string());
+    let mut ready: BTreeSet<(usize, String)> = incoming
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(id, _)| key(id))
+        .collect();
+
+    let mut order = Vec::with_capacity(ids.len());
+    while let Some(next) = ready.pop_first() {
+        let id = next.1;
+        for to in outgoing.get(id.as_str()).into_iter().flatten() {
+            let count = incoming.get_mut(to)?;
+            *count -= 1;
+            if *count == 0 {
+                ready.insert(key(to));
+            }
+        }
+        order.push(id);
+    }
+
+    (order.len() == ids.len()).then_some(order)
+}
+
+async fn load_saved_order(game_id: &str) -> Result<LoadOrder, OxideError> {
+    let path = game_mods_dir(game_id)?.join(LOAD_ORDER_FILE);
+    Ok(persist::read_json(&path).await?.unwrap_or_default())
+}
+
+async fn save_load_order(game_id: &str, load_order: &LoadOrder) -> Result<(), OxideError> {
+    persist::write_json(&game_mods_dir(game_id)?.join(LOAD_ORDER_FILE), load_order).await
+}
+
+/// Contents of the load order file the game reads, listing only the mods deployed
+pub(crate) fn load_order_export(
+    game_id: &str,
+    order: &[String],
+    enabled: &HashSet<String>,
+) -> String {
+    let mut export = format!(
+        "{}{}, generated by Oxide\n# One mod id per line, earlier lines load first\n",
+        EXPORT_HEADER, game_id
+    );
+    for mod_id in order.iter().filter(|mod_id| enabled.contains(*mod_id)) {
+        export.push_str(mod_id);
+        export.push('\n');
+    }
+    export
+}
+
+/// Whether a load order file was written by Oxide rather than shipped with the game
+pub(crate) fn is_generated_export(contents: &[u8]) -> bool {
+    contents.starts_with(EXPORT_HEADER.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(mod_id: &str, dependencies: &[&str], load_after: &[&str]) -> ModDescriptor {
+        ModDescriptor {
+            mod_id: mod_id.to_string(),
+            game_id: "game".to_string(),
+            dependencies: dependencies.iter().map(|s| s.to_string()).collect(),
+            description: String::new(),
+            version: "1.0.0".to_string(),
+            author: String::new(),
+            load_after: load_after.iter().map(|s| s.to_string()).collect(),
+            load_before: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn drops_only_the_hint_closing_a_cycle() {
+        // a -> b and b -> a conflict, c's hint after a is unaffected
+        let mods = vec![
+            descriptor("a", &[], &["b"]),
+            descriptor("b", &[], &["a"]),
+            descriptor("c", &[], &["a"]),
+        ];
+        let order = sort_mods(&mods, &["c".to_string()]).unwrap();
+        let position = |id: &str| order.iter().position(|m| m == id).unwrap();
+        assert!(position("a") < position("c"));
+    }
+
+    #[test]
+    fn dependency_cycle_is_an_error() {
+        let mods = vec![descriptor("a", &["b"], &[]), descriptor("b", &["a"], &[])];
+        assert!(sort_mods(&mods, &[]).is_err());
+    }
+
+    #[test]
+    fn export_lists_only_enabled_mods_in_order() {
+        let order = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let enabled: HashSet<String> = ["c".to_string(), "a".to_string()].into();
+        let export = load_order_export("game", &order, &enabled);
+
+        assert!(is_generated_export(export.as_bytes()));
+        let mods: Vec<&str> = export.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(mods, ["a", "c"]);
+    }
+}
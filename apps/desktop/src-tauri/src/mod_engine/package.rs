@@ -0,0 +1,391 @@
+//! Validation and packaging of mod archives
+//!
+//! Shared by `install_mod` and the `oxide-mod` authoring tool, so an archive the
+//! tool produces is accepted by the launcher and vice versa.
+
+use super::conflicts::{normalize_path, ModFileEntry};
+use super::updates::parse_version;
+use super::{read_archive_descriptor, sha256_hex, ModDescriptor};
+use crate::OxideError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+pub const DESCRIPTOR_FILE: &str = "mod.json";
+
+/// Files operating systems and editors leave behind that never belong in a mod
+const JUNK_FILES: &[&str] = &[".DS_Store", "Thumbs.db", "desktop.ini"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    /// `install_mod` would reject the mod or install it incorrectly
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintIssue {
+    pub severity: LintSeverity,
+    pub path: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackSummary {
+    pub archive: PathBuf,
+    pub sha256: String,
+    pub files: Vec<ModFileEntry>,
+}
+
+/// Check a descriptor against the rules `install_mod` enforces
+///
+/// Returns a human readable description of every problem found.
+pub fn validate_descriptor(descriptor: &ModDescriptor) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for (field, value) in [
+        ("mod_id", &descriptor.mod_id),
+        ("game_id", &descriptor.game_id),
+    ] {
+        if value.is_empty() {
+            problems.push(format!("{} must not be empty", field));
+        } else if !is_valid_id(value) {
+            problems.push(format!(
+                "{} \"{}\" may only contain letters, digits, '-', '_' and '.'",
+                field, value
+            ));
+        }
+    }
+
+    if descriptor.version.trim().is_empty() {
+        problems.push("version must not be empty".to_string());
+    } else if parse_version(&descriptor.version).is_none() {
+        problems.push(format!(
+            "version \"{}\" is not a valid version, expected something like 1.2.0",
+            descriptor.version
+        ));
+    }
+
+    if descriptor.author.trim().is_empty() {
+        problems.push("author must not be empty".to_string());
+    }
+
+    let mut seen = HashSet::new();
+    for dep in &descriptor.dependencies {
+        if dep == &descriptor.mod_id {
+            problems.push("a mod cannot depend on itself".to_string());
+        } else if !is_valid_id(dep) {
+            problems.push(format!("dependency \"{}\" is not a valid mod id", dep));
+        } else if !seen.insert(dep) {
+            problems.push(format!("dependency \"{}\" is listed twice", dep));
+        }
+    }
+
+    for hint in descriptor.load_after.iter().chain(&descriptor.load_before) {
+        if hint == &descriptor.mod_id {
+            problems.push("load order hints cannot reference the mod itself".to_string());
+        }
+    }
+    if let Some(both) = descriptor
+        .load_after
+        .iter()
+        .find(|id| descriptor.load_before.contains(id))
+    {
+        problems.push(format!(
+            "\"{}\" is listed in both load_after and load_before",
+            both
+        ));
+    }
+
+    problems
+}
+
+/// Read and validate the `mod.json` of an unpacked mod directory
+pub fn read_descriptor(root: &Path) -> Result<ModDescriptor, OxideError> {
+    let path = root.join(DESCRIPTOR_FILE);
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| OxideError::NotFound(format!("Cannot read {}: {}", path.display(), e)))?;
+    let descriptor: ModDescriptor = serde_json::from_str(&contents)?;
+
+    let problems = validate_descriptor(&descriptor);
+    if !problems.is_empty() {
+        return Err(OxideError::ModInstallationFailed(problems.join("; ")));
+    }
+    Ok(descriptor)
+}
+
+/// Lint the file layout of an unpacked mod directory
+pub fn lint_layout(root: &Path) -> Result<Vec<LintIssue>, OxideError> {
+    let mut issues = Vec::new();
+    let issue = |severity, path: Option<&str>, message: String| LintIssue {
+        severity,
+        path: path.map(str::to_string),
+        message,
+    };
+
+    match read_descriptor(root) {
+        Ok(_) => {}
+        Err(e) => issues.push(issue(
+            LintSeverity::Error,
+            Some(DESCRIPTOR_FILE),
+            e.to_string(),
+        )),
+    }
+
+    let mut files = Vec::new();
+    collect_files(root, root, &mut files, &mut issues)?;
+
+    let mut lowercase: BTreeMap<String, &str> = BTreeMap::new();
+    for path in &files {
+        if let Some(existing) = lowercase.insert(path.to_lowercase(), path) {
+            issues.push(issue(
+                LintSeverity::Error,
+                Some(path),
+                format!(
+                    "differs from {} only by case and would overwrite it on Windows",
+                    existing
+                ),
+            ));
+        }
+
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        if JUNK_FILES.contains(&file_name) {
+            issues.push(issue(
+                LintSeverity::Warning,
+                Some(path),
+                "system file will be excluded from the archive".to_string(),
+            ));
+        }
+    }
+
+    if !files
+        .iter()
+        .any(|path| path != DESCRIPTOR_FILE && !is_junk(path))
+    {
+        issues.push(issue(
+            LintSeverity::Warning,
+            None,
+            "mod contains no files besides mod.json".to_string(),
+        ));
+    }
+
+    Ok(issues)
+}
+
+/// SHA-256 of every file that would be packed, sorted by path
+pub fn hash_files(root: &Path) -> Result<Vec<ModFileEntry>, OxideError> {
+    let mut entries = Vec::new();
+    for path in packable_files(root, None)? {
+        let contents = fs::read(root.join(&path))?;
+        entries.push(ModFileEntry {
+            path,
+            sha256: sha256_hex(&contents),
+            size: contents.len() as u64,
+        });
+    }
+    Ok(entries)
+}
+
+/// Build a reproducible archive from an unpacked mod directory
+///
+/// Entries are written in sorted order with fixed timestamps and permissions, so
+/// packing the same files twice yields byte-identical archives. The result is
+/// read back the way `install_mod` reads it before it is reported as packed.
+pub fn pack(root: &Path, output: &Path) -> Result<PackSummary, OxideError> {
+    let descriptor = read_descriptor(root)?;
+    let errors: Vec<String> = lint_layout(root)?
+        .into_iter()
+        .filter(|issue| issue.severity == LintSeverity::Error)
+        .map(|issue| match issue.path {
+            Some(path) => format!("{}: {}", path, issue.message),
+            None => issue.message,
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(OxideError::ModInstallationFailed(errors.join("; ")));
+    }
+
+    let mut files = Vec::new();
+    for path in packable_files(root, Some(output))? {
+        let contents = fs::read(root.join(&path))?;
+        files.push(ModFileEntry {
+            path,
+            sha256: sha256_hex(&contents),
+            size: contents.len() as u64,
+        });
+    }
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(DateTime::default())
+        .unix_permissions(0o644);
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = ZipWriter::new(fs::File::create(output)?);
+    for entry in &files {
+        writer.start_file(entry.path.as_str(), options)?;
+        writer.write_all(&fs::read(root.join(&entry.path))?)?;
+    }
+    writer.finish()?;
+
+    let packed = read_archive_descriptor(output)?;
+    if packed.mod_id != descriptor.mod_id {
+        return Err(OxideError::ModInstallationFailed(
+            "Packed archive does not contain the expected mod.json".to_string(),
+        ));
+    }
+
+    Ok(PackSummary {
+        archive: output.to_path_buf(),
+        sha256: sha256_hex(&fs::read(output)?),
+        files,
+    })
+}
+
+/// Default archive name for a mod, `<mod_id>-<version>.zip`
+pub fn archive_name(descriptor: &ModDescriptor) -> String {
+    format!("{}-{}.zip", descriptor.mod_id, descriptor.version)
+}
+
+/// Relative paths of every file that goes into the archive, `mod.json` first
+///
+/// `exclude` keeps an archive written inside the mod directory out of the next pack.
+fn packable_files(root: &Path, exclude: Option<&Path>) -> Result<Vec<String>, OxideError> {
+    let mut files = Vec::new();
+    collect_files(root, root, &mut files, &mut Vec::new())?;
+
+    let excluded = exclude
+        .and_then(|path| path.canonicalize().ok())
+        .and_then(|path| {
+            let root = root.canonicalize().ok()?;
+            path.strip_prefix(root).ok().map(normalize_path)
+        });
+    files.retain(|path| !is_junk(path) && Some(path) != excluded.as_ref());
+    files.sort_by(|a, b| (a != DESCRIPTOR_FILE, a).cmp(&(b != DESCRIPTOR_FILE, b)));
+    Ok(files)
+}
+
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    files: &mut Vec<String>,
+    issues: &mut Vec<LintIssue>,
+) -> Result<(), OxideError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let normalized = normalize_path(relative);
+        let file_type = entry.file_type()?;
+
+        if relative.to_str().is_none() {
+            issues.push(LintIssue {
+                severity: LintSeverity::Error,
+                path: Some(normalized),
+                message: "file name is not valid UTF-8".to_string(),
+            });
+        } else if file_type.is_symlink() {
+            issues.push(LintIssue {
+                severity: LintSeverity::Error,
+                path: Some(normalized),
+                message: "symbolic links cannot be packed".to_string(),
+            });
+        } else if file_type.is_dir() {
+            collect_files(root, &path, files, issues)?;
+        } else {
+            files.push(normalized);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_junk(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    JUNK_FILES.contains(&file_name)
+}
+
+/// Mod and game ids end up in file names, keep them portable
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id != "."
+        && id != ".."
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mod_engine::{deploy, install_mod};
+
+    fn write_mod(root: &Path, game_id: &str) {
+        fs::create_dir_all(root.join("textures")).unwrap();
+        let descriptor = serde_json::json!({
+            "mod_id": "packed-textures",
+            "game_id": game_id,
+            "dependencies": [],
+            "description": "",
+            "version": "1.0.0",
+            "author": "tester",
+        });
+        fs::write(root.join(DESCRIPTOR_FILE), descriptor.to_string()).unwrap();
+        fs::write(root.join("textures/sky.dds"), "sky").unwrap();
+        fs::write(root.join("textures/ground.dds"), "ground").unwrap();
+        fs::write(root.join("textures/Thumbs.db"), "junk").unwrap();
+    }
+
+    #[test]
+    fn packing_twice_gives_identical_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("mod");
+        write_mod(&root, "package-reproducible");
+
+        let first = pack(&root, &dir.path().join("first.zip")).unwrap();
+        // Different modification times must not leak into the archive
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        fs::write(root.join("textures/sky.dds"), "sky").unwrap();
+        let second = pack(&root, &dir.path().join("second.zip")).unwrap();
+
+        assert_eq!(first.sha256, second.sha256);
+        assert_eq!(
+            fs::read(&first.archive).unwrap(),
+            fs::read(&second.archive).unwrap()
+        );
+        let paths: Vec<&str> = first.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [DESCRIPTOR_FILE, "textures/ground.dds", "textures/sky.dds"]
+        );
+    }
+
+    #[tokio::test]
+    async fn install_accepts_packed_archive() {
+        let game_id = "package-install";
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("mod");
+        write_mod(&root, game_id);
+
+        // Packed inside the mod directory, the archive must not pack itself
+        let archive = root.join("packed-textures-1.0.0.zip");
+        pack(&root, &archive).unwrap();
+        pack(&root, &archive).unwrap();
+
+        install_mod(game_id.to_string(), archive.to_string_lossy().to_string())
+            .await
+            .unwrap();
+        let store_dir = deploy::mod_store_dir(game_id, "packed-textures").unwrap();
+        assert_eq!(
+            fs::read_to_string(store_dir.join("textures/sky.dds")).unwrap(),
+            "sky"
+        );
+        assert!(!store_dir.join("textures/Thumbs.db").exists());
+        assert!(!store_dir.join("packed-textures-1.0.0.zip").exists());
+    }
+}
@@ -0,0 +1,533 @@
+use super::{deploy, game_mods_dir, read_installed_mods, ModDescriptor};
+use crate::{persist, OxideError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use tokio::fs;
+
+const PROFILES_FILE: &str = "profiles.json";
+const DEFAULT_PROFILE: &str = "Default";
+const PROFILE_EXPORT_VERSION: u32 = 1;
+
+/// A named set of enabled mods
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModProfile {
+    pub name: String,
+    pub enabled_mods: Vec<String>,
+}
+
+/// All profiles of a game and which one is deployed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModProfiles {
+    pub active: String,
+    pub profiles: Vec<ModProfile>,
+}
+
+/// A mod referenced by an exported profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedMod {
+    pub mod_id: String,
+    pub version: String,
+}
+
+/// Single file representation of a profile, shared between machines
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModProfileExport {
+    pub format_version: u32,
+    pub game_id: String,
+    pub name: String,
+    pub mods: Vec<ExportedMod>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileImportReport {
+    pub profile: ModProfile,
+    /// Mods the profile enables that are not installed on this machine
+    pub missing_mods: Vec<ExportedMod>,
+    /// Mods installed here in a different version than the exporter had
+    pub version_mismatches: Vec<ExportedMod>,
+}
+
+/// List the profiles of a game
+pub async fn list_mod_profiles(game_id: String) -> Result<ModProfiles, OxideError> {
+    load_profiles(&game_id).await
+}
+
+/// Create a new profile, optionally copying the enabled mods of an existing one
+pub async fn create_mod_profile(
+    game_id: String,
+    name: String,
+    copy_from: Option<String>,
+) -> Result<ModProfiles, OxideError> {
+    let mut profiles = load_profiles(&game_id).await?;
+    validate_profile_name(&profiles, &name)?;
+
+    let enabled_mods = match copy_from {
+        Some(source) => find_profile(&profiles, &source)?.enabled_mods.clone(),
+        None => Vec::new(),
+    };
+    profiles.profiles.push(ModProfile { name, enabled_mods });
+
+    save_profiles(&game_id, &profiles).await?;
+    Ok(profiles)
+}
+
+/// Delete a profile that is not currently active
+pub async fn delete_mod_profile(game_id: String, name: String) -> Result<ModProfiles, OxideError> {
+    let mut profiles = load_profiles(&game_id).await?;
+    find_profile(&profiles, &name)?;
+    if profiles.active == name {
+        return Err(OxideError::General(
+            "Cannot delete the active profile, switch to another one first".to_string(),
+        ));
+    }
+
+    profiles.profiles.retain(|p| p.name != name);
+    save_profiles(&game_id, &profiles).await?;
+    Ok(profiles)
+}
+
+/// Activate a profile and deploy its mods
+pub async fn switch_mod_profile(game_id: String, name: String) -> Result<ModProfiles, OxideError> {
+    let mut profiles = load_profiles(&game_id).await?;
+    find_profile(&profiles, &name)?;
+
+    profiles.active = name;
+    save_profiles(&game_id, &profiles).await?;
+    deploy::redeploy_active_profile(&game_id).await?;
+
+    Ok(profiles)
+}
+
+/// Enable or disable an installed mod in the active profile without uninstalling it
+pub async fn set_mod_enabled(
+    game_id: String,
+    mod_id: String,
+    enabled: bool,
+) -> Result<ModProfiles, OxideError> {
+    let mods = read_installed_mods(&game_id).await?;
+    let descriptor = mods
+        .iter()
+        .find(|m| m.mod_id == mod_id)
+        .ok_or_else(|| OxideError::NotFound(format!("Mod {} is not installed", mod_id)))?;
+
+    let mut profiles = load_profiles(&game_id).await?;
+    let active = profiles.active.clone();
+    let profile = find_profile_mut(&mut profiles, &active)?;
+
+    if enabled {
+        check_dependencies_enabled(descriptor, &profile.enabled_mods)?;
+        if !profile.enabled_mods.contains(&mod_id) {
+            profile.enabled_mods.push(mod_id);
+        }
+    } else {
+        if let Some(dependent) = dependents(&mods, &profile.enabled_mods, &mod_id).first() {
+            return Err(OxideError::General(format!(
+                "Mod {} is required by enabled mod {}",
+                mod_id, dependent.mod_id
+            )));
+        }
+        profile.enabled_mods.retain(|id| *id != mod_id);
+    }
+
+    save_profiles(&game_id, &profiles).await?;
+    deploy::redeploy_active_profile(&game_id).await?;
+
+    Ok(profiles)
+}
+
+/// Write a profile to a single shareable file
+pub async fn export_mod_profile(
+    game_id: String,
+    name: String,
+    destination: String,
+) -> Result<String, OxideError> {
+    let profiles = load_profiles(&game_id).await?;
+    let profile = find_profile(&profiles, &name)?;
+    let mods = read_installed_mods(&game_id).await?;
+
+    let export = ModProfileExport {
+        format_version: PROFILE_EXPORT_VERSION,
+        game_id,
+        name: profile.name.clone(),
+        mods: profile
+            .enabled_mods
+            .iter()
+            .map(|mod_id| ExportedMod {
+                mod_id: mod_id.clone(),
+                version: mods
+                    .iter()
+                    .find(|m| &m.mod_id == mod_id)
+                    .map(|m| m.version.clone())
+                    .unwrap_or_default(),
+            })
+            .collect(),
+    };
+
+    let destination = PathBuf::from(destination);
+    fs::write(&destination, serde_json::to_string_pretty(&export)?).await?;
+
+    Ok(format!(
+        "Profile {} exported to {}",
+        name,
+        destination.display()
+    ))
+}
+
+/// Import a profile exported with `export_mod_profile`
+///
+/// The imported profile is added but not activated. Mods it references that are
+/// not installed are reported and left out until they are installed. A profile
+/// enabling a mod without the mods it depends on is rejected.
+pub async fn import_mod_profile(
+    game_id: String,
+    source: String,
+) -> Result<ProfileImportReport, OxideError> {
+    let contents = fs::read_to_string(&source).await?;
+    let export: ModProfileExport = serde_json::from_str(&contents)?;
+
+    if export.format_version > PROFILE_EXPORT_VERSION {
+        return Err(OxideError::General(format!(
+            "Unsupported profile format version {}",
+            export.format_version
+        )));
+    }
+    if export.game_id != game_id {
+        return Err(OxideError::General(format!(
+            "Profile was exported for game {}, not {}",
+            export.game_id, game_id
+        )));
+    }
+
+    let installed = read_installed_mods(&game_id).await?;
+    let mut profiles = load_profiles(&game_id).await?;
+
+    // Keep existing profiles intact by picking a free name
+    let mut name = export.name.clone();
+    let mut suffix = 2;
+    while profiles.profiles.iter().any(|p| p.name == name) {
+        name = format!("{} ({})", export.name, suffix);
+        suffix += 1;
+    }
+
+    let mut enabled = Vec::new();
+    let mut missing_mods = Vec::new();
+    let mut version_mismatches = Vec::new();
+    for exported in export.mods {
+        match installed.iter().find(|m| m.mod_id == exported.mod_id) {
+            Some(descriptor) => {
+                if descriptor.version != exported.version {
+                    version_mismatches.push(exported.clone());
+                }
+                enabled.push(descriptor);
+            }
+            None => missing_mods.push(exported),
+        }
+    }
+
+    let enabled_mods: Vec<String> = enabled.iter().map(|m| m.mod_id.clone()).collect();
+    for descriptor in enabled {
+        check_dependencies_enabled(descriptor, &enabled_mods)?;
+    }
+
+    let profile = ModProfile { name, enabled_mods };
+    profiles.profiles.push(profile.clone());
+    save_profiles(&game_id, &profiles).await?;
+
+    Ok(ProfileImportReport {
+        profile,
+        missing_mods,
+        version_mismatches,
+    })
+}
+
+/// Mods enabled in the active profile of a game
+pub(crate) async fn active_enabled_mods(game_id: &str) -> Result<HashSet<String>, OxideError> {
+    let profiles = load_profiles(game_id).await?;
+    let active = find_profile(&profiles, &profiles.active)?;
+    Ok(active.enabled_mods.iter().cloned().collect())
+}
+
+/// Enable a freshly installed mod in the active profile
+///
+/// The mod is left disabled while one of its dependencies is disabled in the
+/// active profile, that dependency is returned.
+pub(crate) async fn enable_installed_mod(
+    game_id: &str,
+    descriptor: &ModDescriptor,
+) -> Result<Option<String>, OxideError> {
+    let mut profiles = load_profiles(game_id).await?;
+    let active = profiles.active.clone();
+    let profile = find_profile_mut(&mut profiles, &active)?;
+
+    if profile.enabled_mods.contains(&descriptor.mod_id) {
+        return Ok(None);
+    }
+    if let Some(dep) = disabled_dependency(descriptor, &profile.enabled_mods) {
+        return Ok(Some(dep.clone()));
+    }
+    profile.enabled_mods.push(descriptor.mod_id.clone());
+    save_profiles(game_id, &profiles).await?;
+    Ok(None)
+}
+
+/// Drop an uninstalled mod from every profile
+pub(crate) async fn forget_mod(game_id: &str, mod_id: &str) -> Result<(), OxideError> {
+    let mut profiles = load_profiles(game_id).await?;
+    for profile in &mut profiles.profiles {
+        profile.enabled_mods.retain(|id| id != mod_id);
+    }
+    save_profiles(game_id, &profiles).await
+}
+
+/// Fail unless every dependency of `descriptor` is among `enabled`
+fn check_dependencies_enabled(
+    descriptor: &ModDescriptor,
+    enabled: &[String],
+) -> Result<(), OxideError> {
+    match disabled_dependency(descriptor, enabled) {
+        Some(dep) => Err(OxideError::General(format!(
+            "Mod {} requires {} to be enabled",
+            descriptor.mod_id, dep
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// First dependency of `descriptor` missing from `enabled`
+fn disabled_dependency<'a>(
+    descriptor: &'a ModDescriptor,
+    enabled: &[String],
+) -> Option<&'a String> {
+    descriptor
+        .dependencies
+        .iter()
+        .find(|dep| !enabled.contains(dep))
+}
+
+/// Enabled mods that depend on `mod_id`
+fn dependents<'a>(
+    mods: &'a [ModDescriptor],
+    enabled: &[String],
+    mod_id: &str,
+) -> Vec<&'a ModDescriptor> {
+    mods.iter()
+        .filter(|m| enabled.contains(&m.mod_id))
+        .filter(|m| m.dependencies.iter().any(|dep| dep == mod_id))
+        .collect()
+}
+
+fn validate_profile_name(profiles: &ModProfiles, name: &str) -> Result<(), OxideError> {
+    if name.trim().is_empty() {
+        return Err(OxideError::General(
+            "Profile name cannot be empty".to_string(),
+        ));
+    }
+    if profiles.profiles.iter().any(|p| p.name == name) {
+        return Err(OxideError::General(format!(
+            "Profile {} already exists",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn find_profile<'a>(profiles: &'a ModProfiles, name: &str) -> Result<&'a ModProfile, OxideError> {
+    profiles
+        .profiles
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| OxideError::NotFound(format!("Profile {} not found", name)))
+}
+
+fn find_profile_mut<'a>(
+    profiles: &'a mut ModProfiles,
+    name: &str,
+) -> Result<&'a mut ModProfile, OxideError> {
+    profiles
+        .profiles
+        .iter_mut()
+        .find(|p| p.name == name)
+        .ok_or_else(|| OxideError::NotFound(format!("Profile {} not found", name)))
+}
+
+/// Load the profiles of a game, creating a default profile with every installed mod enabled
+async fn load_profiles(game_id: &str) -> Result<ModProfiles, OxideError> {
+    let path = game_mods_dir(game_id)?.join(PROFILES_FILE);
+    if let Some(profiles) = persist::read_json(&path).await? {
+        return Ok(profiles);
+    }
+
+    let installed = read_installed_mods(game_id).await?;
+    Ok(ModProfiles {
+        active: DEFAULT_PROFILE.to_string(),
+        profiles: vec![ModProfile {
+            name: DEFAULT_PROFILE.to_string(),
+            enabled_mods: installed.into_iter().map(|m| m.mod_id).collect(),
+        }],
+    })
+}
+
+async fn save_profiles(game_id: &str, profiles: &ModProfiles) -> Result<(), OxideError> {
+    persist::write_json(&game_mods_dir(game_id)?.join(PROFILES_FILE), profiles).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_downloader::game_install_dir;
+    use crate::mod_engine::{install_mod, package};
+    use std::path::Path;
+
+    /// Install a mod shipping `<mod_id>.esp` from a temporary folder
+    async fn install(game_id: &str, mod_id: &str, dependencies: &[&str]) {
+        let source = tempfile::tempdir().unwrap();
+        let descriptor = serde_json::json!({
+            "mod_id": mod_id,
+            "game_id": game_id,
+            "dependencies": dependencies,
+            "description": "",
+            "version": "1.0.0",
+            "author": "tester",
+        });
+        std::fs::write(
+            source.path().join(package::DESCRIPTOR_FILE),
+            descriptor.to_string(),
+        )
+        .unwrap();
+        std::fs::write(source.path().join(format!("{}.esp", mod_id)), mod_id).unwrap();
+        install_mod(
+            game_id.to_string(),
+            source.path().to_string_lossy().to_string(),
+        )
+        .await
+        .unwrap();
+    }
+
+    fn write_export(path: &Path, export: &ModProfileExport) {
+        std::fs::write(path, serde_json::to_string(export).unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn switching_profiles_deploys_their_mods() {
+        let game_id = "profiles-switch";
+        let game_dir = game_install_dir(game_id).unwrap();
+        std::fs::create_dir_all(&game_dir).unwrap();
+        install(game_id, "lighting", &[]).await;
+        install(game_id, "weather", &[]).await;
+
+        create_mod_profile(game_id.to_string(), "Minimal".to_string(), None)
+            .await
+            .unwrap();
+        let profiles = switch_mod_profile(game_id.to_string(), "Minimal".to_string())
+            .await
+            .unwrap();
+        assert_eq!(profiles.active, "Minimal");
+        assert!(!game_dir.join("lighting.esp").exists());
+        assert!(!game_dir.join("weather.esp").exists());
+
+        set_mod_enabled(game_id.to_string(), "weather".to_string(), true)
+            .await
+            .unwrap();
+        switch_mod_profile(game_id.to_string(), DEFAULT_PROFILE.to_string())
+            .await
+            .unwrap();
+        assert!(game_dir.join("lighting.esp").is_file());
+        assert!(game_dir.join("weather.esp").is_file());
+
+        // The other profile kept its own selection
+        let profiles = list_mod_profiles(game_id.to_string()).await.unwrap();
+        let minimal = find_profile(&profiles, "Minimal").unwrap();
+        assert_eq!(minimal.enabled_mods, ["weather"]);
+
+        assert!(
+            delete_mod_profile(game_id.to_string(), DEFAULT_PROFILE.to_string())
+                .await
+                .is_err()
+        );
+        delete_mod_profile(game_id.to_string(), "Minimal".to_string())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn export_and_import_round_trip() {
+        let game_id = "profiles-round-trip";
+        let dir = tempfile::tempdir().unwrap();
+        install(game_id, "framework", &[]).await;
+        install(game_id, "addon", &["framework"]).await;
+
+        let file = dir.path().join("profile.json");
+        export_mod_profile(
+            game_id.to_string(),
+            DEFAULT_PROFILE.to_string(),
+            file.to_string_lossy().to_string(),
+        )
+        .await
+        .unwrap();
+
+        let mut export: ModProfileExport =
+            serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(export.game_id, game_id);
+        assert_eq!(export.mods.len(), 2);
+
+        // Pretend the exporting machine had another version and an extra mod
+        export.mods[0].version = "0.9.0".to_string();
+        export.mods.push(ExportedMod {
+            mod_id: "not-here".to_string(),
+            version: "1.0.0".to_string(),
+        });
+        write_export(&file, &export);
+
+        let report = import_mod_profile(game_id.to_string(), file.to_string_lossy().to_string())
+            .await
+            .unwrap();
+        // The name is taken by the exported profile itself
+        assert_eq!(report.profile.name, format!("{} (2)", DEFAULT_PROFILE));
+        assert_eq!(report.profile.enabled_mods.len(), 2);
+        assert_eq!(report.missing_mods.len(), 1);
+        assert_eq!(report.missing_mods[0].mod_id, "not-here");
+        assert_eq!(report.version_mismatches.len(), 1);
+
+        let profiles = list_mod_profiles(game_id.to_string()).await.unwrap();
+        assert_eq!(profiles.active, DEFAULT_PROFILE);
+        assert_eq!(profiles.profiles.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn import_rejects_other_games_and_missing_dependencies() {
+        let game_id = "profiles-import-checks";
+        let dir = tempfile::tempdir().unwrap();
+        install(game_id, "framework", &[]).await;
+        install(game_id, "addon", &["framework"]).await;
+
+        let file = dir.path().join("profile.json");
+        let mut export = ModProfileExport {
+            format_version: PROFILE_EXPORT_VERSION,
+            game_id: "another-game".to_string(),
+            name: "Shared".to_string(),
+            mods: Vec::new(),
+        };
+        write_export(&file, &export);
+        assert!(
+            import_mod_profile(game_id.to_string(), file.to_string_lossy().to_string())
+                .await
+                .is_err()
+        );
+
+        export.game_id = game_id.to_string();
+        export.mods.push(ExportedMod {
+            mod_id: "addon".to_string(),
+            version: "1.0.0".to_string(),
+        });
+        write_export(&file, &export);
+        assert!(
+            import_mod_profile(game_id.to_string(), file.to_string_lossy().to_string())
+                .await
+                .is_err()
+        );
+
+        let profiles = list_mod_profiles(game_id.to_string()).await.unwrap();
+        assert_eq!(profiles.profiles.len(), 1);
+    }
+}
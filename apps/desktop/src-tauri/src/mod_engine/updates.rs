@@ -0,0 +1,595 @@
+use super::{
+    conflicts, deploy, game_mods_dir, install_mod, load_order, mods_root, read_archive_descriptor,
+    read_installed_mods, sha256_hex, ModDescriptor,
+};
+use crate::signing::signature_path;
+use crate::{persist, store, OxideError, API_BASE_URL};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+
+const REPOSITORY_SETTINGS_FILE: &str = "repository.json";
+const DOWNLOADS_DIR: &str = ".downloads";
+/// Previous version of each upgraded mod, kept for rollback
+const ROLLBACK_DIR: &str = ".rollback";
+
+/// A downloadable version of a mod listed by a repository index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModRelease {
+    pub mod_id: String,
+    pub game_id: String,
+    pub version: String,
+    /// Absolute URL, or a path relative to the index location
+    pub download_url: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub changelog: Option<String>,
+    /// Detached publisher signature, resolved like `download_url`
+    #[serde(default)]
+    pub signature_url: Option<String>,
+}
+
+/// Repository index document, `index.json` at the repository root
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModIndex {
+    pub mods: Vec<ModRelease>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositorySettings {
+    /// `http(s)://` URL of the index, or a local path / `file://` URL
+    pub index_url: String,
+}
+
+impl Default for RepositorySettings {
+    fn default() -> Self {
+        RepositorySettings {
+            index_url: format!("{}/mods/index.json", API_BASE_URL),
+        }
+    }
+}
+
+/// An installed mod with a newer release available
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModUpdate {
+    pub mod_id: String,
+    pub installed_version: String,
+    pub latest: ModRelease,
+}
+
+enum IndexLocation {
+    Remote(String),
+    Local(PathBuf),
+}
+
+/// Client for a mod repository index served over HTTP or from a local directory
+pub struct ModRepositoryClient {
+    client: reqwest::Client,
+    location: IndexLocation,
+}
+
+impl ModRepositoryClient {
+    pub fn new(index_url: &str) -> Self {
+        let location = if index_url.starts_with("http://") || index_url.starts_with("https://") {
+            IndexLocation::Remote(index_url.to_string())
+        } else {
+            let path = index_url.strip_prefix("file://").unwrap_or(index_url);
+            IndexLocation::Local(PathBuf::from(path))
+        };
+
+        ModRepositoryClient {
+            client: reqwest::Client::new(),
+            location,
+        }
+    }
+
+    /// Client for the repository configured in `~/.Oxide/Mods/repository.json`
+    pub async fn from_settings() -> Result<Self, OxideError> {
+        let settings = load_repository_settings().await?;
+        Ok(Self::new(&settings.index_url))
+    }
+
+    pub async fn fetch_index(&self) -> Result<ModIndex, OxideError> {
+        match &self.location {
+            IndexLocation::Remote(url) => {
+                let response = self.client.get(url).send().await.map_err(|e| {
+                    OxideError::General(format!("Failed to fetch mod index: {}", e))
+                })?;
+                if !response.status().is_success() {
+                    return Err(OxideError::General(format!(
+                        "Mod index request failed with status {}",
+                        response.status()
+                    )));
+                }
+                response
+                    .json::<ModIndex>()
+                    .await
+                    .map_err(|e| OxideError::General(format!("Failed to parse mod index: {}", e)))
+            }
+            IndexLocation::Local(path) => {
+                let contents = fs::read_to_string(path).await?;
+                Ok(serde_json::from_str(&contents)?)
+            }
+        }
+    }
+
+    /// Newest release of every mod for a game
+    pub async fn latest_releases(&self, game_id: &str) -> Result<Vec<ModRelease>, OxideError> {
+        let index = self.fetch_index().await?;
+        let mut latest: Vec<ModRelease> = Vec::new();
+
+        for release in index.mods.into_iter().filter(|r| r.game_id == game_id) {
+            match latest.iter_mut().find(|r| r.mod_id == release.mod_id) {
+                Some(existing) => {
+                    if compare_versions(&release.version, &existing.version) == Ordering::Greater {
+                        *existing = release;
+                    }
+                }
+                None => latest.push(release),
+            }
+        }
+
+        Ok(latest)
+    }
+
+    /// Download a release archive to `destination` and verify its checksum
+    pub async fn download(
+        &self,
+        release: &ModRelease,
+        destination: &Path,
+    ) -> Result<(), OxideError> {
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        self.fetch(&release.download_url, destination, &release.mod_id)
+            .await?;
+
+        // install_mod picks the signature up from next to the archive
+        let signature = signature_path(destination);
+        match &release.signature_url {
+            Some(url) => self.fetch(url, &signature, &release.mod_id).await?,
+            None if signature.exists() => fs::remove_file(&signature).await?,
+            None => {}
+        }
+
+        let digest = sha256_hex(&fs::read(destination).await?);
+        if !digest.eq_ignore_ascii_case(&release.sha256) {
+            fs::remove_file(destination).await?;
+            return Err(OxideError::ModInstallationFailed(format!(
+                "Checksum mismatch for {} {}",
+                release.mod_id, release.version
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn fetch(&self, url: &str, destination: &Path, mod_id: &str) -> Result<(), OxideError> {
+        match self.resolve(url) {
+            IndexLocation::Remote(url) => {
+                let response = self.client.get(&url).send().await.map_err(|e| {
+                    OxideError::General(format!("Failed to download {}: {}", mod_id, e))
+                })?;
+                if !response.status().is_success() {
+                    return Err(OxideError::General(format!(
+                        "Download of {} failed with status {}",
+                        mod_id,
+                        response.status()
+                    )));
+                }
+
+                let mut out = File::create(destination).await?;
+                let mut stream = response.bytes_stream();
+                while let Some(chunk) = stream.next().await {
+                    let chunk = chunk.map_err(|e| {
+                        OxideError::General(format!("Failed to download {}: {}", mod_id, e))
+                    })?;
+                    out.write_all(&chunk).await?;
+                }
+                out.flush().await?;
+            }
+            IndexLocation::Local(path) => {
+                fs::copy(&path, destination).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve a download URL against the index location
+    fn resolve(&self, download_url: &str) -> IndexLocation {
+        if download_url.starts_with("http://") || download_url.starts_with("https://") {
+            return IndexLocation::Remote(download_url.to_string());
+        }
+        if let Some(path) = download_url.strip_prefix("file://") {
+            return IndexLocation::Local(PathBuf::from(path));
+        }
+
+        match &self.location {
+            IndexLocation::Remote(index_url) => {
+                let base = index_url
+                    .rsplit_once('/')
+                    .map(|(base, _)| base)
+                    .unwrap_or(index_url);
+                IndexLocation::Remote(format!("{}/{}", base, download_url.trim_start_matches('/')))
+            }
+            IndexLocation::Local(index_path) => {
+                let base = index_path.parent().unwrap_or_else(|| Path::new("."));
+                IndexLocation::Local(base.join(download_url))
+            }
+        }
+    }
+}
+
+/// Compare installed mods of a game against the repository
+pub async fn check_mod_updates(game_id: String) -> Result<Vec<ModUpdate>, OxideError> {
+    let client = ModRepositoryClient::from_settings().await?;
+    let releases = client.latest_releases(&game_id).await?;
+    let installed = read_installed_mods(&game_id).await?;
+
+    let updates = installed
+        .into_iter()
+        .filter_map(|descriptor| {
+            let latest = releases.iter().find(|r| r.mod_id == descriptor.mod_id)?;
+            (compare_versions(&latest.version, &descriptor.version) == Ordering::Greater).then(
+                || ModUpdate {
+                    mod_id: descriptor.mod_id,
+                    installed_version: descriptor.version,
+                    latest: latest.clone(),
+                },
+            )
+        })
+        .collect();
+
+    Ok(updates)
+}
+
+/// Download and install the newest release of a mod, keeping the current version for rollback
+pub async fn upgrade_mod(game_id: String, mod_id: String) -> Result<String, OxideError> {
+    let update = check_mod_updates(game_id.clone())
+        .await?
+        .into_iter()
+        .find(|u| u.mod_id == mod_id)
+        .ok_or_else(|| OxideError::NotFound(format!("No update available for mod {}", mod_id)))?;
+
+    let client = ModRepositoryClient::from_settings().await?;
+    let archive = game_mods_dir(&game_id)?
+        .join(DOWNLOADS_DIR)
+        .join(format!("{}-{}", mod_id, update.latest.version));
+    client.download(&update.latest, &archive).await?;
+
+    // Refuse archives that would install as a different mod
+    let descriptor = read_archive_descriptor(&archive)?;
+    if descriptor.mod_id != mod_id || descriptor.game_id != game_id {
+        remove_download(&archive).await;
+        return Err(OxideError::ModInstallationFailed(format!(
+            "Downloaded archive contains {} for {}, expected {} for {}",
+            descriptor.mod_id, descriptor.game_id, mod_id, game_id
+        )));
+    }
+
+    backup_installed_mod(&game_id, &mod_id).await?;
+
+    let result = match install_mod(game_id.clone(), archive.to_string_lossy().to_string()).await {
+        Ok(_) => load_order::ensure_dependency_order(&game_id).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        restore_backup(&game_id, &mod_id).await?;
+        remove_download(&archive).await;
+        return Err(OxideError::ModInstallationFailed(format!(
+            "Upgrade of {} failed and was rolled back: {}",
+            mod_id, e
+        )));
+    }
+    remove_download(&archive).await;
+
+    Ok(format!(
+        "Mod {} upgraded from {} to {}",
+        mod_id, update.installed_version, update.latest.version
+    ))
+}
+
+/// Delete a downloaded archive and its signature
+///
+/// Best effort: a leftover download is replaced by the next one, so failing to
+/// delete it must not fail the upgrade or skip a rollback.
+async fn remove_download(archive: &Path) {
+    for path in [archive.to_path_buf(), signature_path(archive)] {
+        match fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                eprintln!("Failed to remove download {}: {}", path.display(), e);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Restore the version of a mod that was installed before its last upgrade
+pub async fn rollback_mod(game_id: String, mod_id: String) -> Result<String, OxideError> {
+    let backup_dir = rollback_dir(&game_id, &mod_id)?;
+    if !backup_dir.exists() {
+        return Err(OxideError::NotFound(format!(
+            "No previous version of mod {} to roll back to",
+            mod_id
+        )));
+    }
+
+    restore_backup(&game_id, &mod_id).await?;
+    load_order::ensure_dependency_order(&game_id).await?;
+
+    let version = read_installed_mods(&game_id)
+        .await?
+        .into_iter()
+        .find(|m| m.mod_id == mod_id)
+        .map(|m| m.version)
+        .unwrap_or_default();
+    Ok(format!("Mod {} rolled back to {}", mod_id, version))
+}
+
+/// Get the configured mod repository
+pub async fn get_mod_repository() -> Result<RepositorySettings, OxideError> {
+    load_repository_settings().await
+}
+
+/// Point update checks at another repository index
+pub async fn set_mod_repository(index_url: String) -> Result<RepositorySettings, OxideError> {
+    let settings = RepositorySettings { index_url };
+    persist::write_json(&repository_settings_path()?, &settings).await?;
+    Ok(settings)
+}
+
+/// Order two version strings, treating missing components as zero
+///
+/// Versions are parsed as semver when possible so pre-releases sort before the
+/// release; anything else is compared component by component.
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_version(a), parse_version(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => {
+            let parts = |v: &str| -> Vec<u64> {
+                v.split(|c: char| !c.is_ascii_digit())
+                    .filter(|p| !p.is_empty())
+                    .map(|p| p.parse().unwrap_or(0))
+                    .collect()
+            };
+            parts(a).cmp(&parts(b))
+        }
+    }
+}
+
+/// Parse a mod version as semver, accepting a leading `v` and missing components
+pub(crate) fn parse_version(version: &str) -> Option<semver::Version> {
+    let version = version.trim().trim_start_matches('v');
+    semver::Version::parse(version).ok().or_else(|| {
+        let padded = match version.matches('.').count() {
+            0 => format!("{}.0.0", version),
+            1 => format!("{}.0", version),
+            _ => return None,
+        };
+        semver::Version::parse(&padded).ok()
+    })
+}
+
+fn rollback_dir(game_id: &str, mod_id: &str) -> Result<PathBuf, OxideError> {
+    Ok(game_mods_dir(game_id)?.join(ROLLBACK_DIR).join(mod_id))
+}
+
+/// Move the installed copy of a mod aside, replacing any older backup
+async fn backup_installed_mod(game_id: &str, mod_id: &str) -> Result<(), OxideError> {
+    let backup_dir = rollback_dir(game_id, mod_id)?;
+    if backup_dir.exists() {
+        fs::remove_dir_all(&backup_dir).await?;
+    }
+    fs::create_dir_all(&backup_dir).await?;
+
+    let descriptor = store::get_mod(game_id, mod_id)?
+        .ok_or_else(|| OxideError::NotFound(format!("Mod {} is not installed", mod_id)))?;
+    persist::write_json(&backup_dir.join("metadata.json"), &descriptor).await?;
+    let manifest = conflicts::manifest_path(game_id, mod_id)?;
+    if manifest.exists() {
+        fs::copy(&manifest, backup_dir.join("files.json")).await?;
+    }
+    let store_dir = deploy::mod_store_dir(game_id, mod_id)?;
+    if store_dir.exists() {
+        fs::rename(&store_dir, backup_dir.join("store")).await?;
+    }
+
+    Ok(())
+}
+
+/// Put a backed up version of a mod back in place and redeploy it
+async fn restore_backup(game_id: &str, mod_id: &str) -> Result<(), OxideError> {
+    let backup_dir = rollback_dir(game_id, mod_id)?;
+
+    let store_dir = deploy::mod_store_dir(game_id, mod_id)?;
+    if store_dir.exists() {
+        fs::remove_dir_all(&store_dir).await?;
+    }
+    if backup_dir.join("store").exists() {
+        fs::rename(backup_dir.join("store"), &store_dir).await?;
+    }
+
+    let descriptor = persist::read_json::<ModDescriptor>(&backup_dir.join("metadata.json"))
+        .await?
+        .ok_or_else(|| OxideError::NotFound(format!("Backup of mod {}", mod_id)))?;
+    store::save_mod(game_id, &descriptor)?;
+    let manifest = conflicts::manifest_path(game_id, mod_id)?;
+    if backup_dir.join("files.json").exists() {
+        fs::copy(backup_dir.join("files.json"), &manifest).await?;
+    } else {
+        persist::remove_json(&manifest).await?;
+    }
+
+    fs::remove_dir_all(&backup_dir).await?;
+    deploy::redeploy_active_profile(game_id).await?;
+    Ok(())
+}
+
+fn repository_settings_path() -> Result<PathBuf, OxideError> {
+    Ok(mods_root()?.join(REPOSITORY_SETTINGS_FILE))
+}
+
+async fn load_repository_settings() -> Result<RepositorySettings, OxideError> {
+    Ok(persist::read_json(&repository_settings_path()?)
+        .await?
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mod_engine::package;
+    use std::path::Path;
+    use tokio::sync::Mutex;
+
+    /// The repository setting is global, tests using it take turns
+    static REPOSITORY: Mutex<()> = Mutex::const_new(());
+
+    /// Pack a mod shipping `data.txt` with `contents` and list it in `index`
+    fn publish(
+        repo: &Path,
+        index: &mut ModIndex,
+        game_id: &str,
+        version: &str,
+        dependencies: &[&str],
+        contents: &str,
+    ) {
+        let source = repo.join(format!("src-{}", version));
+        std::fs::create_dir_all(&source).unwrap();
+        let descriptor = ModDescriptor {
+            mod_id: "texture-pack".to_string(),
+            game_id: game_id.to_string(),
+            dependencies: dependencies.iter().map(|s| s.to_string()).collect(),
+            description: String::new(),
+            version: version.to_string(),
+            author: "tester".to_string(),
+            load_after: Vec::new(),
+            load_before: Vec::new(),
+        };
+        std::fs::write(
+            source.join(package::DESCRIPTOR_FILE),
+            serde_json::to_string(&descriptor).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(source.join("data.txt"), contents).unwrap();
+
+        let name = package::archive_name(&descriptor);
+        let packed = package::pack(&source, &repo.join(&name)).unwrap();
+        index.mods.push(ModRelease {
+            mod_id: descriptor.mod_id,
+            game_id: game_id.to_string(),
+            version: version.to_string(),
+            download_url: name,
+            sha256: packed.sha256,
+            changelog: None,
+            signature_url: None,
+        });
+    }
+
+    /// Install 1.0.0 of the mod, then serve an index listing `index`
+    async fn install_and_serve(repo: &Path, game_id: &str, mut index: ModIndex) {
+        let mut installed = ModIndex::default();
+        publish(repo, &mut installed, game_id, "1.0.0", &[], "v1");
+        install_mod(
+            game_id.to_string(),
+            repo.join("texture-pack-1.0.0.zip")
+                .to_string_lossy()
+                .to_string(),
+        )
+        .await
+        .unwrap();
+
+        index.mods.extend(installed.mods);
+        let index_path = repo.join("index.json");
+        std::fs::write(&index_path, serde_json::to_string(&index).unwrap()).unwrap();
+        set_mod_repository(index_path.to_string_lossy().to_string())
+            .await
+            .unwrap();
+    }
+
+    fn deployed_store_file(game_id: &str) -> String {
+        let store_dir = deploy::mod_store_dir(game_id, "texture-pack").unwrap();
+        std::fs::read_to_string(store_dir.join("data.txt")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn finds_newer_release_in_local_index() {
+        let _repository = REPOSITORY.lock().await;
+        let repo = tempfile::tempdir().unwrap();
+        let game_id = "updates-found";
+        let mut index = ModIndex::default();
+        publish(repo.path(), &mut index, game_id, "1.2.0", &[], "v2");
+        publish(repo.path(), &mut index, "other-game", "9.0.0", &[], "v9");
+        install_and_serve(repo.path(), game_id, index).await;
+
+        let updates = check_mod_updates(game_id.to_string()).await.unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].installed_version, "1.0.0");
+        assert_eq!(updates[0].latest.version, "1.2.0");
+    }
+
+    #[tokio::test]
+    async fn upgrade_installs_latest_and_keeps_rollback() {
+        let _repository = REPOSITORY.lock().await;
+        let repo = tempfile::tempdir().unwrap();
+        let game_id = "updates-upgrade";
+        let mut index = ModIndex::default();
+        publish(repo.path(), &mut index, game_id, "2.0.0", &[], "v2");
+        install_and_serve(repo.path(), game_id, index).await;
+
+        upgrade_mod(game_id.to_string(), "texture-pack".to_string())
+            .await
+            .unwrap();
+        let installed = store::get_mod(game_id, "texture-pack").unwrap().unwrap();
+        assert_eq!(installed.version, "2.0.0");
+        assert_eq!(deployed_store_file(game_id), "v2");
+        assert!(check_mod_updates(game_id.to_string())
+            .await
+            .unwrap()
+            .is_empty());
+
+        rollback_mod(game_id.to_string(), "texture-pack".to_string())
+            .await
+            .unwrap();
+        let installed = store::get_mod(game_id, "texture-pack").unwrap().unwrap();
+        assert_eq!(installed.version, "1.0.0");
+        assert_eq!(deployed_store_file(game_id), "v1");
+    }
+
+    #[tokio::test]
+    async fn failed_install_rolls_back() {
+        let _repository = REPOSITORY.lock().await;
+        let repo = tempfile::tempdir().unwrap();
+        let game_id = "updates-rollback";
+        let mut index = ModIndex::default();
+        // The new version needs a mod that is not installed, so installing it fails
+        publish(
+            repo.path(),
+            &mut index,
+            game_id,
+            "2.0.0",
+            &["missing-lib"],
+            "v2",
+        );
+        install_and_serve(repo.path(), game_id, index).await;
+
+        let error = upgrade_mod(game_id.to_string(), "texture-pack".to_string())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("rolled back"), "{}", error);
+
+        let installed = store::get_mod(game_id, "texture-pack").unwrap().unwrap();
+        assert_eq!(installed.version, "1.0.0");
+        assert_eq!(deployed_store_file(game_id), "v1");
+        assert!(!rollback_dir(game_id, "texture-pack").unwrap().exists());
+        assert!(!game_mods_dir(game_id)
+            .unwrap()
+            .join(DOWNLOADS_DIR)
+            .join("texture-pack-2.0.0")
+            .exists());
+    }
+}
@@ -0,0 +1,168 @@
+//! Catalog records shared by the commands, the search index and sync
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Game {
+    pub id: String,
+    pub title: String,
+    pub slug: String,
+    pub description: Option<String>,
+    pub developer: Option<String>,
+    pub publisher: Option<String>,
+    pub media: GameMedia,
+    pub pricing: GamePricing,
+    pub details: GameDetails,
+    pub stats: GameStats,
+    pub features: GameFeatures,
+    #[serde(rename = "discountPercent")]
+    pub discount_percent: Option<f64>,
+    #[serde(rename = "isHot")]
+    pub is_hot: Option<bool>,
+    #[serde(rename = "isNew")]
+    pub is_new: Option<bool>,
+    #[serde(rename = "isOnSale")]
+    pub is_on_sale: Option<bool>,
+    #[serde(rename = "isAiRecommended")]
+    pub is_ai_recommended: Option<bool>,
+    #[serde(rename = "aiScore")]
+    pub ai_score: Option<f64>,
+    #[serde(rename = "matchReason")]
+    pub match_reason: Option<String>,
+    #[serde(rename = "isInstalled")]
+    pub is_installed: Option<bool>,
+    #[serde(rename = "isInLibrary")]
+    pub is_in_library: Option<bool>,
+    #[serde(rename = "isWishlisted")]
+    pub is_wishlisted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameMedia {
+    #[serde(rename = "coverImage")]
+    pub cover_image: String,
+    pub screenshots: Vec<String>,
+    #[serde(rename = "iconUrl")]
+    pub icon_url: String,
+    pub title: String,
+    #[serde(rename = "trailerUrl")]
+    pub trailer_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GamePricing {
+    #[serde(rename = "basePrice")]
+    pub base_price: f64,
+    #[serde(rename = "currentPrice")]
+    pub current_price: f64,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameDetails {
+    pub platforms: Vec<String>,
+    pub genres: Vec<String>,
+    pub tags: Vec<String>,
+    #[serde(rename = "releaseDate")]
+    pub release_date: Option<String>,
+    #[serde(rename = "lastUpdated")]
+    pub last_updated: Option<String>,
+    pub version: Option<String>,
+    #[serde(rename = "downloadSize")]
+    pub download_size: Option<String>,
+    #[serde(rename = "systemRequirements")]
+    pub system_requirements: Option<SystemRequirements>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemRequirements {
+    pub minimum: HashMap<String, String>,
+    pub recommended: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameStats {
+    pub rating: f64,
+    #[serde(rename = "reviewCount")]
+    pub review_count: u32,
+    #[serde(rename = "downloadCount")]
+    pub download_count: u32,
+    #[serde(rename = "wishlistCount")]
+    pub wishlist_count: u32,
+    #[serde(rename = "playTime")]
+    pub play_time: Option<PlayTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayTime {
+    pub average: f64,
+    pub median: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameFeatures {
+    pub controller: bool,
+    #[serde(rename = "cloudPlay")]
+    pub cloud_play: bool,
+    pub offline: bool,
+    #[serde(rename = "multiPlayer")]
+    pub multi_player: bool,
+    #[serde(rename = "singlePlayer")]
+    pub single_player: bool,
+    pub achievements: bool,
+    pub workshop: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GamesData {
+    pub data: Vec<Game>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Developer {
+    pub id: String,
+    pub name: String,
+    pub bio: Option<String>,
+    pub location: Option<String>,
+    #[serde(rename = "websiteUrl")]
+    pub website_url: Option<String>,
+    pub avatar: Option<String>,
+    #[serde(rename = "publishedGames", default)]
+    pub published_games: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DevelopersData {
+    pub data: Vec<Developer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Achievement {
+    pub id: String,
+    #[serde(rename = "gameId")]
+    pub game_id: String,
+    pub title: String,
+    pub description: String,
+    pub category: Option<String>,
+    pub difficulty: Option<String>,
+    pub points: Option<u32>,
+    #[serde(rename = "iconUrl")]
+    pub icon_url: Option<String>,
+    #[serde(rename = "isHidden", default)]
+    pub is_hidden: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AchievementsData {
+    pub data: Vec<Achievement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Friend {
+    pub id: String,
+    pub username: String,
+    pub status: Option<String>,
+    /// Title of the game the friend is playing
+    pub playing: Option<String>,
+}
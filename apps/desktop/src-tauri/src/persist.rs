@@ -0,0 +1,316 @@
+//! Crash-safe JSON state files
+//!
+//! Files are written to a temporary file next to them, flushed to disk and
+//! renamed over the old version, so a crash leaves either the old or the new
+//! contents and never half a file. The version replaced is kept as
+//! `<name>.bak`, and reading a file that no longer parses falls back to it:
+//! the damaged file is set aside as `<name>.corrupt` and the backup restored.
+
+use crate::OxideError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// `path` with `suffix` appended to its file name
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, ".bak")
+}
+
+/// Write `data` to `path`, all or nothing, backing up what it replaces
+///
+/// A `private` file and its backup can only be read by the current user.
+fn write_atomic(path: &Path, data: &[u8], private: bool) -> Result<(), OxideError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Unique per writer, so concurrent writes never share a temporary file
+    let temp = sibling(
+        path,
+        &format!(".{}-{:08x}.tmp", std::process::id(), rand::random::<u32>()),
+    );
+    let written = (|| {
+        let mut file = create(&temp, private)?;
+        file.write_all(data)?;
+        file.sync_all()
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(OxideError::FileOperationError(format!(
+            "Failed to write {}: {}",
+            path.display(),
+            e
+        )));
+    }
+
+    // A damaged file is not worth keeping over an intact backup
+    if path.exists() && parses(path) {
+        let backup = backup_path(path);
+        fs::copy(path, sibling(&backup, ".tmp"))?;
+        if private {
+            set_private(&sibling(&backup, ".tmp"))?;
+        }
+        fs::rename(sibling(&backup, ".tmp"), &backup)?;
+    }
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        OxideError::FileOperationError(format!("Failed to replace {}: {}", path.display(), e))
+    })?;
+    sync_parent(path);
+    Ok(())
+}
+
+/// Serialize `value` as pretty JSON and write it with `write_atomic`
+pub fn write_json_blocking<T: Serialize + ?Sized>(
+    path: &Path,
+    value: &T,
+) -> Result<(), OxideError> {
+    write_atomic(path, &serde_json::to_vec_pretty(value)?, false)
+}
+
+/// Read JSON written by `write_json`, `None` when the file does not exist
+///
+/// A file that is not valid JSON, torn by a crash before it was written this
+/// way or damaged on disk, is replaced by its backup when the backup reads fine.
+pub fn read_json_blocking<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, OxideError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let error = match fs::read(path).map(|data| serde_json::from_slice(&data)) {
+        Ok(Ok(value)) => return Ok(Some(value)),
+        // Intact JSON of another shape is not damage, the backup would not help
+        Ok(Err(e)) if parses(path) => return Err(e.into()),
+        Ok(Err(e)) => OxideError::Json(e),
+        Err(e) => OxideError::Io(e),
+    };
+
+    let backup = backup_path(path);
+    let restored = fs::read(&backup).ok().and_then(|data| {
+        serde_json::from_slice(&data)
+            .ok()
+            .map(|value| (data, value))
+    });
+    let Some((data, value)) = restored else {
+        return Err(OxideError::FileOperationError(format!(
+            "{} is damaged and has no usable backup: {}",
+            path.display(),
+            error
+        )));
+    };
+    eprintln!(
+        "{} is damaged ({}), restored the previous version",
+        path.display(),
+        error
+    );
+    fs::rename(path, sibling(path, ".corrupt"))?;
+    write_atomic(path, &data, is_private(&backup))?;
+    Ok(Some(value))
+}
+
+/// Async `write_json_blocking`, the disk work runs off the async runtime
+pub async fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), OxideError> {
+    let data = serde_json::to_vec_pretty(value)?;
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || write_atomic(&path, &data, false))
+        .await
+        .map_err(|e| OxideError::General(format!("Write task failed: {}", e)))?
+}
+
+/// `write_json` for files holding secrets, only the current user can read them
+pub async fn write_json_private<T: Serialize + ?Sized>(
+    path: &Path,
+    value: &T,
+) -> Result<(), OxideError> {
+    let data = serde_json::to_vec_pretty(value)?;
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || write_atomic(&path, &data, true))
+        .await
+        .map_err(|e| OxideError::General(format!("Write task failed: {}", e)))?
+}
+
+/// Async `read_json_blocking`, the disk work runs off the async runtime
+pub async fn read_json<T: DeserializeOwned + Send + 'static>(
+    path: &Path,
+) -> Result<Option<T>, OxideError> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || read_json_blocking(&path))
+        .await
+        .map_err(|e| OxideError::General(format!("Read task failed: {}", e)))?
+}
+
+/// Delete a file written by `write_json` along with its backup
+pub async fn remove_json(path: &Path) -> Result<(), OxideError> {
+    for file in [path.to_path_buf(), backup_path(path)] {
+        match tokio::fs::remove_file(&file).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parses(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|data| serde_json::from_slice::<serde_json::Value>(&data).is_ok())
+}
+
+/// Create a file to write, readable by the current user only when `private`
+fn create(path: &Path, private: bool) -> std::io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+fn set_private(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Whether only the current user can read a file
+fn is_private(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o077 == 0)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Flush the rename to disk, which on Unix takes syncing the directory
+fn sync_parent(path: &Path) {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|dir| dir.sync_all());
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct State {
+        version: u32,
+    }
+
+    #[tokio::test]
+    async fn keeps_a_backup_of_the_previous_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/state.json");
+        assert_eq!(read_json::<State>(&path).await.unwrap(), None);
+
+        write_json(&path, &State { version: 1 }).await.unwrap();
+        write_json(&path, &State { version: 2 }).await.unwrap();
+        assert_eq!(
+            read_json::<State>(&path).await.unwrap(),
+            Some(State { version: 2 })
+        );
+        assert_eq!(
+            read_json_blocking::<State>(&backup_path(&path)).unwrap(),
+            Some(State { version: 1 })
+        );
+
+        // No temporary files are left behind
+        let mut files: Vec<String> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["state.json", "state.json.bak"]);
+
+        remove_json(&path).await.unwrap();
+        assert!(!path.exists() && !backup_path(&path).exists());
+        remove_json(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn recovers_a_damaged_file_from_its_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        write_json(&path, &State { version: 1 }).await.unwrap();
+        write_json(&path, &State { version: 2 }).await.unwrap();
+
+        // Torn by a crash halfway through a write
+        fs::write(&path, br#"{"vers"#).unwrap();
+        assert_eq!(
+            read_json::<State>(&path).await.unwrap(),
+            Some(State { version: 1 })
+        );
+        assert_eq!(fs::read(sibling(&path, ".corrupt")).unwrap(), br#"{"vers"#);
+        assert_eq!(
+            read_json_blocking::<State>(&path).unwrap(),
+            Some(State { version: 1 })
+        );
+
+        // The damaged file is not kept as the backup of the next write
+        fs::write(&path, b"\0\0\0").unwrap();
+        write_json(&path, &State { version: 3 }).await.unwrap();
+        assert_eq!(
+            read_json_blocking::<State>(&backup_path(&path)).unwrap(),
+            Some(State { version: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_damage_it_cannot_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        // No backup to fall back to
+        fs::write(&path, b"not json").unwrap();
+        assert!(read_json::<State>(&path).await.is_err());
+
+        // Intact JSON of another shape is an error, not damage
+        write_json(&path, &State { version: 1 }).await.unwrap();
+        write_json(&path, &"a string").await.unwrap();
+        assert!(read_json::<State>(&path).await.is_err());
+        assert_eq!(
+            read_json::<String>(&path).await.unwrap().as_deref(),
+            Some("a string")
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn private_files_stay_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backend.json");
+        write_json_private(&path, &"first").await.unwrap();
+        write_json_private(&path, &"second").await.unwrap();
+        for file in [path.clone(), backup_path(&path)] {
+            let mode = fs::metadata(&file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", file.display());
+        }
+        assert_eq!(
+            read_json::<String>(&path).await.unwrap().as_deref(),
+            Some("second")
+        );
+    }
+}
@@ -1,183 +1,1606 @@
+use crate::models::Game;
 use crate::OxideError;
-use crate::{
-    models::{Game, GameIndex},
-    API_BASE_URL,
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use tantivy::collector::{Count, DocSetCollector, FacetCollector, FacetCounts, TopDocs};
+use tantivy::fastfield::FastValue;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, FuzzyTermQuery, Occur, Query, RangeQuery,
+    TermQuery,
 };
-use serde_json;
-use std::path::PathBuf;
-use std::sync::OnceLock;
-use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
+use tantivy::tokenizer::Language;
 use tantivy::{
-    schema::*, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument,
+    schema::*, DateTime, DocAddress, Index, IndexReader, IndexWriter, Order, ReloadPolicy,
+    Searcher, TantivyDocument,
 };
 
+pub mod analysis;
+pub mod analytics;
+pub mod entities;
+pub mod suggest;
+
+use analysis::{SearchSettings, Synonyms};
+use entities::{Entity, EntityGroup, EntityHit, EntityKind};
+use suggest::{Suggester, Suggestion};
+
+/// Memory budget of the index writer, shared by all indexing threads
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// Relevance boosts, a title match outranks a tag match outranks a description match
+const TITLE_BOOST: f32 = 3.0;
+const TAGS_BOOST: f32 = 2.0;
+const GENRES_BOOST: f32 = 1.5;
+const DEVELOPER_BOOST: f32 = 1.5;
+const DESCRIPTION_BOOST: f32 = 1.0;
+/// Typo and prefix matches on titles score below exact title matches
+const FUZZY_TITLE_BOOST: f32 = 1.0;
+const PREFIX_TITLE_BOOST: f32 = 2.0;
+
+/// Roots of the facet hierarchy, a game has `/genre/<genre>`, `/platform/<platform>`
+/// and `/feature/<feature>` facets
+const GENRE_FACET: &str = "genre";
+const PLATFORM_FACET: &str = "platform";
+const FEATURE_FACET: &str = "feature";
+
+/// Fast field names, used by range filters and sorting
+const PRICE_FIELD: &str = "price";
+const RATING_FIELD: &str = "rating";
+const RELEASE_DATE_FIELD: &str = "release_date";
+const DOWNLOAD_COUNT_FIELD: &str = "download_count";
+const AI_SCORE_FIELD: &str = "ai_score";
+
+/// Structured filters applied on top of the text query
+///
+/// Genres and platforms match any of the listed values, features must all be
+/// present. Dates are `YYYY-MM-DD` and bounds are inclusive.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchFilters {
+    pub genres: Vec<String>,
+    pub platforms: Vec<String>,
+    /// Feature flags as named in the catalog, e.g. `controller` or `cloudPlay`
+    pub features: Vec<String>,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    pub min_rating: Option<f64>,
+    pub released_after: Option<String>,
+    pub released_before: Option<String>,
+}
+
+impl SearchFilters {
+    /// Whether no filter is set
+    pub fn is_empty(&self) -> bool {
+        self.genres.is_empty()
+            && self.platforms.is_empty()
+            && self.features.is_empty()
+            && self.min_price.is_none()
+            && self.max_price.is_none()
+            && self.min_rating.is_none()
+            && self.released_after.is_none()
+            && self.released_before.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchSort {
+    /// Best matches first, most downloaded first for an empty query
+    #[default]
+    Relevance,
+    Popular,
+    Newest,
+    Rating,
+    PriceLow,
+    PriceHigh,
+    AiScore,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchRequest {
+    pub query: String,
+    pub filters: SearchFilters,
+    pub sort: SearchSort,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// A catalog game matching a search, in the requested order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub game_id: String,
+    /// Relevance score, only set when sorted by relevance
+    pub score: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Number of matching games per facet value, most common first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchFacets {
+    pub genres: Vec<FacetCount>,
+    pub platforms: Vec<FacetCount>,
+    pub features: Vec<FacetCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    /// Number of matching games, not just the returned page
+    pub total: usize,
+    /// Facet counts over every matching game
+    pub facets: SearchFacets,
+}
+
+/// Catalog version of each kind of record, stored as the commit payload
+type CatalogVersions = BTreeMap<EntityKind, String>;
+
+/// Documents of one kind keyed by id, with their content hash
+type Documents = BTreeMap<String, (String, TantivyDocument)>;
+
+/// What an index update changed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexUpdate {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// Catalog version stamped on the index for the updated kind
+    pub catalog_version: String,
+}
+
 /// Initialize and manage the search index for games
+///
+/// Developers, mods, achievements and friends share the index for the unified
+/// search box. Every document has a `kind`, game searches only see games.
 pub struct GameSearchIndex {
     index: Index,
     reader: IndexReader,
-    schema: Schema,
+    writer: Mutex<IndexWriter>,
+    suggester: RwLock<Suggester>,
+    /// Version of the catalog source the games and suggestions were built from
+    source_version: Mutex<Option<String>>,
+    /// Stemming language of the text fields, `None` for no stemming
+    language: Option<Language>,
+    synonyms: RwLock<Synonyms>,
+    /// `<kind>:<id>`, unique across the index
+    key_field: Field,
+    kind_field: Field,
+    id_field: Field,
+    /// Game a record belongs to, a game's own id for games
+    game_id_field: Field,
+    /// Hash of a record's indexed fields, to skip unchanged records on update
+    content_hash_field: Field,
     title_field: Field,
     description_field: Field,
     tags_field: Field,
+    genres_field: Field,
+    developer_field: Field,
+    facets_field: Field,
+    price_field: Field,
+    rating_field: Field,
+    release_date_field: Field,
+    download_count_field: Field,
+    ai_score_field: Field,
 }
 
 impl GameSearchIndex {
-    pub fn new() -> Result<Self, OxideError> {
-        let mut schema_builder = Schema::builder();
+    /// Open the index analyzed in `language`
+    pub fn new(language: Option<Language>, synonyms: &[Vec<String>]) -> Result<Self, OxideError> {
+        let index_root = crate::oxide_dir()?.join("index");
+        // Before indexes were kept per language the index lived in the root itself
+        if index_root.join("meta.json").exists() {
+            std::fs::remove_dir_all(&index_root)?;
+        }
+        let index_dir = index_root.join(analysis::language_code(language));
+        Self::open(&index_dir, language, synonyms)
+    }
 
-        let title_field = schema_builder.add_text_field("title", TEXT | STORED);
-        let description_field = schema_builder.add_text_field("description", TEXT | STORED);
-        let tags_field = schema_builder.add_text_field("tags", TEXT | STORED);
+    /// Open the index kept in `index_dir`
+    fn open(
+        index_dir: &Path,
+        language: Option<Language>,
+        synonyms: &[Vec<String>],
+    ) -> Result<Self, OxideError> {
+        let mut schema_builder = Schema::builder();
 
-        let schema = schema_builder.build();
+        let tokenizer = analysis::tokenizer_name(language);
+        let text = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(&tokenizer)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
 
-        // Create index directory
-        let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-            OxideError::InitializationError("Could not determine home directory".to_string())
-        })?;
-        let index_dir = home_dir.join(".Oxide").join("index");
+        let key_field = schema_builder.add_text_field("key", STRING);
+        let kind_field = schema_builder.add_text_field("kind", STRING | STORED);
+        let id_field = schema_builder.add_text_field("id", STRING | STORED);
+        let game_id_field = schema_builder.add_text_field("game_id", STRING | STORED);
+        let content_hash_field = schema_builder.add_text_field("content_hash", STRING | STORED);
+        let title_field = schema_builder.add_text_field("title", text.clone() | STORED);
+        let description_field = schema_builder.add_text_field("description", text.clone());
+        let tags_field = schema_builder.add_text_field("tags", text.clone());
+        let genres_field = schema_builder.add_text_field("genres", text.clone());
+        let developer_field = schema_builder.add_text_field("developer", text);
+        let facets_field = schema_builder.add_facet_field("facets", FacetOptions::default());
+        let price_field = schema_builder.add_f64_field(PRICE_FIELD, FAST | INDEXED);
+        let rating_field = schema_builder.add_f64_field(RATING_FIELD, FAST | INDEXED);
+        let release_date_field = schema_builder.add_date_field(RELEASE_DATE_FIELD, FAST | INDEXED);
+        let download_count_field = schema_builder.add_u64_field(DOWNLOAD_COUNT_FIELD, FAST);
+        let ai_score_field = schema_builder.add_f64_field(AI_SCORE_FIELD, FAST);
 
-        if !index_dir.exists() {
-            std::fs::create_dir_all(&index_dir).map_err(|e| {
-                OxideError::InitializationError(format!("Failed to create index directory: {}", e))
-            })?;
-        }
-
-        let index = Index::open_or_create(
-            tantivy::directory::MmapDirectory::open(&index_dir).map_err(|e| {
-                OxideError::InitializationError(format!("Failed to open index directory: {}", e))
-            })?,
-            schema.clone(),
-        )
-        .map_err(|e| OxideError::InitializationError(format!("Failed to create index: {}", e)))?;
+        let schema = schema_builder.build();
+        let index = open_index(index_dir, &schema)?;
+        // Analyzers are not stored in the index, register ours on every open
+        let mut analyzer = analysis::analyzer(language);
+        index.tokenizers().register(&tokenizer, analyzer.clone());
+        let synonyms = Synonyms::new(synonyms, &mut analyzer);
 
         let reader = index
             .reader_builder()
             .reload_policy(ReloadPolicy::OnCommitWithDelay)
             .try_into()?;
 
+        // Tantivy allows a single writer per index, it lives as long as the index
+        let writer = index.writer(WRITER_MEMORY)?;
+
         Ok(GameSearchIndex {
             index,
             reader,
-            schema,
+            writer: Mutex::new(writer),
+            suggester: RwLock::new(Suggester::default()),
+            source_version: Mutex::new(None),
+            language,
+            synonyms: RwLock::new(synonyms),
+            key_field,
+            kind_field,
+            id_field,
+            game_id_field,
+            content_hash_field,
             title_field,
             description_field,
             tags_field,
+            genres_field,
+            developer_field,
+            facets_field,
+            price_field,
+            rating_field,
+            release_date_field,
+            download_count_field,
+            ai_score_field,
         })
     }
 
-    pub fn add_game(
+    /// Version of the catalog the index was last updated from
+    pub fn catalog_version(&self) -> Result<Option<String>, OxideError> {
+        Ok(self.catalog_versions()?.remove(&EntityKind::Game))
+    }
+
+    fn catalog_versions(&self) -> Result<CatalogVersions, OxideError> {
+        // An index stamped before versions were kept per kind is simply re-diffed
+        Ok(self
+            .index
+            .load_metas()?
+            .payload
+            .and_then(|payload| serde_json::from_str(&payload).ok())
+            .unwrap_or_default())
+    }
+
+    /// Bring the index in line with the catalog
+    ///
+    /// Changed and new games are upserted, games no longer in the catalog are
+    /// deleted. Nothing is written when the catalog version is unchanged.
+    pub fn update_catalog(&self, games: &[Game]) -> Result<IndexUpdate, OxideError> {
+        self.refresh_suggestions(games)?;
+
+        let documents = games
+            .iter()
+            .map(|game| (game.id.clone(), self.with_hash(self.game_document(game))))
+            .collect();
+        self.update_kind(EntityKind::Game, documents)
+    }
+
+    /// Bring the records of one kind in line with `entities`, like `update_catalog`
+    pub fn update_entities(
         &self,
-        _game_id: &str,
-        title: &str,
-        description: &str,
-        tags: &[String],
+        kind: EntityKind,
+        entities: &[Entity],
+    ) -> Result<IndexUpdate, OxideError> {
+        let documents = entities
+            .iter()
+            .filter(|entity| entity.kind == kind)
+            .map(|entity| {
+                (
+                    entity.id.clone(),
+                    self.with_hash(self.entity_document(entity)),
+                )
+            })
+            .collect();
+        self.update_kind(kind, documents)
+    }
+
+    fn update_kind(
+        &self,
+        kind: EntityKind,
+        documents: Documents,
+    ) -> Result<IndexUpdate, OxideError> {
+        let catalog_version = catalog_version(&documents);
+        let mut versions = self.catalog_versions()?;
+        if versions.get(&kind) == Some(&catalog_version) {
+            return Ok(IndexUpdate {
+                catalog_version,
+                ..IndexUpdate::default()
+            });
+        }
+
+        let indexed = self.indexed_hashes(kind)?;
+        let mut update = IndexUpdate {
+            catalog_version,
+            ..IndexUpdate::default()
+        };
+
+        let mut writer = self.lock_writer()?;
+        for id in indexed.keys() {
+            if !documents.contains_key(id) {
+                writer.delete_term(self.key_term(kind, id));
+                update.removed += 1;
+            }
+        }
+        // Upserts delete the previous document of a record before adding the new one
+        for (id, (hash, doc)) in documents {
+            match indexed.get(&id) {
+                Some(indexed_hash) if *indexed_hash == hash => continue,
+                Some(_) => update.updated += 1,
+                None => update.added += 1,
+            }
+            writer.delete_term(self.key_term(kind, &id));
+            writer.add_document(doc)?;
+        }
+
+        versions.insert(kind, update.catalog_version.clone());
+        self.commit(&mut writer, &versions)?;
+        Ok(update)
+    }
+
+    /// Drop every game and index the catalog from scratch, other records are kept
+    pub fn rebuild(&self, games: &[Game]) -> Result<IndexUpdate, OxideError> {
+        self.refresh_suggestions(games)?;
+
+        let documents: Documents = games
+            .iter()
+            .map(|game| (game.id.clone(), self.with_hash(self.game_document(game))))
+            .collect();
+        let catalog_version = catalog_version(&documents);
+        let mut versions = self.catalog_versions()?;
+
+        let mut writer = self.lock_writer()?;
+        writer.delete_term(self.kind_term(EntityKind::Game));
+        let added = documents.len();
+        for (_, (_, doc)) in documents {
+            writer.add_document(doc)?;
+        }
+        versions.insert(EntityKind::Game, catalog_version.clone());
+        self.commit(&mut writer, &versions)?;
+
+        Ok(IndexUpdate {
+            added,
+            catalog_version,
+            ..IndexUpdate::default()
+        })
+    }
+
+    /// Autocomplete completions for a prefix
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Result<Vec<Suggestion>, OxideError> {
+        Ok(self.read_suggester()?.suggest(prefix, limit))
+    }
+
+    /// Replace the synonym groups queries are expanded with
+    pub fn set_synonyms(&self, groups: &[Vec<String>]) -> Result<(), OxideError> {
+        let mut analyzer = analysis::analyzer(self.language);
+        let synonyms = Synonyms::new(groups, &mut analyzer);
+        *self
+            .synonyms
+            .write()
+            .map_err(|_| OxideError::SearchIndex("Synonyms lock poisoned".to_string()))? = synonyms;
+        Ok(())
+    }
+
+    /// Suggestions are kept in memory, so they are rebuilt even when the index is current
+    fn refresh_suggestions(&self, games: &[Game]) -> Result<(), OxideError> {
+        let suggester = Suggester::from_catalog(games);
+        *self
+            .suggester
+            .write()
+            .map_err(|_| OxideError::SearchIndex("Suggester lock poisoned".to_string()))? =
+            suggester;
+        Ok(())
+    }
+
+    /// The update for a catalog source the games were already built from, if they were
+    fn built_from(&self, source_version: &str) -> Result<Option<IndexUpdate>, OxideError> {
+        if self.lock_source_version()?.as_deref() != Some(source_version) {
+            return Ok(None);
+        }
+        Ok(Some(IndexUpdate {
+            catalog_version: self.catalog_version()?.unwrap_or_default(),
+            ..IndexUpdate::default()
+        }))
+    }
+
+    fn set_built_from(&self, source_version: &str) -> Result<(), OxideError> {
+        *self.lock_source_version()? = Some(source_version.to_string());
+        Ok(())
+    }
+
+    fn lock_source_version(&self) -> Result<std::sync::MutexGuard<'_, Option<String>>, OxideError> {
+        self.source_version
+            .lock()
+            .map_err(|_| OxideError::SearchIndex("Catalog version lock poisoned".to_string()))
+    }
+
+    fn read_suggester(&self) -> Result<std::sync::RwLockReadGuard<'_, Suggester>, OxideError> {
+        self.suggester
+            .read()
+            .map_err(|_| OxideError::SearchIndex("Suggester lock poisoned".to_string()))
+    }
+
+    fn lock_writer(&self) -> Result<std::sync::MutexGuard<'_, IndexWriter>, OxideError> {
+        self.writer
+            .lock()
+            .map_err(|_| OxideError::SearchIndex("Index writer lock poisoned".to_string()))
+    }
+
+    /// Commit pending changes stamped with the catalog versions
+    fn commit(
+        &self,
+        writer: &mut IndexWriter,
+        versions: &CatalogVersions,
     ) -> Result<(), OxideError> {
-        let mut index_writer: IndexWriter = self.index.writer(50_000_000)?;
+        let mut prepared = writer.prepare_commit()?;
+        prepared.set_payload(&serde_json::to_string(versions)?);
+        prepared.commit()?;
+        // Searches right after indexing must see the new documents
+        self.reader.reload()?;
+        Ok(())
+    }
 
+    /// Content hash of every indexed record of a kind, keyed by id
+    fn indexed_hashes(&self, kind: EntityKind) -> Result<HashMap<String, String>, OxideError> {
+        let searcher = self.reader.searcher();
+        let mut hashes = HashMap::new();
+        for address in searcher.search(&self.kind_query(kind), &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let field = |field| {
+                doc.get_first(field)
+                    .and_then(|value| value.as_str())
+                    .map(str::to_string)
+            };
+            if let (Some(id), Some(hash)) = (field(self.id_field), field(self.content_hash_field)) {
+                hashes.insert(id, hash);
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Stamp a document with the hash of its indexed fields
+    fn with_hash(&self, mut doc: TantivyDocument) -> (String, TantivyDocument) {
+        let hash = format!(
+            "{:x}",
+            Sha256::digest(doc.to_json(&self.index.schema()).as_bytes())
+        );
+        doc.add_text(self.content_hash_field, &hash);
+        (hash, doc)
+    }
+
+    fn key_term(&self, kind: EntityKind, id: &str) -> Term {
+        Term::from_field_text(self.key_field, &format!("{}:{}", kind.as_str(), id))
+    }
+
+    fn kind_term(&self, kind: EntityKind) -> Term {
+        Term::from_field_text(self.kind_field, kind.as_str())
+    }
+
+    fn kind_query(&self, kind: EntityKind) -> TermQuery {
+        TermQuery::new(self.kind_term(kind), IndexRecordOption::Basic)
+    }
+
+    /// Fields shared by every kind of record
+    fn base_document(&self, kind: EntityKind, id: &str, game_id: Option<&str>) -> TantivyDocument {
         let mut doc = TantivyDocument::new();
-        doc.add_text(self.title_field, title);
-        doc.add_text(self.description_field, description);
-        doc.add_text(self.tags_field, &tags.join(" "));
+        doc.add_text(self.key_field, format!("{}:{}", kind.as_str(), id));
+        doc.add_text(self.kind_field, kind.as_str());
+        doc.add_text(self.id_field, id);
+        if let Some(game_id) = game_id {
+            doc.add_text(self.game_id_field, game_id);
+        }
+        doc
+    }
+
+    fn entity_document(&self, entity: &Entity) -> TantivyDocument {
+        let mut doc = self.base_document(entity.kind, &entity.id, entity.game_id.as_deref());
+        doc.add_text(self.title_field, &entity.name);
+        if !entity.description.is_empty() {
+            doc.add_text(self.description_field, &entity.description);
+        }
+        for keyword in &entity.keywords {
+            doc.add_text(self.tags_field, keyword);
+        }
+        doc
+    }
 
-        index_writer.add_document(doc)?;
-        index_writer.commit()?;
+    fn game_document(&self, game: &Game) -> TantivyDocument {
+        let mut doc = self.base_document(EntityKind::Game, &game.id, Some(&game.id));
+        doc.add_text(self.title_field, &game.title);
+        if let Some(description) = &game.description {
+            doc.add_text(self.description_field, description);
+        }
+        for tag in &game.details.tags {
+            doc.add_text(self.tags_field, tag);
+        }
+        for genre in &game.details.genres {
+            doc.add_text(self.genres_field, genre);
+        }
+        if let Some(developer) = &game.developer {
+            doc.add_text(self.developer_field, developer);
+        }
 
-        Ok(())
+        for genre in &game.details.genres {
+            doc.add_facet(self.facets_field, Facet::from_path([GENRE_FACET, genre]));
+        }
+        for platform in &game.details.platforms {
+            doc.add_facet(
+                self.facets_field,
+                Facet::from_path([PLATFORM_FACET, platform]),
+            );
+        }
+        for feature in game_features(game) {
+            doc.add_facet(
+                self.facets_field,
+                Facet::from_path([FEATURE_FACET, feature]),
+            );
+        }
+
+        doc.add_f64(self.price_field, game.pricing.current_price);
+        doc.add_f64(self.rating_field, game.stats.rating);
+        if let Some(date) = game.details.release_date.as_deref().and_then(parse_date) {
+            doc.add_date(self.release_date_field, date);
+        }
+        doc.add_u64(self.download_count_field, game.stats.download_count.into());
+        if let Some(ai_score) = game.ai_score {
+            doc.add_f64(self.ai_score_field, ai_score);
+        }
+        doc
+    }
+
+    pub fn search(&self, request: &SearchRequest) -> Result<SearchResults, OxideError> {
+        let searcher = self.reader.searcher();
+        let query = self.filtered_query(&request.query, &request.filters)?;
+
+        let mut facet_collector = FacetCollector::for_field("facets");
+        for root in [GENRE_FACET, PLATFORM_FACET, FEATURE_FACET] {
+            facet_collector.add_facet(Facet::from_path([root]));
+        }
+        let (total, facet_counts) = searcher.search(&query, &(Count, facet_collector))?;
+
+        let (offset, limit) = (request.offset, request.limit);
+        let sort = match request.sort {
+            SearchSort::Relevance if request.query.trim().is_empty() => SearchSort::Popular,
+            sort => sort,
+        };
+        let page = match sort {
+            SearchSort::Relevance => {
+                // TopDocs refuses a limit of zero
+                let top_docs = TopDocs::with_limit(limit.max(1)).and_offset(offset);
+                searcher
+                    .search(&query, &top_docs)?
+                    .into_iter()
+                    .map(|(score, address)| (Some(score), address))
+                    .collect()
+            }
+            SearchSort::Popular => by_field::<u64>(
+                &searcher,
+                &query,
+                DOWNLOAD_COUNT_FIELD,
+                Order::Desc,
+                offset,
+                limit,
+            )?,
+            SearchSort::Newest => by_field::<DateTime>(
+                &searcher,
+                &query,
+                RELEASE_DATE_FIELD,
+                Order::Desc,
+                offset,
+                limit,
+            )?,
+            SearchSort::Rating => {
+                by_field::<f64>(&searcher, &query, RATING_FIELD, Order::Desc, offset, limit)?
+            }
+            SearchSort::PriceLow => {
+                by_field::<f64>(&searcher, &query, PRICE_FIELD, Order::Asc, offset, limit)?
+            }
+            SearchSort::PriceHigh => {
+                by_field::<f64>(&searcher, &query, PRICE_FIELD, Order::Desc, offset, limit)?
+            }
+            SearchSort::AiScore => by_field::<f64>(
+                &searcher,
+                &query,
+                AI_SCORE_FIELD,
+                Order::Desc,
+                offset,
+                limit,
+            )?,
+        };
+
+        let mut hits = Vec::new();
+
+        for (score, doc_address) in page.into_iter().take(limit) {
+            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
+
+            if let Some(game_id) = retrieved_doc
+                .get_first(self.id_field)
+                .and_then(|value| value.as_str())
+            {
+                hits.push(SearchHit {
+                    game_id: game_id.to_string(),
+                    score,
+                });
+            }
+        }
+
+        Ok(SearchResults {
+            hits,
+            total,
+            facets: SearchFacets {
+                genres: facet_values(&facet_counts, GENRE_FACET),
+                platforms: facet_values(&facet_counts, PLATFORM_FACET),
+                features: facet_values(&facet_counts, FEATURE_FACET),
+            },
+        })
     }
 
-    pub fn search(
+    /// The text query restricted by the filters, filters do not change scores
+    fn filtered_query(
+        &self,
+        query_str: &str,
+        filters: &SearchFilters,
+    ) -> Result<Box<dyn Query>, OxideError> {
+        let query = self.build_query(query_str)?;
+        let filters = self.filter_clauses(filters)?;
+
+        let mut clauses = vec![(Occur::Must, query)];
+        for filter in filters {
+            clauses.push((
+                Occur::Must,
+                Box::new(ConstScoreQuery::new(filter, 0.0)) as Box<dyn Query>,
+            ));
+        }
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    fn filter_clauses(&self, filters: &SearchFilters) -> Result<Vec<Box<dyn Query>>, OxideError> {
+        let facet = |root: &str, value: &str| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_facet(self.facets_field, &Facet::from_path([root, value])),
+                IndexRecordOption::Basic,
+            ))
+        };
+        let any_of = |root: &str, values: &[String]| -> Box<dyn Query> {
+            Box::new(BooleanQuery::union(
+                values.iter().map(|value| facet(root, value)).collect(),
+            ))
+        };
+
+        // Only games have facets and fast fields
+        let mut clauses: Vec<Box<dyn Query>> = vec![Box::new(self.kind_query(EntityKind::Game))];
+        if !filters.genres.is_empty() {
+            clauses.push(any_of(GENRE_FACET, &filters.genres));
+        }
+        if !filters.platforms.is_empty() {
+            clauses.push(any_of(PLATFORM_FACET, &filters.platforms));
+        }
+        for feature in &filters.features {
+            clauses.push(facet(FEATURE_FACET, feature));
+        }
+
+        if filters.min_price.is_some() || filters.max_price.is_some() {
+            clauses.push(Box::new(RangeQuery::new_f64_bounds(
+                PRICE_FIELD.to_string(),
+                inclusive(filters.min_price),
+                inclusive(filters.max_price),
+            )));
+        }
+        if let Some(min_rating) = filters.min_rating {
+            clauses.push(Box::new(RangeQuery::new_f64_bounds(
+                RATING_FIELD.to_string(),
+                Bound::Included(min_rating),
+                Bound::Unbounded,
+            )));
+        }
+
+        let date = |value: &Option<String>| -> Result<Option<DateTime>, OxideError> {
+            value
+                .as_deref()
+                .map(|value| {
+                    parse_date(value).ok_or_else(|| {
+                        OxideError::SearchIndex(format!(
+                            "Invalid date \"{}\", expected YYYY-MM-DD",
+                            value
+                        ))
+                    })
+                })
+                .transpose()
+        };
+        let (after, before) = (
+            date(&filters.released_after)?,
+            date(&filters.released_before)?,
+        );
+        if after.is_some() || before.is_some() {
+            clauses.push(Box::new(RangeQuery::new_date_bounds(
+                RELEASE_DATE_FIELD.to_string(),
+                inclusive(after),
+                inclusive(before),
+            )));
+        }
+
+        Ok(clauses)
+    }
+
+    /// Search every kind of record, best matches of each kind grouped together
+    ///
+    /// Kinds without matches are left out. An empty query finds nothing.
+    pub fn search_all(
         &self,
         query_str: &str,
         limit: usize,
-    ) -> Result<Vec<serde_json::Value>, OxideError> {
+    ) -> Result<Vec<EntityGroup>, OxideError> {
+        if query_str.trim().is_empty() {
+            return Ok(Vec::new());
+        }
         let searcher = self.reader.searcher();
+        let stored = |doc: &TantivyDocument, field| {
+            doc.get_first(field)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
 
-        let query_parser = QueryParser::for_index(
-            &self.index,
-            vec![self.title_field, self.description_field, self.tags_field],
-        );
+        let mut groups = Vec::new();
+        for kind in EntityKind::ALL {
+            let query = BooleanQuery::new(vec![
+                (Occur::Must, self.build_query(query_str)?),
+                (
+                    Occur::Must,
+                    Box::new(ConstScoreQuery::new(Box::new(self.kind_query(kind)), 0.0)),
+                ),
+            ]);
+            let (top_docs, total) =
+                searcher.search(&query, &(TopDocs::with_limit(limit.max(1)), Count))?;
+            if total == 0 {
+                continue;
+            }
 
-        let query = query_parser.parse_query(query_str)?;
+            let mut hits = Vec::new();
+            for (score, address) in top_docs.into_iter().take(limit) {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                if let Some(id) = stored(&doc, self.id_field) {
+                    hits.push(EntityHit {
+                        kind,
+                        id,
+                        game_id: stored(&doc, self.game_id_field),
+                        name: stored(&doc, self.title_field).unwrap_or_default(),
+                        score,
+                    });
+                }
+            }
+            groups.push(EntityGroup { kind, hits, total });
+        }
+        Ok(groups)
+    }
 
-        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit))?;
+    /// Turn user input into a query without a query syntax that could fail to parse
+    ///
+    /// Every word must match in some field. Title words also match with typos,
+    /// and the last word matches as a title prefix while the user is typing.
+    /// Words forming a synonym also match every other phrase of its group.
+    fn build_query(&self, query_str: &str) -> Result<Box<dyn Query>, OxideError> {
+        let words = self.tokenize(query_str)?;
+        // An empty search lists the whole catalog
+        if words.is_empty() {
+            return Ok(Box::new(AllQuery));
+        }
+        let typing_last_word = !query_str.ends_with(|c: char| c.is_whitespace());
+        let is_typing = |i: usize| typing_last_word && i == words.len() - 1;
 
-        let mut results = Vec::new();
+        let synonyms = self
+            .synonyms
+            .read()
+            .map_err(|_| OxideError::SearchIndex("Synonyms lock poisoned".to_string()))?;
 
-        for (_score, doc_address) in top_docs {
-            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
-            
-            // Convert document to JSON and parse it
-            let doc_json = retrieved_doc.to_json(&self.schema);
-            let doc_value: serde_json::Value = serde_json::from_str(&doc_json)?;
-            
-            let title = doc_value["title"]
-                .as_array()
-                .and_then(|arr| arr.first())
-                .and_then(|v| v.as_str())
-                .unwrap_or("Unknown");
-                
-            let description = doc_value["description"]
-                .as_array()
-                .and_then(|arr| arr.first())
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-                
-            let tags = doc_value["tags"]
-                .as_array()
-                .and_then(|arr| arr.first())
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-
-            let result = serde_json::json!({
-                "title": title,
-                "description": description,
-                "tags": tags.split_whitespace().collect::<Vec<_>>()
-            });
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            let (len, group) = synonyms.longest_match(&words[i..]).unwrap_or((1, &[]));
+            let typed = all_of(
+                (i..i + len)
+                    .map(|j| self.word_query(&words[j], true, is_typing(j)))
+                    .collect(),
+            );
+
+            let mut alternatives = vec![typed];
+            // Synonyms are matched exactly, the typed words keep their typo tolerance
+            for phrase in group.iter().filter(|phrase| **phrase != words[i..i + len]) {
+                alternatives.push(all_of(
+                    phrase
+                        .iter()
+                        .map(|word| self.word_query(word, false, false))
+                        .collect(),
+                ));
+            }
+            clauses.push((Occur::Must, Box::new(BooleanQuery::union(alternatives))));
+            i += len;
+        }
+
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// A word matching in any text field, boosted per field
+    fn word_query(&self, word: &str, fuzzy: bool, prefix: bool) -> Box<dyn Query> {
+        let fields = [
+            (self.title_field, TITLE_BOOST),
+            (self.tags_field, TAGS_BOOST),
+            (self.genres_field, GENRES_BOOST),
+            (self.developer_field, DEVELOPER_BOOST),
+            (self.description_field, DESCRIPTION_BOOST),
+        ];
+        let mut alternatives: Vec<Box<dyn Query>> = fields
+            .iter()
+            .map(|&(field, boost)| {
+                let term = TermQuery::new(
+                    Term::from_field_text(field, word),
+                    IndexRecordOption::WithFreqs,
+                );
+                Box::new(BoostQuery::new(Box::new(term), boost)) as Box<dyn Query>
+            })
+            .collect();
+
+        let title_term = Term::from_field_text(self.title_field, word);
+        let distance = typo_distance(word);
+        if fuzzy && distance > 0 {
+            let fuzzy = FuzzyTermQuery::new(title_term.clone(), distance, true);
+            alternatives.push(Box::new(BoostQuery::new(
+                Box::new(fuzzy),
+                FUZZY_TITLE_BOOST,
+            )));
+        }
+        if prefix {
+            let prefix = FuzzyTermQuery::new_prefix(title_term, 0, true);
+            alternatives.push(Box::new(BoostQuery::new(
+                Box::new(prefix),
+                PREFIX_TITLE_BOOST,
+            )));
+        }
+
+        Box::new(BooleanQuery::union(alternatives))
+    }
+
+    /// Split input into terms the way titles were tokenized at index time
+    fn tokenize(&self, text: &str) -> Result<Vec<String>, OxideError> {
+        let mut analyzer = self.index.tokenizer_for_field(self.title_field)?;
+        Ok(analysis::tokenize(&mut analyzer, text))
+    }
+}
+
+/// A query matching when every one of `queries` does
+fn all_of(mut queries: Vec<Box<dyn Query>>) -> Box<dyn Query> {
+    if queries.len() == 1 {
+        return queries.remove(0);
+    }
+    Box::new(BooleanQuery::intersection(queries))
+}
+
+/// Edits tolerated in a word, short words must match exactly
+fn typo_distance(word: &str) -> u8 {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// One page of matches ordered by a fast field, without relevance scores
+fn by_field<T: FastValue>(
+    searcher: &Searcher,
+    query: &dyn Query,
+    field: &str,
+    order: Order,
+    offset: usize,
+    limit: usize,
+) -> Result<Vec<(Option<f32>, DocAddress)>, OxideError> {
+    let top_docs = TopDocs::with_limit(limit.max(1))
+        .and_offset(offset)
+        .order_by_fast_field::<T>(field, order);
+    Ok(searcher
+        .search(query, &top_docs)?
+        .into_iter()
+        .map(|(_, address)| (None, address))
+        .collect())
+}
 
-            results.push(result);
+/// Values under a facet root with their counts, most common first
+fn facet_values(counts: &FacetCounts, root: &str) -> Vec<FacetCount> {
+    let mut values: Vec<FacetCount> = counts
+        .get(Facet::from_path([root]))
+        .filter_map(|(facet, count)| {
+            facet.to_path().last().map(|value| FacetCount {
+                value: value.to_string(),
+                count,
+            })
+        })
+        .collect();
+    values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    values
+}
+
+/// Feature flags a game has, named as in the catalog
+fn game_features(game: &Game) -> impl Iterator<Item = &'static str> {
+    let features = &game.features;
+    [
+        ("controller", features.controller),
+        ("cloudPlay", features.cloud_play),
+        ("offline", features.offline),
+        ("multiPlayer", features.multi_player),
+        ("singlePlayer", features.single_player),
+        ("achievements", features.achievements),
+        ("workshop", features.workshop),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+}
+
+/// Midnight UTC of a `YYYY-MM-DD` date, a trailing time is ignored
+fn parse_date(value: &str) -> Option<DateTime> {
+    let date = chrono::NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()?;
+    Some(DateTime::from_timestamp_secs(
+        date.and_hms_opt(0, 0, 0)?.and_utc().timestamp(),
+    ))
+}
+
+fn inclusive<T>(value: Option<T>) -> Bound<T> {
+    value.map_or(Bound::Unbounded, Bound::Included)
+}
+
+/// Open the on-disk index, rebuilding it when it was written with another schema
+fn open_index(index_dir: &Path, schema: &Schema) -> Result<Index, OxideError> {
+    let open = || -> Result<Index, OxideError> {
+        if !index_dir.exists() {
+            std::fs::create_dir_all(index_dir).map_err(|e| {
+                OxideError::InitializationError(format!("Failed to create index directory: {}", e))
+            })?;
         }
 
-        Ok(results)
+        let directory = tantivy::directory::MmapDirectory::open(index_dir).map_err(|e| {
+            OxideError::InitializationError(format!("Failed to open index directory: {}", e))
+        })?;
+        Ok(Index::open_or_create(directory, schema.clone())?)
+    };
+
+    match open() {
+        // The index only mirrors the catalog, so an outdated one is thrown away
+        Err(OxideError::Tantivy(tantivy::TantivyError::SchemaError(_))) => {
+            std::fs::remove_dir_all(index_dir)?;
+            open()
+        }
+        result => result,
+    }
+}
+
+/// Stamp identifying a catalog by the content of its records
+fn catalog_version(documents: &Documents) -> String {
+    let mut hasher = Sha256::new();
+    for (id, (hash, _)) in documents {
+        hasher.update(id.as_bytes());
+        hasher.update(b":");
+        hasher.update(hash.as_bytes());
+        hasher.update(b"\n");
     }
+    format!("{:x}", hasher.finalize())
 }
 
-// Global search index instance
-static SEARCH_INDEX: OnceLock<GameSearchIndex> = OnceLock::new();
+/// Open search index by language code, only the catalog's current language is kept open
+static SEARCH_INDEXES: Mutex<BTreeMap<&'static str, Arc<GameSearchIndex>>> =
+    Mutex::new(BTreeMap::new());
+/// Language code detected from the catalog, used when no locale is configured
+static DETECTED_LANGUAGE: Mutex<Option<&'static str>> = Mutex::new(None);
 
-/// Initialize the search index
-pub async fn index_games() -> Result<String, OxideError> {
-    let search_index = GameSearchIndex::new()?;
+fn lock_indexes(
+) -> Result<std::sync::MutexGuard<'static, BTreeMap<&'static str, Arc<GameSearchIndex>>>, OxideError>
+{
+    SEARCH_INDEXES
+        .lock()
+        .map_err(|_| OxideError::SearchIndex("Search index lock poisoned".to_string()))
+}
+
+/// Open the index for a language on first use
+///
+/// Opening is serialized by the lock, a second writer on the same directory would fail.
+fn search_index_for(language: Option<Language>) -> Result<Arc<GameSearchIndex>, OxideError> {
+    let code = analysis::language_code(language);
+    let mut indexes = lock_indexes()?;
+    if let Some(search_index) = indexes.get(code) {
+        return Ok(search_index.clone());
+    }
+
+    let settings = analysis::load_settings()?;
+    let search_index = Arc::new(GameSearchIndex::new(language, &settings.synonyms)?);
+    indexes.clear();
+    indexes.insert(code, search_index.clone());
+    Ok(search_index)
+}
+
+/// Language the catalog is analyzed in, the configured locale wins over detection
+///
+/// Without a catalog at hand the last detected language is used.
+fn catalog_language(games: Option<&[Game]>) -> Result<Option<Language>, OxideError> {
+    if let Some(locale) = analysis::load_settings()?.locale {
+        return Ok(analysis::language_for_locale(&locale));
+    }
+
+    let mut detected = DETECTED_LANGUAGE
+        .lock()
+        .map_err(|_| OxideError::SearchIndex("Search index lock poisoned".to_string()))?;
+    if let Some(games) = games {
+        *detected = Some(analysis::language_code(analysis::detect_language(games)));
+    }
+    Ok(match *detected {
+        Some(code) => analysis::language_for_locale(code),
+        None => Some(Language::English),
+    })
+}
 
-    // Add some sample games for demonstration
-    search_index.add_game(
-        "indie_platformer_1",
-        "Super Jump Adventure",
-        "A thrilling platformer with amazing graphics and smooth gameplay",
-        &["platformer", "indie", "adventure", "2d"].iter().map(|s| s.to_string()).collect::<Vec<String>>(),
-    )?;
+/// The index for the catalog's current language
+fn search_index() -> Result<Arc<GameSearchIndex>, OxideError> {
+    search_index_for(catalog_language(None)?)
+}
 
-    search_index.add_game(
-        "strategy_game_1",
-        "Empire Builder",
-        "Build your empire and conquer the world in this strategy masterpiece",
-        &["strategy", "rts", "empire", "war"].iter().map(|s| s.to_string()).collect::<Vec<String>>(),
-    )?;
+/// Run index work, analyzing, writing and searching, off the async runtime
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, OxideError> + Send + 'static,
+) -> Result<T, OxideError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| OxideError::SearchIndex(format!("Search index task failed: {}", e)))?
+}
+
+/// Update the search index from the game catalog, a no-op when it is current
+///
+/// `source_version` identifies the catalog as read, e.g. a hash of its file. A
+/// catalog the open index was built from is not analyzed again, so repeated
+/// calls skip language detection, hashing every game and rebuilding suggestions.
+pub async fn index_games(games: &[Game], source_version: &str) -> Result<IndexUpdate, OxideError> {
+    // Only the current language's index is open, a locale change closes it
+    let open = lock_indexes()?.values().next().cloned();
+    if let Some(update) = open
+        .map(|search_index| search_index.built_from(source_version))
+        .transpose()?
+        .flatten()
+    {
+        return Ok(update);
+    }
+
+    let (games, source_version) = (games.to_vec(), source_version.to_string());
+    blocking(move || {
+        let search_index = search_index_for(catalog_language(Some(&games))?)?;
+        let update = search_index.update_catalog(&games)?;
+        search_index.set_built_from(&source_version)?;
+        Ok(update)
+    })
+    .await
+}
+
+/// Rebuild the search index from scratch
+pub async fn rebuild_index(
+    games: &[Game],
+    source_version: &str,
+) -> Result<IndexUpdate, OxideError> {
+    let (games, source_version) = (games.to_vec(), source_version.to_string());
+    blocking(move || {
+        let search_index = search_index_for(catalog_language(Some(&games))?)?;
+        let update = search_index.rebuild(&games)?;
+        search_index.set_built_from(&source_version)?;
+        Ok(update)
+    })
+    .await
+}
 
-    SEARCH_INDEX
-        .set(search_index)
-        .map_err(|_| OxideError::InitializationError("Failed to initialize search index".to_string()))?;
+/// Update the developers, mods, achievements or friends in the index
+pub async fn index_entities(
+    kind: EntityKind,
+    entities: &[Entity],
+) -> Result<IndexUpdate, OxideError> {
+    let entities = entities.to_vec();
+    blocking(move || search_index()?.update_entities(kind, &entities)).await
+}
 
-    Ok("Search index initialized successfully".to_string())
+/// Search every kind of record, at most `limit` hits per kind
+pub async fn search_all(query: String, limit: usize) -> Result<Vec<EntityGroup>, OxideError> {
+    blocking(move || search_index()?.search_all(&query, limit)).await
 }
 
 /// Search for games using the index
-pub async fn search_games(query: String) -> Result<Vec<serde_json::Value>, OxideError> {
-    let search_index = SEARCH_INDEX
-        .get()
-        .ok_or_else(|| OxideError::SearchIndex("Search index not initialized".to_string()))?;
+pub async fn search_games(request: SearchRequest) -> Result<SearchResults, OxideError> {
+    blocking(move || search_index()?.search(&request)).await
+}
+
+/// Autocomplete suggestions for the search box
+pub fn suggest(prefix: &str, limit: usize) -> Result<Vec<Suggestion>, OxideError> {
+    search_index()?.suggest(prefix, limit)
+}
+
+/// Whether suggestions have been built from the catalog since startup
+pub fn has_suggestions() -> bool {
+    lock_indexes().is_ok_and(|indexes| {
+        indexes.values().any(|search_index| {
+            search_index
+                .read_suggester()
+                .is_ok_and(|suggester| !suggester.is_empty())
+        })
+    })
+}
+
+/// Get the catalog locale and synonym groups
+pub fn get_search_settings() -> Result<SearchSettings, OxideError> {
+    analysis::load_settings()
+}
 
-    search_index.search(&query, 10)
+/// Set the catalog locale, `None` detects the language from the catalog
+///
+/// The index for the new language starts empty, callers index every kind of
+/// record again.
+pub fn set_search_locale(locale: Option<String>) -> Result<SearchSettings, OxideError> {
+    let mut settings = analysis::load_settings()?;
+    settings.locale = locale
+        .map(|locale| locale.trim().to_string())
+        .filter(|locale| !locale.is_empty());
+    analysis::save_settings(&settings)?;
+
+    // Close the current index so suggestions are rebuilt for the new one
+    lock_indexes()?.clear();
+    Ok(settings)
+}
+
+/// Replace the synonym groups, applied to the next search without reindexing
+pub fn set_search_synonyms(synonyms: Vec<Vec<String>>) -> Result<SearchSettings, OxideError> {
+    let mut settings = analysis::load_settings()?;
+    settings.synonyms = synonyms;
+    analysis::save_settings(&settings)?;
+
+    for search_index in lock_indexes()?.values() {
+        search_index.set_synonyms(&settings.synonyms)?;
+    }
+    Ok(settings)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct TestGame {
+        id: &'static str,
+        title: &'static str,
+        description: &'static str,
+        genres: &'static [&'static str],
+        tags: &'static [&'static str],
+        platforms: &'static [&'static str],
+        price: f64,
+        rating: f64,
+        release_date: &'static str,
+        downloads: u32,
+        controller: bool,
+    }
+
+    const DEFAULT: TestGame = TestGame {
+        id: "",
+        title: "",
+        description: "",
+        genres: &[],
+        tags: &[],
+        platforms: &["windows"],
+        price: 10.0,
+        rating: 4.0,
+        release_date: "2020-01-01",
+        downloads: 0,
+        controller: false,
+    };
+
+    fn game(game: TestGame) -> Game {
+        serde_json::from_value(json!({
+            "id": game.id,
+            "title": game.title,
+            "slug": game.id,
+            "description": game.description,
+            "developer": "Test Studio",
+            "media": { "coverImage": "", "screenshots": [], "iconUrl": "", "title": "" },
+            "pricing": { "basePrice": game.price, "currentPrice": game.price },
+            "details": {
+                "platforms": game.platforms,
+                "genres": game.genres,
+                "tags": game.tags,
+                "releaseDate": game.release_date,
+            },
+            "stats": {
+                "rating": game.rating, "reviewCount": 0,
+                "downloadCount": game.downloads, "wishlistCount": 0
+            },
+            "features": {
+                "controller": game.controller, "cloudPlay": false, "offline": true,
+                "multiPlayer": false, "singlePlayer": true, "achievements": false,
+                "workshop": false
+            }
+        }))
+        .unwrap()
+    }
+
+    fn catalog() -> Vec<Game> {
+        vec![
+            game(TestGame {
+                id: "half-life",
+                title: "Half-Life",
+                description: "A scientist fights his way out of a research facility",
+                genres: &["Shooter"],
+                tags: &["first-person shooter", "classic"],
+                platforms: &["windows", "linux"],
+                price: 9.99,
+                rating: 4.9,
+                release_date: "1998-11-19",
+                downloads: 5000,
+                controller: true,
+            }),
+            game(TestGame {
+                id: "witcher",
+                title: "The Witcher",
+                description: "Monster hunting across a dark fantasy world",
+                genres: &["RPG"],
+                tags: &["fantasy", "story rich"],
+                price: 19.99,
+                rating: 4.7,
+                release_date: "2007-10-26",
+                downloads: 3000,
+                ..DEFAULT
+            }),
+            game(TestGame {
+                id: "pokemon",
+                title: "Pokémon Trainers",
+                description: "Catching creatures and building teams",
+                genres: &["RPG"],
+                platforms: &["linux"],
+                price: 29.99,
+                rating: 4.1,
+                release_date: "2021-05-01",
+                downloads: 1000,
+                controller: true,
+                ..DEFAULT
+            }),
+            game(TestGame {
+                id: "city",
+                title: "Skyline Architect",
+                description: "Plan roads and zone districts",
+                genres: &["Simulation"],
+                price: 0.0,
+                rating: 3.5,
+                release_date: "2015-03-10",
+                downloads: 200,
+                ..DEFAULT
+            }),
+        ]
+    }
+
+    /// An English index in a fresh temporary directory
+    fn open_index(dir: &tempfile::TempDir) -> GameSearchIndex {
+        GameSearchIndex::open(
+            dir.path(),
+            Some(Language::English),
+            &SearchSettings::default().synonyms,
+        )
+        .unwrap()
+    }
+
+    fn search(index: &GameSearchIndex, query: &str) -> Vec<String> {
+        let request = SearchRequest {
+            query: query.to_string(),
+            limit: 10,
+            ..SearchRequest::default()
+        };
+        index
+            .search(&request)
+            .unwrap()
+            .hits
+            .into_iter()
+            .map(|hit| hit.game_id)
+            .collect()
+    }
+
+    fn filtered(index: &GameSearchIndex, filters: SearchFilters) -> SearchResults {
+        index
+            .search(&SearchRequest {
+                filters,
+                limit: 10,
+                ..SearchRequest::default()
+            })
+            .unwrap()
+    }
+
+    fn facet(counts: &[FacetCount], value: &str) -> u64 {
+        counts
+            .iter()
+            .find(|count| count.value == value)
+            .map_or(0, |count| count.count)
+    }
+
+    #[test]
+    fn updates_upsert_and_delete_incrementally() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir);
+        let mut games = catalog();
+
+        let update = index.update_catalog(&games).unwrap();
+        assert_eq!((update.added, update.updated, update.removed), (4, 0, 0));
+        assert_eq!(
+            index.catalog_version().unwrap().as_deref(),
+            Some(update.catalog_version.as_str())
+        );
+
+        // The same catalog changes nothing
+        let unchanged = index.update_catalog(&games).unwrap();
+        assert_eq!(
+            (unchanged.added, unchanged.updated, unchanged.removed),
+            (0, 0, 0)
+        );
+
+        games.retain(|game| game.id != "city");
+        games[0].title = "Half-Life Source".to_string();
+        games.push(game(TestGame {
+            id: "portal",
+            title: "Portal",
+            ..DEFAULT
+        }));
+        let update = index.update_catalog(&games).unwrap();
+        assert_eq!((update.added, update.updated, update.removed), (1, 1, 1));
+
+        assert_eq!(search(&index, "portal"), ["portal"]);
+        assert_eq!(search(&index, "source"), ["half-life"]);
+        assert!(search(&index, "skyline").is_empty());
+        assert_eq!(filtered(&index, SearchFilters::default()).total, 4);
+
+        // Other records survive a catalog rebuild
+        index
+            .update_entities(
+                EntityKind::Friend,
+                &[Entity {
+                    kind: EntityKind::Friend,
+                    id: "f1".to_string(),
+                    game_id: None,
+                    name: "gordon".to_string(),
+                    description: String::new(),
+                    keywords: Vec::new(),
+                }],
+            )
+            .unwrap();
+        let rebuilt = index.rebuild(&games).unwrap();
+        assert_eq!(rebuilt.added, 4);
+        assert_eq!(filtered(&index, SearchFilters::default()).total, 4);
+        assert_eq!(index.search_all("gordon", 5).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn matches_typos_prefixes_and_ignores_query_syntax() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir);
+        index.update_catalog(&catalog()).unwrap();
+
+        assert_eq!(search(&index, "witchr "), ["witcher"]);
+        assert_eq!(search(&index, "half lfie "), ["half-life"]);
+        // The last word is still being typed
+        assert_eq!(search(&index, "witc"), ["witcher"]);
+        assert!(search(&index, "witc ").is_empty());
+
+        for query in [
+            "\"half-life:",
+            "title:(",
+            "half AND OR -life",
+            "[1 TO",
+            "*",
+            "\\",
+        ] {
+            let request = SearchRequest {
+                query: query.to_string(),
+                limit: 10,
+                ..SearchRequest::default()
+            };
+            assert!(index.search(&request).is_ok(), "{}", query);
+            assert!(index.search_all(query, 5).is_ok(), "{}", query);
+        }
+        assert_eq!(search(&index, "\"half-life:"), ["half-life"]);
+    }
+
+    #[test]
+    fn suggests_titles_developers_and_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir);
+        index.refresh_suggestions(&catalog()).unwrap();
+
+        // A later word of the title completes too
+        let suggestions = index.suggest("wit", 5).unwrap();
+        assert_eq!(suggestions[0].text, "The Witcher");
+        assert_eq!(suggestions[0].game_id.as_deref(), Some("witcher"));
+
+        let developer = index.suggest("test st", 5).unwrap();
+        assert_eq!(developer[0].text, "Test Studio");
+        assert_eq!(developer[0].kind, suggest::SuggestionKind::Developer);
+
+        // Leading matches first, then the most downloaded
+        let names: Vec<String> = index
+            .suggest("s", 5)
+            .unwrap()
+            .into_iter()
+            .map(|suggestion| suggestion.text)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Shooter",
+                "story rich",
+                "Simulation",
+                "Skyline Architect",
+                "Test Studio"
+            ]
+        );
+        assert!(index.suggest("zzz", 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn filters_and_counts_facets() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir);
+        index.update_catalog(&catalog()).unwrap();
+
+        let all = filtered(&index, SearchFilters::default());
+        assert_eq!(all.total, 4);
+        assert_eq!(facet(&all.facets.genres, "RPG"), 2);
+        assert_eq!(facet(&all.facets.platforms, "linux"), 2);
+        assert_eq!(facet(&all.facets.features, "controller"), 2);
+        // Empty queries are ordered by downloads
+        let ids: Vec<&str> = all.hits.iter().map(|hit| hit.game_id.as_str()).collect();
+        assert_eq!(ids, ["half-life", "witcher", "pokemon", "city"]);
+
+        let rpgs = filtered(
+            &index,
+            SearchFilters {
+                genres: vec!["RPG".to_string()],
+                ..SearchFilters::default()
+            },
+        );
+        assert_eq!(rpgs.total, 2);
+        assert_eq!(facet(&rpgs.facets.platforms, "linux"), 1);
+
+        let linux_with_controller = filtered(
+            &index,
+            SearchFilters {
+                platforms: vec!["linux".to_string()],
+                features: vec!["controller".to_string()],
+                max_price: Some(10.0),
+                ..SearchFilters::default()
+            },
+        );
+        assert_eq!(linux_with_controller.total, 1);
+        assert_eq!(linux_with_controller.hits[0].game_id, "half-life");
+
+        let recent_and_rated = filtered(
+            &index,
+            SearchFilters {
+                released_after: Some("2000-01-01".to_string()),
+                released_before: Some("2015-03-10".to_string()),
+                min_rating: Some(4.0),
+                ..SearchFilters::default()
+            },
+        );
+        assert_eq!(recent_and_rated.total, 1);
+        assert_eq!(recent_and_rated.hits[0].game_id, "witcher");
+
+        let request = SearchRequest {
+            filters: SearchFilters {
+                released_after: Some("yesterday".to_string()),
+                ..SearchFilters::default()
+            },
+            ..SearchRequest::default()
+        };
+        assert!(index.search(&request).is_err());
+
+        let cheapest = index
+            .search(&SearchRequest {
+                sort: SearchSort::PriceLow,
+                limit: 1,
+                ..SearchRequest::default()
+            })
+            .unwrap();
+        assert_eq!(cheapest.hits[0].game_id, "city");
+        assert_eq!(cheapest.total, 4);
+    }
+
+    #[test]
+    fn groups_unified_search_by_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir);
+        index.update_catalog(&catalog()).unwrap();
+        let entity = |kind, id: &str, name: &str, keywords: &[&str]| Entity {
+            kind,
+            id: id.to_string(),
+            game_id: None,
+            name: name.to_string(),
+            description: String::new(),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+        };
+        index
+            .update_entities(
+                EntityKind::Friend,
+                &[
+                    entity(EntityKind::Friend, "f1", "geralt_fan", &["The Witcher"]),
+                    entity(EntityKind::Friend, "f2", "ciri", &["The Witcher"]),
+                    entity(EntityKind::Friend, "f3", "gordon", &["Half-Life"]),
+                ],
+            )
+            .unwrap();
+        index
+            .update_entities(
+                EntityKind::Developer,
+                &[entity(EntityKind::Developer, "d1", "Witcher Works", &[])],
+            )
+            .unwrap();
+
+        let groups = index.search_all("witcher", 1).unwrap();
+        let kinds: Vec<EntityKind> = groups.iter().map(|group| group.kind).collect();
+        assert_eq!(
+            kinds,
+            [EntityKind::Game, EntityKind::Developer, EntityKind::Friend]
+        );
+        let friends = &groups[2];
+        assert_eq!((friends.hits.len(), friends.total), (1, 2));
+        assert_eq!(groups[0].hits[0].id, "witcher");
+        assert_eq!(groups[0].hits[0].name, "The Witcher");
+
+        // Game searches never see other kinds of records
+        assert_eq!(search(&index, "gordon"), Vec::<String>::new());
+        assert!(index.search_all("  ", 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stems_folds_accents_and_expands_synonyms() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = open_index(&dir);
+        index.update_catalog(&catalog()).unwrap();
+
+        // "building" in the description, "builds" stems to the same term
+        assert_eq!(search(&index, "builds teams "), ["pokemon"]);
+        assert_eq!(search(&index, "hunted "), ["witcher"]);
+        assert_eq!(search(&index, "pokemon "), ["pokemon"]);
+        assert_eq!(search(&index, "POKÉMON "), ["pokemon"]);
+
+        // "fps" and "first-person shooter" are in one default group
+        assert_eq!(search(&index, "fps "), ["half-life"]);
+        let mut rpgs = search(&index, "role playing game ");
+        rpgs.sort();
+        assert_eq!(rpgs, ["pokemon", "witcher"]);
+
+        index
+            .set_synonyms(&[vec!["metropolis".to_string(), "skyline".to_string()]])
+            .unwrap();
+        assert_eq!(search(&index, "metropolis "), ["city"]);
+        assert!(search(&index, "fps ").is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+//! Text analysis for the search index: stemming, accent folding and synonyms
+//!
+//! Text fields are tokenized by an analyzer for the catalog language, which is
+//! the configured catalog locale or, without one, detected from the catalog
+//! itself. Stems are baked into the index, so every language gets its own index
+//! directory. Synonyms are expanded at query time and can change without
+//! reindexing.
+
+use crate::models::Game;
+use crate::{persist, OxideError};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+    TextAnalyzer,
+};
+
+const SETTINGS_FILE: &str = "search_settings.json";
+/// Locale code of the analyzer without stemming, for languages without a stemmer
+const PLAIN: &str = "plain";
+/// Tokens longer than this are dropped, they are never words users type
+const MAX_TOKEN_LENGTH: usize = 40;
+/// Games whose text is sampled to detect the catalog language
+const DETECTION_SAMPLE: usize = 200;
+
+/// ISO 639-1 codes of the languages with a stemmer, the first code of a language is its own
+const LANGUAGES: &[(&str, Language)] = &[
+    ("ar", Language::Arabic),
+    ("da", Language::Danish),
+    ("de", Language::German),
+    ("el", Language::Greek),
+    ("en", Language::English),
+    ("es", Language::Spanish),
+    ("fi", Language::Finnish),
+    ("fr", Language::French),
+    ("hu", Language::Hungarian),
+    ("it", Language::Italian),
+    ("nl", Language::Dutch),
+    ("no", Language::Norwegian),
+    ("nb", Language::Norwegian),
+    ("nn", Language::Norwegian),
+    ("pt", Language::Portuguese),
+    ("ro", Language::Romanian),
+    ("ru", Language::Russian),
+    ("sv", Language::Swedish),
+    ("ta", Language::Tamil),
+    ("tr", Language::Turkish),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchSettings {
+    /// Catalog locale such as `en` or `fr-FR`, detected from the catalog when unset
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Groups of interchangeable terms, e.g. `["rpg", "role-playing"]`
+    #[serde(default = "default_synonyms")]
+    pub synonyms: Vec<Vec<String>>,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        SearchSettings {
+            locale: None,
+            synonyms: default_synonyms(),
+        }
+    }
+}
+
+/// Genre shorthand players search with
+fn default_synonyms() -> Vec<Vec<String>> {
+    [
+        &["rpg", "role-playing", "role playing game"][..],
+        &["jrpg", "japanese rpg"],
+        &["fps", "first-person shooter"],
+        &["mmo", "mmorpg", "massively multiplayer"],
+        &["rts", "real-time strategy"],
+        &["moba", "multiplayer online battle arena"],
+        &["co-op", "coop", "cooperative"],
+        &["roguelike", "roguelite", "rogue-lite"],
+        &["sim", "simulation", "simulator"],
+        &["pvp", "player versus player"],
+        &["vr", "virtual reality"],
+    ]
+    .iter()
+    .map(|group| group.iter().map(|term| term.to_string()).collect())
+    .collect()
+}
+
+/// Stemming language for a locale, `None` when the language has no stemmer
+pub fn language_for_locale(locale: &str) -> Option<Language> {
+    let primary = locale.split(['-', '_']).next()?.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(code, _)| *code == primary)
+        .map(|&(_, language)| language)
+}
+
+/// Locale code naming the analyzer and index directory of a language
+pub fn language_code(language: Option<Language>) -> &'static str {
+    language
+        .and_then(|language| LANGUAGES.iter().find(|(_, known)| *known == language))
+        .map_or(PLAIN, |&(code, _)| code)
+}
+
+/// Language of the catalog's text, English when it cannot be told reliably
+pub fn detect_language(games: &[Game]) -> Option<Language> {
+    let text = games
+        .iter()
+        .take(DETECTION_SAMPLE)
+        .flat_map(|game| [Some(game.title.as_str()), game.description.as_deref()])
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(". ");
+
+    let info = match whatlang::detect(&text) {
+        Some(info) if info.is_reliable() => info,
+        _ => return Some(Language::English),
+    };
+    use whatlang::Lang;
+    let code = match info.lang() {
+        Lang::Ara => "ar",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Spa => "es",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Hun => "hu",
+        Lang::Ita => "it",
+        Lang::Nld => "nl",
+        Lang::Nob => "no",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Swe => "sv",
+        Lang::Tam => "ta",
+        Lang::Tur => "tr",
+        _ => PLAIN,
+    };
+    language_for_locale(code)
+}
+
+/// Name the analyzer of a language is registered under
+pub fn tokenizer_name(language: Option<Language>) -> String {
+    format!("oxide_{}", language_code(language))
+}
+
+/// Lowercase, stem and fold accents, so "Pokémon" matches "pokemon"
+pub fn analyzer(language: Option<Language>) -> TextAnalyzer {
+    let builder = TextAnalyzer::builder(SimpleTokenizer::default())
+        .filter(RemoveLongFilter::limit(MAX_TOKEN_LENGTH))
+        .filter(LowerCaser)
+        .dynamic();
+    // Stemmers expect the accents folding would remove
+    let builder = match language {
+        Some(language) => builder.filter_dynamic(Stemmer::new(language)),
+        None => builder,
+    };
+    builder.filter_dynamic(AsciiFoldingFilter).build()
+}
+
+/// Terms of `text` as the analyzer indexes them
+pub fn tokenize(analyzer: &mut TextAnalyzer, text: &str) -> Vec<String> {
+    let mut stream = analyzer.token_stream(text);
+    let mut words = Vec::new();
+    while stream.advance() {
+        words.push(stream.token().text.clone());
+    }
+    words
+}
+
+/// Synonym groups with every phrase analyzed into index terms
+#[derive(Debug, Default)]
+pub struct Synonyms {
+    groups: Vec<Vec<Vec<String>>>,
+}
+
+impl Synonyms {
+    pub fn new(groups: &[Vec<String>], analyzer: &mut TextAnalyzer) -> Self {
+        let groups = groups
+            .iter()
+            .map(|group| {
+                let mut phrases: Vec<Vec<String>> = Vec::new();
+                for phrase in group.iter().map(|term| tokenize(analyzer, term)) {
+                    if !phrase.is_empty() && !phrases.contains(&phrase) {
+                        phrases.push(phrase);
+                    }
+                }
+                phrases
+            })
+            .filter(|phrases| phrases.len() > 1)
+            .collect();
+        Synonyms { groups }
+    }
+
+    /// The group with the longest phrase `words` starts with, and that phrase's length
+    pub fn longest_match(&self, words: &[String]) -> Option<(usize, &[Vec<String>])> {
+        self.groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .iter()
+                    .filter(|phrase| words.starts_with(phrase))
+                    .map(move |phrase| (phrase.len(), group.as_slice()))
+            })
+            .max_by_key(|&(len, _)| len)
+    }
+}
+
+fn settings_path() -> Result<PathBuf, OxideError> {
+    Ok(crate::oxide_dir()?.join(SETTINGS_FILE))
+}
+
+/// Read the search settings, defaults when none were saved
+pub fn load_settings() -> Result<SearchSettings, OxideError> {
+    Ok(persist::read_json_blocking(&settings_path()?)?.unwrap_or_default())
+}
+
+pub(crate) fn save_settings(settings: &SearchSettings) -> Result<(), OxideError> {
+    persist::write_json_blocking(&settings_path()?, settings)
+}
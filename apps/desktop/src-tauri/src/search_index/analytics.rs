@@ -0,0 +1,382 @@
+//! Opt-in local log of searches and the results users open
+//!
+//! Nothing is recorded until analytics are enabled. Events are appended to a
+//! JSON lines file under `~/.Oxide` and never leave the machine; reports and
+//! exports are computed from that file on demand.
+
+use crate::{oxide_dir, persist, OxideError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+const SETTINGS_FILE: &str = "search_analytics.json";
+const LOG_FILE: &str = "search_log.jsonl";
+/// Entries per list in a report
+const REPORT_TOP: usize = 20;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnalyticsSettings {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SearchEvent {
+    Search {
+        at: DateTime<Utc>,
+        query: String,
+        total: usize,
+        /// Whether structured filters narrowed the search
+        #[serde(default)]
+        filtered: bool,
+    },
+    Click {
+        at: DateTime<Utc>,
+        query: String,
+        game_id: String,
+        /// Position of the result in the list, starting at 0
+        position: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryCount {
+    pub query: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameClicks {
+    pub game_id: String,
+    pub clicks: usize,
+}
+
+/// Summary of the local search log
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchReport {
+    pub generated_at: Option<DateTime<Utc>>,
+    /// Time of the first and last logged event
+    pub first_event: Option<DateTime<Utc>>,
+    pub last_event: Option<DateTime<Utc>>,
+    pub searches: usize,
+    /// Searches with a query and no filters that found nothing
+    pub zero_result_searches: usize,
+    /// Searches that found nothing with filters applied, the filters may be to blame
+    #[serde(default)]
+    pub filtered_zero_result_searches: usize,
+    pub clicks: usize,
+    /// Searches followed by at least one click, over all searches with a query
+    pub click_through_rate: f64,
+    /// Average position of clicked results, starting at 0
+    pub average_click_position: Option<f64>,
+    pub top_queries: Vec<QueryCount>,
+    pub top_zero_result_queries: Vec<QueryCount>,
+    pub top_clicked_games: Vec<GameClicks>,
+}
+
+/// Lowercase and collapse whitespace, so "Half  Life" and "half life" count once
+fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+async fn load_settings() -> Result<AnalyticsSettings, OxideError> {
+    let path = oxide_dir()?.join(SETTINGS_FILE);
+    Ok(persist::read_json(&path).await?.unwrap_or_default())
+}
+
+async fn append_event(event: &SearchEvent) -> Result<(), OxideError> {
+    if !load_settings().await?.enabled {
+        return Ok(());
+    }
+
+    let dir = oxide_dir()?;
+    fs::create_dir_all(&dir).await?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(LOG_FILE))
+        .await?;
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+async fn read_events() -> Result<Vec<SearchEvent>, OxideError> {
+    let path = oxide_dir()?.join(LOG_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(&path).await?;
+    // A line cut short by a crash is skipped rather than failing the report
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Log a search when analytics are enabled
+pub async fn record_search(query: &str, total: usize, filtered: bool) -> Result<(), OxideError> {
+    append_event(&SearchEvent::Search {
+        at: Utc::now(),
+        query: normalize_query(query),
+        total,
+        filtered,
+    })
+    .await
+}
+
+/// Log that a search result was opened when analytics are enabled
+pub async fn record_click(query: &str, game_id: String, position: usize) -> Result<(), OxideError> {
+    append_event(&SearchEvent::Click {
+        at: Utc::now(),
+        query: normalize_query(query),
+        game_id,
+        position,
+    })
+    .await
+}
+
+/// Summarize the search log
+pub async fn search_report() -> Result<SearchReport, OxideError> {
+    let events = read_events().await?;
+    Ok(report(&events, Utc::now()))
+}
+
+/// Summarize logged events
+fn report(events: &[SearchEvent], generated_at: DateTime<Utc>) -> SearchReport {
+    let mut report = SearchReport {
+        generated_at: Some(generated_at),
+        ..SearchReport::default()
+    };
+
+    let mut queries: HashMap<&str, usize> = HashMap::new();
+    let mut zero_result: HashMap<&str, usize> = HashMap::new();
+    let mut games: HashMap<&str, usize> = HashMap::new();
+    let mut clicked_queries: HashMap<&str, usize> = HashMap::new();
+    let mut position_sum = 0;
+
+    for event in events {
+        let at = match event {
+            SearchEvent::Search { at, .. } | SearchEvent::Click { at, .. } => *at,
+        };
+        report.first_event = Some(report.first_event.map_or(at, |first| first.min(at)));
+        report.last_event = Some(report.last_event.map_or(at, |last| last.max(at)));
+
+        match event {
+            SearchEvent::Search {
+                query,
+                total,
+                filtered,
+                ..
+            } => {
+                report.searches += 1;
+                // Browsing without a query says nothing about what users look for
+                if query.is_empty() {
+                    continue;
+                }
+                *queries.entry(query).or_default() += 1;
+                // Nothing matching the filters is no sign the catalog lacks the game
+                if *total == 0 && *filtered {
+                    report.filtered_zero_result_searches += 1;
+                } else if *total == 0 {
+                    report.zero_result_searches += 1;
+                    *zero_result.entry(query).or_default() += 1;
+                }
+            }
+            SearchEvent::Click {
+                query,
+                game_id,
+                position,
+                ..
+            } => {
+                report.clicks += 1;
+                position_sum += position;
+                *games.entry(game_id).or_default() += 1;
+                *clicked_queries.entry(query).or_default() += 1;
+            }
+        }
+    }
+
+    // A click counts towards the searches for its query, at most once per search
+    let searches_with_query: usize = queries.values().sum();
+    let clicked_searches: usize = queries
+        .iter()
+        .map(|(query, &count)| clicked_queries.get(query).copied().unwrap_or(0).min(count))
+        .sum();
+    if searches_with_query > 0 {
+        report.click_through_rate = clicked_searches as f64 / searches_with_query as f64;
+    }
+    if report.clicks > 0 {
+        report.average_click_position = Some(position_sum as f64 / report.clicks as f64);
+    }
+
+    report.top_queries = top_queries(queries);
+    report.top_zero_result_queries = top_queries(zero_result);
+    let mut top_games: Vec<GameClicks> = games
+        .into_iter()
+        .map(|(game_id, clicks)| GameClicks {
+            game_id: game_id.to_string(),
+            clicks,
+        })
+        .collect();
+    top_games.sort_by(|a, b| {
+        b.clicks
+            .cmp(&a.clicks)
+            .then_with(|| a.game_id.cmp(&b.game_id))
+    });
+    top_games.truncate(REPORT_TOP);
+    report.top_clicked_games = top_games;
+    report
+}
+
+/// Most frequent queries first, ties in alphabetical order
+fn top_queries(counts: HashMap<&str, usize>) -> Vec<QueryCount> {
+    let mut top: Vec<QueryCount> = counts
+        .into_iter()
+        .map(|(query, count)| QueryCount {
+            query: query.to_string(),
+            count,
+        })
+        .collect();
+    top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.query.cmp(&b.query)));
+    top.truncate(REPORT_TOP);
+    top
+}
+
+/// Write the search report to a JSON file
+pub async fn export_search_report(destination: String) -> Result<String, OxideError> {
+    let report = search_report().await?;
+    let destination = PathBuf::from(destination);
+    fs::write(&destination, serde_json::to_string_pretty(&report)?).await?;
+
+    Ok(format!(
+        "Exported a report of {} searches to {}",
+        report.searches,
+        destination.display()
+    ))
+}
+
+/// Get whether search analytics are enabled
+pub async fn get_analytics_settings() -> Result<AnalyticsSettings, OxideError> {
+    load_settings().await
+}
+
+/// Opt in or out of search analytics, opting out deletes the log
+pub async fn set_analytics_enabled(enabled: bool) -> Result<AnalyticsSettings, OxideError> {
+    let settings = AnalyticsSettings { enabled };
+    persist::write_json(&oxide_dir()?.join(SETTINGS_FILE), &settings).await?;
+
+    if !enabled {
+        clear_search_log().await?;
+    }
+    Ok(settings)
+}
+
+/// Delete every logged search and click
+pub async fn clear_search_log() -> Result<(), OxideError> {
+    let path = oxide_dir()?.join(LOG_FILE);
+    if path.exists() {
+        fs::remove_file(&path).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, minute, 0).unwrap()
+    }
+
+    fn search(minute: u32, query: &str, total: usize, filtered: bool) -> SearchEvent {
+        SearchEvent::Search {
+            at: at(minute),
+            query: normalize_query(query),
+            total,
+            filtered,
+        }
+    }
+
+    fn click(minute: u32, query: &str, game_id: &str, position: usize) -> SearchEvent {
+        SearchEvent::Click {
+            at: at(minute),
+            query: normalize_query(query),
+            game_id: game_id.to_string(),
+            position,
+        }
+    }
+
+    #[test]
+    fn reports_queries_clicks_and_misses() {
+        let events = [
+            search(1, "Half  Life", 3, false),
+            click(2, "half life", "half-life", 0),
+            search(3, "half life", 3, false),
+            search(4, "", 40, true),
+            search(5, "stardew", 0, false),
+            search(6, "stardew", 0, false),
+            // Found nothing only because of the filters
+            search(7, "witcher", 0, true),
+            search(8, "witcher", 2, false),
+            click(9, "witcher", "witcher-3", 1),
+            click(10, "witcher", "witcher-2", 2),
+        ];
+        let report = report(&events, at(30));
+
+        assert_eq!(report.generated_at, Some(at(30)));
+        assert_eq!(
+            (report.first_event, report.last_event),
+            (Some(at(1)), Some(at(10)))
+        );
+        assert_eq!(report.searches, 7);
+        assert_eq!(report.zero_result_searches, 2);
+        assert_eq!(report.filtered_zero_result_searches, 1);
+        assert_eq!(report.clicks, 3);
+        // Clicks count against searches for the same query, at most one each
+        assert_eq!(report.click_through_rate, 0.5);
+        assert_eq!(report.average_click_position, Some(1.0));
+
+        let counts = |top: &[QueryCount]| -> Vec<(String, usize)> {
+            top.iter().map(|q| (q.query.clone(), q.count)).collect()
+        };
+        assert_eq!(
+            counts(&report.top_queries),
+            [
+                ("half life".to_string(), 2),
+                ("stardew".to_string(), 2),
+                ("witcher".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            counts(&report.top_zero_result_queries),
+            [("stardew".to_string(), 2)]
+        );
+        let games: Vec<(&str, usize)> = report
+            .top_clicked_games
+            .iter()
+            .map(|game| (game.game_id.as_str(), game.clicks))
+            .collect();
+        assert_eq!(
+            games,
+            [("half-life", 1), ("witcher-2", 1), ("witcher-3", 1)]
+        );
+    }
+
+    #[test]
+    fn empty_log_gives_empty_report() {
+        let report = report(&[], at(0));
+        assert_eq!(report.searches, 0);
+        assert_eq!(report.click_through_rate, 0.0);
+        assert!(report.first_event.is_none() && report.average_click_position.is_none());
+    }
+}
@@ -0,0 +1,132 @@
+//! Records other than games that the unified search box finds
+//!
+//! Developers, installed mods, achievements and friends are indexed next to the
+//! catalog, each document tagged with its kind. They are reduced to an `Entity`
+//! whose name, description and keywords map onto the title, description and
+//! tags fields games are searched by.
+
+use crate::mod_engine::ModDescriptor;
+use crate::models::{Achievement, Developer, Friend};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Game,
+    Developer,
+    Mod,
+    Achievement,
+    Friend,
+}
+
+impl EntityKind {
+    /// Every kind, in the order result groups are returned
+    pub const ALL: [EntityKind; 5] = [
+        EntityKind::Game,
+        EntityKind::Developer,
+        EntityKind::Mod,
+        EntityKind::Achievement,
+        EntityKind::Friend,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntityKind::Game => "game",
+            EntityKind::Developer => "developer",
+            EntityKind::Mod => "mod",
+            EntityKind::Achievement => "achievement",
+            EntityKind::Friend => "friend",
+        }
+    }
+}
+
+/// A searchable record reduced to what the index stores
+#[derive(Debug, Clone)]
+pub struct Entity {
+    pub kind: EntityKind,
+    /// Unique within its kind
+    pub id: String,
+    /// Game the record belongs to, if any
+    pub game_id: Option<String>,
+    pub name: String,
+    pub description: String,
+    pub keywords: Vec<String>,
+}
+
+impl From<&Developer> for Entity {
+    fn from(developer: &Developer) -> Self {
+        Entity {
+            kind: EntityKind::Developer,
+            id: developer.id.clone(),
+            game_id: None,
+            name: developer.name.clone(),
+            description: developer.bio.clone().unwrap_or_default(),
+            keywords: developer.location.iter().cloned().collect(),
+        }
+    }
+}
+
+impl From<&ModDescriptor> for Entity {
+    fn from(descriptor: &ModDescriptor) -> Self {
+        Entity {
+            kind: EntityKind::Mod,
+            // Mod ids are only unique per game
+            id: format!("{}/{}", descriptor.game_id, descriptor.mod_id),
+            game_id: Some(descriptor.game_id.clone()),
+            name: descriptor.mod_id.clone(),
+            description: descriptor.description.clone(),
+            keywords: vec![descriptor.author.clone()],
+        }
+    }
+}
+
+impl From<&Achievement> for Entity {
+    fn from(achievement: &Achievement) -> Self {
+        Entity {
+            kind: EntityKind::Achievement,
+            id: achievement.id.clone(),
+            game_id: Some(achievement.game_id.clone()),
+            name: achievement.title.clone(),
+            description: achievement.description.clone(),
+            keywords: achievement
+                .category
+                .iter()
+                .chain(&achievement.difficulty)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl From<&Friend> for Entity {
+    fn from(friend: &Friend) -> Self {
+        Entity {
+            kind: EntityKind::Friend,
+            id: friend.id.clone(),
+            game_id: None,
+            name: friend.username.clone(),
+            description: String::new(),
+            // Searching a game title also finds the friends playing it
+            keywords: friend.playing.iter().cloned().collect(),
+        }
+    }
+}
+
+/// A record matching a unified search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityHit {
+    pub kind: EntityKind,
+    pub id: String,
+    pub game_id: Option<String>,
+    pub name: String,
+    pub score: f32,
+}
+
+/// Best matches of one kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityGroup {
+    pub kind: EntityKind,
+    pub hits: Vec<EntityHit>,
+    /// Number of matching records of this kind, not just the returned hits
+    pub total: usize,
+}
@@ -0,0 +1,139 @@
+//! Search-as-you-type completions over titles, developers and tags
+//!
+//! Completions live in memory as a sorted list of normalized keys, so a lookup
+//! is a binary search for the prefix range. Every word of a name starts a key,
+//! which lets "tower" complete to "Pizza Tower".
+
+use crate::models::Game;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    Game,
+    Developer,
+    Tag,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub text: String,
+    pub kind: SuggestionKind,
+    /// Set for game suggestions
+    pub game_id: Option<String>,
+    /// Popularity the suggestion is ranked by
+    pub weight: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Suggester {
+    suggestions: Vec<Suggestion>,
+    /// Normalized key and the suggestion it completes to, sorted by key
+    keys: Vec<(String, usize)>,
+}
+
+impl Suggester {
+    /// Build completions from the catalog
+    ///
+    /// Games weigh their download count, developers and tags the downloads of
+    /// all their games.
+    pub fn from_catalog(games: &[Game]) -> Self {
+        let mut suggestions = Vec::new();
+        let mut developers: BTreeMap<&str, u64> = BTreeMap::new();
+        let mut tags: BTreeMap<&str, u64> = BTreeMap::new();
+
+        for game in games {
+            let downloads = u64::from(game.stats.download_count);
+            suggestions.push(Suggestion {
+                text: game.title.clone(),
+                kind: SuggestionKind::Game,
+                game_id: Some(game.id.clone()),
+                weight: downloads,
+            });
+            if let Some(developer) = &game.developer {
+                *developers.entry(developer).or_default() += downloads;
+            }
+            for tag in game.details.tags.iter().chain(&game.details.genres) {
+                *tags.entry(tag).or_default() += downloads;
+            }
+        }
+
+        for (kind, names) in [
+            (SuggestionKind::Developer, developers),
+            (SuggestionKind::Tag, tags),
+        ] {
+            suggestions.extend(names.into_iter().map(|(name, weight)| Suggestion {
+                text: name.to_string(),
+                kind,
+                game_id: None,
+                weight,
+            }));
+        }
+
+        let mut keys = Vec::new();
+        for (i, suggestion) in suggestions.iter().enumerate() {
+            let normalized = normalize(&suggestion.text);
+            let mut start = 0;
+            for word in normalized.split(' ') {
+                if !word.is_empty() {
+                    keys.push((normalized[start..].to_string(), i));
+                }
+                start += word.len() + 1;
+            }
+        }
+        keys.sort();
+
+        Suggester { suggestions, keys }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.suggestions.is_empty()
+    }
+
+    /// Completions for a prefix, best first
+    ///
+    /// Names that start with the prefix rank above names where a later word
+    /// does, then popularity decides.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let prefix = normalize(prefix);
+        if prefix.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let start = self
+            .keys
+            .partition_point(|(key, _)| key.as_str() < prefix.as_str());
+        let mut seen = HashSet::new();
+        let mut matches: Vec<(bool, &Suggestion)> = self.keys[start..]
+            .iter()
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .filter(|(_, i)| seen.insert(*i))
+            .map(|(_, i)| {
+                let suggestion = &self.suggestions[*i];
+                (normalize(&suggestion.text).starts_with(&prefix), suggestion)
+            })
+            .collect();
+
+        matches.sort_by(|(a_leading, a), (b_leading, b)| {
+            b_leading
+                .cmp(a_leading)
+                .then(b.weight.cmp(&a.weight))
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, suggestion)| suggestion.clone())
+            .collect()
+    }
+}
+
+/// Lowercase and collapse everything but letters and digits into single spaces
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
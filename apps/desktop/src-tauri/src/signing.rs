@@ -0,0 +1,539 @@
+//! Publisher signatures for mods and game builds
+//!
+//! Publishers sign artifacts with an ed25519 key and ship the signature in a
+//! detached `<artifact>.sig` file. The signed message is the SHA-256 digest of
+//! the artifact, or for an unpacked mod folder the digest of its file listing as
+//! printed by `oxide-mod hash`. The launcher checks signatures against a local
+//! trust store of publisher keys and applies the configured policy.
+
+use crate::mod_engine::conflicts::ModFileEntry;
+use crate::mod_engine::package;
+use crate::{persist, OxideError};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs as std_fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const SIGNATURE_EXTENSION: &str = "sig";
+const ALGORITHM: &str = "ed25519";
+
+/// Contents of a detached `.sig` file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetachedSignature {
+    pub publisher: String,
+    pub key_id: String,
+    pub algorithm: String,
+    /// Base64 encoded signature over the artifact digest
+    pub signature: String,
+}
+
+/// A publisher's signing key as written by `oxide-mod keygen`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublisherKey {
+    pub publisher: String,
+    pub key_id: String,
+    /// Base64 encoded public key, the value to add to a trust store
+    pub public_key: String,
+    /// Base64 encoded secret key, never leaves the publisher
+    pub secret_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedKey {
+    pub key_id: String,
+    pub public_key: String,
+    pub added_at: DateTime<Utc>,
+}
+
+/// What to do with artifacts that are not signed by a trusted publisher
+///
+/// Signatures that fail to verify are refused under every policy except `Off`,
+/// as they mean the artifact was modified after signing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePolicy {
+    /// Refuse unsigned artifacts and unknown keys
+    Require,
+    /// Install, but report unsigned artifacts and unknown keys
+    #[default]
+    Warn,
+    /// Skip verification entirely
+    Off,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrustStore {
+    #[serde(default)]
+    pub policy: SignaturePolicy,
+    /// Trusted keys per publisher
+    #[serde(default)]
+    pub publishers: BTreeMap<String, Vec<TrustedKey>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Verified {
+        publisher: String,
+        key_id: String,
+    },
+    Unsigned,
+    UntrustedKey {
+        publisher: String,
+        key_id: String,
+    },
+    Invalid {
+        reason: String,
+    },
+    /// The policy is `Off`
+    Skipped,
+}
+
+impl SignatureStatus {
+    /// Warning to surface when the artifact is installed anyway
+    pub fn warning(&self) -> Option<String> {
+        match self {
+            SignatureStatus::Unsigned => Some("not signed".to_string()),
+            SignatureStatus::UntrustedKey { publisher, key_id } => Some(format!(
+                "signed by {} with key {}, which is not in the trust store",
+                publisher, key_id
+            )),
+            SignatureStatus::Invalid { reason } => Some(format!("invalid signature: {}", reason)),
+            SignatureStatus::Verified { .. } | SignatureStatus::Skipped => None,
+        }
+    }
+}
+
+impl PublisherKey {
+    /// Generate a new signing key for a publisher
+    pub fn generate(publisher: &str) -> Self {
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let verifying_key = signing_key.verifying_key();
+        PublisherKey {
+            publisher: publisher.to_string(),
+            key_id: key_id(&verifying_key),
+            public_key: BASE64.encode(verifying_key.to_bytes()),
+            secret_key: BASE64.encode(signing_key.to_bytes()),
+        }
+    }
+
+    /// Write the key to a new file that only the current user can read
+    pub fn save(&self, path: &Path) -> Result<(), OxideError> {
+        let mut options = std_fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Sign `artifact` and write the detached signature next to it
+    pub fn sign(&self, artifact: &Path) -> Result<PathBuf, OxideError> {
+        let secret: [u8; 32] = BASE64
+            .decode(&self.secret_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| OxideError::Signature("Malformed secret key".to_string()))?;
+        let signing_key = SigningKey::from_bytes(&secret);
+
+        let signature = DetachedSignature {
+            publisher: self.publisher.clone(),
+            key_id: key_id(&signing_key.verifying_key()),
+            algorithm: ALGORITHM.to_string(),
+            signature: BASE64.encode(signing_key.sign(&artifact_digest(artifact)?).to_bytes()),
+        };
+
+        let path = signature_path(artifact);
+        std_fs::write(&path, serde_json::to_string_pretty(&signature)?)?;
+        Ok(path)
+    }
+}
+
+/// Short identifier of a public key, the first 16 hex digits of its SHA-256
+pub fn key_id(key: &VerifyingKey) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))[..16].to_string()
+}
+
+/// Path of the detached signature for an artifact
+pub fn signature_path(artifact: &Path) -> PathBuf {
+    let mut name = artifact
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    artifact.with_file_name(name)
+}
+
+/// Digest a signature covers
+fn artifact_digest(artifact: &Path) -> Result<Vec<u8>, OxideError> {
+    if artifact.is_dir() {
+        Ok(listing_digest(&package::hash_files(artifact)?))
+    } else {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std_fs::File::open(artifact)?, &mut hasher)?;
+        Ok(hasher.finalize().to_vec())
+    }
+}
+
+/// Digest of a mod folder's file listing, `mod.json` first and then by path
+pub(crate) fn listing_digest(files: &[ModFileEntry]) -> Vec<u8> {
+    let mut files: Vec<&ModFileEntry> = files.iter().collect();
+    files.sort_by(|a, b| {
+        (a.path != package::DESCRIPTOR_FILE, &a.path)
+            .cmp(&(b.path != package::DESCRIPTOR_FILE, &b.path))
+    });
+    let listing: String = files
+        .iter()
+        .map(|entry| format!("{}  {}\n", entry.sha256, entry.path))
+        .collect();
+    Sha256::digest(listing.as_bytes()).to_vec()
+}
+
+/// Check the detached signature of an artifact against a trust store
+pub fn verify_artifact(artifact: &Path, store: &TrustStore) -> Result<SignatureStatus, OxideError> {
+    verify_with(artifact, store, || artifact_digest(artifact))
+}
+
+/// Check the signature next to `artifact` against the digest `digest` computes
+///
+/// The digest is only computed when there is a trusted key to check it with.
+fn verify_with<D>(
+    artifact: &Path,
+    store: &TrustStore,
+    digest: D,
+) -> Result<SignatureStatus, OxideError>
+where
+    D: FnOnce() -> Result<Vec<u8>, OxideError>,
+{
+    let path = signature_path(artifact);
+    if !path.exists() {
+        return Ok(SignatureStatus::Unsigned);
+    }
+
+    let invalid = |reason: &str| {
+        Ok(SignatureStatus::Invalid {
+            reason: reason.to_string(),
+        })
+    };
+    let signature: DetachedSignature = match serde_json::from_str(&std_fs::read_to_string(&path)?) {
+        Ok(signature) => signature,
+        Err(_) => return invalid("signature file is malformed"),
+    };
+    if signature.algorithm != ALGORITHM {
+        return invalid("unsupported signature algorithm");
+    }
+
+    let trusted = store
+        .publishers
+        .get(&signature.publisher)
+        .and_then(|keys| keys.iter().find(|key| key.key_id == signature.key_id));
+    let trusted = match trusted {
+        Some(key) => key,
+        None => {
+            return Ok(SignatureStatus::UntrustedKey {
+                publisher: signature.publisher,
+                key_id: signature.key_id,
+            })
+        }
+    };
+
+    let verifying_key = decode_public_key(&trusted.public_key)?;
+    let signature_bytes = match BASE64.decode(&signature.signature) {
+        Ok(bytes) => bytes,
+        Err(_) => return invalid("signature is not valid base64"),
+    };
+    let ed_signature = match Signature::from_slice(&signature_bytes) {
+        Ok(sig) => sig,
+        Err(_) => return invalid("signature has the wrong length"),
+    };
+
+    if verifying_key.verify(&digest()?, &ed_signature).is_err() {
+        return invalid("artifact does not match its signature");
+    }
+
+    Ok(SignatureStatus::Verified {
+        publisher: signature.publisher,
+        key_id: signature.key_id,
+    })
+}
+
+/// Verify an artifact from its digest and apply the trust store policy
+///
+/// `digest` must be computed from the very bytes that get installed, e.g. while
+/// they were downloaded or copied aside, so the file cannot be swapped between
+/// the check and the install. Returns the status when installation may
+/// proceed; its `warning` is for the caller to report along with the result.
+pub(crate) async fn check_digest(
+    artifact: &Path,
+    digest: &[u8],
+    what: &str,
+) -> Result<SignatureStatus, OxideError> {
+    let store = load_trust_store().await?;
+    if store.policy == SignaturePolicy::Off {
+        return Ok(SignatureStatus::Skipped);
+    }
+
+    let status = verify_with(artifact, &store, || Ok(digest.to_vec()))?;
+    apply_policy(status, store.policy, what)
+}
+
+/// Refuse `what` unless `status` is acceptable under `policy`
+fn apply_policy(
+    status: SignatureStatus,
+    policy: SignaturePolicy,
+    what: &str,
+) -> Result<SignatureStatus, OxideError> {
+    match (&status, policy) {
+        (_, SignaturePolicy::Off) | (SignatureStatus::Verified { .. }, _) => Ok(status),
+        (SignatureStatus::Invalid { reason }, _) => Err(OxideError::Signature(format!(
+            "Refusing to install {}: {}",
+            what, reason
+        ))),
+        (_, SignaturePolicy::Require) => Err(OxideError::Signature(format!(
+            "Refusing to install {}: {}",
+            what,
+            status.warning().unwrap_or_default()
+        ))),
+        (_, SignaturePolicy::Warn) => Ok(status),
+    }
+}
+
+fn decode_public_key(public_key: &str) -> Result<VerifyingKey, OxideError> {
+    BASE64
+        .decode(public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| OxideError::Signature("Malformed public key".to_string()))
+}
+
+fn trust_store_path() -> Result<PathBuf, OxideError> {
+    Ok(crate::oxide_dir()?.join("trust_store.json"))
+}
+
+async fn load_trust_store() -> Result<TrustStore, OxideError> {
+    Ok(persist::read_json(&trust_store_path()?)
+        .await?
+        .unwrap_or_default())
+}
+
+async fn save_trust_store(store: &TrustStore) -> Result<(), OxideError> {
+    persist::write_json(&trust_store_path()?, store).await
+}
+
+/// Get the trust store
+pub async fn get_trust_store() -> Result<TrustStore, OxideError> {
+    load_trust_store().await
+}
+
+/// Trust a publisher's public key
+pub async fn trust_publisher_key(
+    publisher: String,
+    public_key: String,
+) -> Result<TrustStore, OxideError> {
+    if publisher.trim().is_empty() {
+        return Err(OxideError::Signature(
+            "Publisher name must not be empty".to_string(),
+        ));
+    }
+    let key_id = key_id(&decode_public_key(&public_key)?);
+
+    let mut store = load_trust_store().await?;
+    let keys = store.publishers.entry(publisher).or_default();
+    if !keys.iter().any(|key| key.key_id == key_id) {
+        keys.push(TrustedKey {
+            key_id,
+            public_key,
+            added_at: Utc::now(),
+        });
+        save_trust_store(&store).await?;
+    }
+    Ok(store)
+}
+
+/// Remove a publisher key from the trust store
+pub async fn revoke_publisher_key(
+    publisher: String,
+    key_id: String,
+) -> Result<TrustStore, OxideError> {
+    let mut store = load_trust_store().await?;
+    let keys = store
+        .publishers
+        .get_mut(&publisher)
+        .ok_or_else(|| OxideError::NotFound(format!("Publisher not trusted: {}", publisher)))?;
+
+    let before = keys.len();
+    keys.retain(|key| key.key_id != key_id);
+    if keys.len() == before {
+        return Err(OxideError::NotFound(format!(
+            "Key {} not trusted for {}",
+            key_id, publisher
+        )));
+    }
+    if keys.is_empty() {
+        store.publishers.remove(&publisher);
+    }
+
+    save_trust_store(&store).await?;
+    Ok(store)
+}
+
+/// Set how unsigned artifacts are handled
+pub async fn set_signature_policy(policy: SignaturePolicy) -> Result<TrustStore, OxideError> {
+    let mut store = load_trust_store().await?;
+    store.policy = policy;
+    save_trust_store(&store).await?;
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mod_engine::archive;
+
+    #[test]
+    fn listing_digest_matches_folder_digest() {
+        let source = tempfile::tempdir().unwrap();
+        std_fs::create_dir_all(source.path().join("data")).unwrap();
+        std_fs::write(source.path().join("mod.json"), "{}").unwrap();
+        std_fs::write(source.path().join("data/a.txt"), "a").unwrap();
+        std_fs::write(source.path().join("README.md"), "readme").unwrap();
+
+        // Listed in another order than the folder walk
+        let files: Vec<ModFileEntry> = ["data/a.txt", "README.md", "mod.json"]
+            .iter()
+            .map(|path| {
+                let contents = std_fs::read(source.path().join(path)).unwrap();
+                ModFileEntry {
+                    path: path.to_string(),
+                    sha256: format!("{:x}", Sha256::digest(&contents)),
+                    size: contents.len() as u64,
+                }
+            })
+            .collect();
+        assert_eq!(
+            listing_digest(&files),
+            artifact_digest(source.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn copy_digest_matches_file_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("mod.zip");
+        std_fs::write(&source, b"not really a zip").unwrap();
+
+        let copy = dir.path().join("copy");
+        let digest = archive::copy_hashed(&source, &copy).unwrap();
+        assert_eq!(digest, artifact_digest(&source).unwrap());
+        assert_eq!(std_fs::read(&copy).unwrap(), b"not really a zip");
+    }
+
+    /// A freshly generated key trusted by an in-memory store
+    fn trusted_key() -> (PublisherKey, TrustStore) {
+        let key = PublisherKey::generate("Example Studio");
+        let mut store = TrustStore {
+            policy: SignaturePolicy::Require,
+            ..TrustStore::default()
+        };
+        store.publishers.insert(
+            key.publisher.clone(),
+            vec![TrustedKey {
+                key_id: key.key_id.clone(),
+                public_key: key.public_key.clone(),
+                added_at: Utc::now(),
+            }],
+        );
+        (key, store)
+    }
+
+    #[test]
+    fn signed_artifacts_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let (key, store) = trusted_key();
+
+        let archive = dir.path().join("mod.zip");
+        std_fs::write(&archive, b"archive bytes").unwrap();
+        key.sign(&archive).unwrap();
+        let verified = SignatureStatus::Verified {
+            publisher: key.publisher.clone(),
+            key_id: key.key_id.clone(),
+        };
+        assert_eq!(verify_artifact(&archive, &store).unwrap(), verified);
+
+        let folder = dir.path().join("mod");
+        std_fs::create_dir_all(&folder).unwrap();
+        std_fs::write(folder.join("mod.json"), "{}").unwrap();
+        std_fs::write(folder.join("data.txt"), "data").unwrap();
+        key.sign(&folder).unwrap();
+        assert_eq!(verify_artifact(&folder, &store).unwrap(), verified);
+        assert_eq!(
+            apply_policy(verified.clone(), SignaturePolicy::Require, "mod").unwrap(),
+            verified
+        );
+    }
+
+    #[test]
+    fn require_refuses_invalid_untrusted_and_unsigned() {
+        let dir = tempfile::tempdir().unwrap();
+        let (key, store) = trusted_key();
+        let archive = dir.path().join("mod.zip");
+        std_fs::write(&archive, b"archive bytes").unwrap();
+        key.sign(&archive).unwrap();
+
+        // Modified after signing
+        std_fs::write(&archive, b"other bytes").unwrap();
+        let tampered = verify_artifact(&archive, &store).unwrap();
+        assert!(matches!(tampered, SignatureStatus::Invalid { .. }));
+        for policy in [SignaturePolicy::Require, SignaturePolicy::Warn] {
+            assert!(matches!(
+                apply_policy(tampered.clone(), policy, "mod"),
+                Err(OxideError::Signature(_))
+            ));
+        }
+
+        let stranger = PublisherKey::generate("Example Studio");
+        stranger.sign(&archive).unwrap();
+        let untrusted = verify_artifact(&archive, &store).unwrap();
+        assert!(matches!(untrusted, SignatureStatus::UntrustedKey { .. }));
+        assert!(apply_policy(untrusted.clone(), SignaturePolicy::Require, "mod").is_err());
+        let allowed = apply_policy(untrusted, SignaturePolicy::Warn, "mod").unwrap();
+        assert!(allowed.warning().is_some());
+
+        std_fs::remove_file(signature_path(&archive)).unwrap();
+        let unsigned = verify_artifact(&archive, &store).unwrap();
+        assert_eq!(unsigned, SignatureStatus::Unsigned);
+        assert!(apply_policy(unsigned, SignaturePolicy::Require, "mod").is_err());
+    }
+
+    #[test]
+    fn saved_keys_are_private_and_never_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("publisher.key");
+        let key = PublisherKey::generate("Example Studio");
+        key.save(&path).unwrap();
+
+        let saved: PublisherKey =
+            serde_json::from_str(&std_fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.secret_key, key.secret_key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std_fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(PublisherKey::generate("Other").save(&path).is_err());
+    }
+}
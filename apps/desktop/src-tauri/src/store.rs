@@ -0,0 +1,371 @@
+//! Embedded database for local state
+//!
+//! The library, install records, installed mods, play sessions and sync state
+//! are kept in a SQLite database at `~/.Oxide/oxide.db`. Opening it brings the
+//! schema up to date, see `migrations`, and when it is first created the JSON
+//! files this state used to be kept in are imported, see `import`. Settings
+//! stay in their JSON files.
+
+mod import;
+mod migrations;
+
+use crate::mod_engine::ModDescriptor;
+use crate::sync::{FieldTimes, GameLibrary};
+use crate::OxideError;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const DATABASE_FILE: &str = "oxide.db";
+
+/// Sync state keys, see `get_state`
+pub(crate) const SYNC_STATUS_KEY: &str = "sync_status";
+pub(crate) const SYNC_BASE_KEY: &str = "sync_base";
+pub(crate) const SYNC_CONFLICTS_KEY: &str = "sync_conflicts";
+pub(crate) const SYNC_QUEUE_KEY: &str = "sync_queue";
+/// When the library was last written, edits since the last sync are stamped with it
+const LIBRARY_WRITTEN_KEY: &str = "library_written_at";
+
+/// Opened on first use and kept open for the life of the process
+static DATABASE: Mutex<Option<Connection>> = Mutex::new(None);
+
+/// A game build the downloader installed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallRecord {
+    pub game_id: String,
+    pub install_dir: PathBuf,
+    /// The downloaded file
+    pub artifact: PathBuf,
+    pub installed_at: DateTime<Utc>,
+}
+
+/// One run of a game, between `begin_game_session` and `end_game_session`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaySession {
+    pub id: i64,
+    pub game_id: String,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the game runs, or when the app quit before it ended
+    pub ended_at: Option<DateTime<Utc>>,
+    pub duration_secs: Option<u64>,
+}
+
+impl PlaySession {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let started_at: DateTime<Utc> = row.get(2)?;
+        let ended_at: Option<DateTime<Utc>> = row.get(3)?;
+        Ok(PlaySession {
+            id: row.get(0)?,
+            game_id: row.get(1)?,
+            started_at,
+            ended_at,
+            duration_secs: ended_at.map(|ended| (ended - started_at).num_seconds().max(0) as u64),
+        })
+    }
+}
+
+/// Run `f` with the database, opening it on first use
+fn with_db<T>(f: impl FnOnce(&mut Connection) -> Result<T, OxideError>) -> Result<T, OxideError> {
+    let mut database = DATABASE
+        .lock()
+        .map_err(|_| OxideError::General("Database lock poisoned".to_string()))?;
+    if database.is_none() {
+        *database = Some(open(&crate::oxide_dir()?)?);
+    }
+    f(database.as_mut().expect("database was opened above"))
+}
+
+/// Open the database in `oxide_dir`, creating it from the JSON files there if need be
+fn open(oxide_dir: &Path) -> Result<Connection, OxideError> {
+    std::fs::create_dir_all(oxide_dir)?;
+    let mut connection = Connection::open(oxide_dir.join(DATABASE_FILE))?;
+    // Another instance of the app may be writing, wait for it rather than fail
+    connection.busy_timeout(Duration::from_secs(5))?;
+    connection
+        .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+
+    let tx = connection.transaction()?;
+    if migrations::migrate(&tx)? == 0 {
+        import::import_json_state(&tx, oxide_dir)?;
+    }
+    tx.commit()?;
+    Ok(connection)
+}
+
+/// Run database work off the async runtime, it blocks while another query runs
+pub(crate) async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, OxideError> + Send + 'static,
+) -> Result<T, OxideError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| OxideError::General(format!("Database task failed: {}", e)))?
+}
+
+/// Read the sync state stored under `key`, `None` when nothing was stored yet
+pub(crate) fn get_state<T: DeserializeOwned>(key: &str) -> Result<Option<T>, OxideError> {
+    with_db(|db| read_state(db, key))
+}
+
+/// Store sync state under `key`, replacing what was stored before
+pub(crate) fn set_state<T: Serialize + ?Sized>(key: &str, value: &T) -> Result<(), OxideError> {
+    with_db(|db| write_state(db, key, value))
+}
+
+fn read_state<T: DeserializeOwned>(db: &Connection, key: &str) -> Result<Option<T>, OxideError> {
+    let value: Option<String> = db
+        .query_row(
+            "SELECT value FROM sync_state WHERE key = ?1",
+            [key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value
+        .map(|value| serde_json::from_str(&value))
+        .transpose()?)
+}
+
+fn write_state<T: Serialize + ?Sized>(
+    db: &Connection,
+    key: &str,
+    value: &T,
+) -> Result<(), OxideError> {
+    db.execute(
+        "INSERT OR REPLACE INTO sync_state (key, value) VALUES (?1, ?2)",
+        params![key, serde_json::to_string(value)?],
+    )?;
+    Ok(())
+}
+
+/// Load the library, empty until one was saved
+pub(crate) fn load_library() -> Result<GameLibrary, OxideError> {
+    with_db(|db| read_library(db))
+}
+
+fn read_library(db: &Connection) -> Result<GameLibrary, OxideError> {
+    let mut games = Vec::new();
+    let mut statement = db.prepare("SELECT game FROM library_games ORDER BY position")?;
+    for game in statement.query_map([], |row| row.get::<_, String>(0))? {
+        games.push(serde_json::from_str(&game?)?);
+    }
+
+    let mut modified: BTreeMap<String, FieldTimes> = BTreeMap::new();
+    let mut statement =
+        db.prepare("SELECT game_id, field, modified_at FROM library_field_times")?;
+    let times = statement.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?))
+    })?;
+    for time in times {
+        let (game_id, field, modified_at) = time?;
+        modified
+            .entry(game_id)
+            .or_default()
+            .insert(field, modified_at);
+    }
+
+    Ok(GameLibrary { games, modified })
+}
+
+/// Replace the library
+pub(crate) fn save_library(library: &GameLibrary) -> Result<(), OxideError> {
+    with_db(|db| {
+        let tx = db.transaction()?;
+        write_library(&tx, library, Utc::now())?;
+        tx.commit()?;
+        Ok(())
+    })
+}
+
+fn write_library(
+    db: &Connection,
+    library: &GameLibrary,
+    written_at: DateTime<Utc>,
+) -> Result<(), OxideError> {
+    db.execute("DELETE FROM library_games", [])?;
+    db.execute("DELETE FROM library_field_times", [])?;
+    for (position, game) in library.games.iter().enumerate() {
+        db.execute(
+            "INSERT OR REPLACE INTO library_games (id, position, game) VALUES (?1, ?2, ?3)",
+            params![game.id, position as i64, serde_json::to_string(game)?],
+        )?;
+    }
+    for (game_id, times) in &library.modified {
+        for (field, modified_at) in times {
+            db.execute(
+                "INSERT INTO library_field_times (game_id, field, modified_at)
+                 VALUES (?1, ?2, ?3)",
+                params![game_id, field, modified_at],
+            )?;
+        }
+    }
+    write_state(db, LIBRARY_WRITTEN_KEY, &written_at)
+}
+
+/// When the library was last saved, `None` before it ever was
+pub(crate) fn library_written_at() -> Result<Option<DateTime<Utc>>, OxideError> {
+    get_state(LIBRARY_WRITTEN_KEY)
+}
+
+/// Record that a game was installed, replacing any earlier install of it
+pub(crate) fn record_install(record: &InstallRecord) -> Result<(), OxideError> {
+    with_db(|db| {
+        db.execute(
+            "INSERT OR REPLACE INTO installs (game_id, install_dir, artifact, installed_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                record.game_id,
+                record.install_dir.to_string_lossy(),
+                record.artifact.to_string_lossy(),
+                record.installed_at
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+/// Get where a game was installed, `None` when it was not installed by the app
+pub(crate) fn get_install(game_id: &str) -> Result<Option<InstallRecord>, OxideError> {
+    with_db(|db| {
+        Ok(db
+            .query_row(
+                "SELECT game_id, install_dir, artifact, installed_at FROM installs
+                 WHERE game_id = ?1",
+                [game_id],
+                |row| {
+                    Ok(InstallRecord {
+                        game_id: row.get(0)?,
+                        install_dir: PathBuf::from(row.get::<_, String>(1)?),
+                        artifact: PathBuf::from(row.get::<_, String>(2)?),
+                        installed_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?)
+    })
+}
+
+/// Descriptors of the mods installed for a game, sorted by mod id
+pub(crate) fn installed_mods(game_id: &str) -> Result<Vec<ModDescriptor>, OxideError> {
+    with_db(|db| {
+        let mut statement =
+            db.prepare("SELECT descriptor FROM mods WHERE game_id = ?1 ORDER BY mod_id")?;
+        let descriptors = statement.query_map([game_id], |row| row.get::<_, String>(0))?;
+        descriptors
+            .map(|descriptor| Ok(serde_json::from_str(&descriptor?)?))
+            .collect()
+    })
+}
+
+/// Descriptors of the mods installed for every game, sorted by game and mod id
+pub(crate) fn all_installed_mods() -> Result<Vec<ModDescriptor>, OxideError> {
+    with_db(|db| {
+        let mut statement = db.prepare("SELECT descriptor FROM mods ORDER BY game_id, mod_id")?;
+        let descriptors = statement.query_map([], |row| row.get::<_, String>(0))?;
+        descriptors
+            .map(|descriptor| Ok(serde_json::from_str(&descriptor?)?))
+            .collect()
+    })
+}
+
+/// Descriptor of an installed mod, `None` when it is not installed
+pub(crate) fn get_mod(game_id: &str, mod_id: &str) -> Result<Option<ModDescriptor>, OxideError> {
+    with_db(|db| {
+        let descriptor: Option<String> = db
+            .query_row(
+                "SELECT descriptor FROM mods WHERE game_id = ?1 AND mod_id = ?2",
+                [game_id, mod_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(descriptor
+            .map(|descriptor| serde_json::from_str(&descriptor))
+            .transpose()?)
+    })
+}
+
+/// Record a mod as installed for a game, replacing an installed version of it
+pub(crate) fn save_mod(game_id: &str, descriptor: &ModDescriptor) -> Result<(), OxideError> {
+    with_db(|db| write_mod(db, game_id, descriptor, Utc::now()))
+}
+
+fn write_mod(
+    db: &Connection,
+    game_id: &str,
+    descriptor: &ModDescriptor,
+    installed_at: DateTime<Utc>,
+) -> Result<(), OxideError> {
+    db.execute(
+        "INSERT OR REPLACE INTO mods (game_id, mod_id, descriptor, installed_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            game_id,
+            descriptor.mod_id,
+            serde_json::to_string(descriptor)?,
+            installed_at
+        ],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn remove_mod(game_id: &str, mod_id: &str) -> Result<(), OxideError> {
+    with_db(|db| {
+        db.execute(
+            "DELETE FROM mods WHERE game_id = ?1 AND mod_id = ?2",
+            [game_id, mod_id],
+        )?;
+        Ok(())
+    })
+}
+
+/// Record that a game was launched
+pub(crate) fn start_play_session(game_id: &str) -> Result<PlaySession, OxideError> {
+    with_db(|db| {
+        let started_at = Utc::now();
+        db.execute(
+            "INSERT INTO play_sessions (game_id, started_at) VALUES (?1, ?2)",
+            params![game_id, started_at],
+        )?;
+        Ok(PlaySession {
+            id: db.last_insert_rowid(),
+            game_id: game_id.to_string(),
+            started_at,
+            ended_at: None,
+            duration_secs: None,
+        })
+    })
+}
+
+/// Record that a game exited, ending its latest session still open
+///
+/// `None` when no session was open, e.g. when the game was launched outside the app.
+pub(crate) fn end_play_session(game_id: &str) -> Result<Option<PlaySession>, OxideError> {
+    with_db(|db| {
+        Ok(db
+            .query_row(
+                "UPDATE play_sessions SET ended_at = ?2
+                 WHERE id = (SELECT id FROM play_sessions
+                             WHERE game_id = ?1 AND ended_at IS NULL
+                             ORDER BY started_at DESC LIMIT 1)
+                 RETURNING id, game_id, started_at, ended_at",
+                params![game_id, Utc::now()],
+                PlaySession::from_row,
+            )
+            .optional()?)
+    })
+}
+
+/// Sessions of a game, most recent first
+pub(crate) fn play_sessions(game_id: &str) -> Result<Vec<PlaySession>, OxideError> {
+    with_db(|db| {
+        let mut statement = db.prepare(
+            "SELECT id, game_id, started_at, ended_at FROM play_sessions
+             WHERE game_id = ?1 ORDER BY started_at DESC",
+        )?;
+        let sessions = statement.query_map([game_id], PlaySession::from_row)?;
+        Ok(sessions.collect::<Result<_, _>>()?)
+    })
+}
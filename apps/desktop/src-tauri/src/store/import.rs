@@ -0,0 +1,219 @@
+//! One-time import of the JSON files state was kept in before the database
+//!
+//! Runs in the transaction creating the schema, so an import that fails is
+//! tried again the next time the app starts. A file that cannot be read is
+//! skipped with a warning instead, it would otherwise keep the app from
+//! starting. The files are left in place for older versions of the app.
+
+use super::{
+    write_library, write_mod, write_state, SYNC_BASE_KEY, SYNC_CONFLICTS_KEY, SYNC_QUEUE_KEY,
+    SYNC_STATUS_KEY,
+};
+use crate::mod_engine::ModDescriptor;
+use crate::sync::GameLibrary;
+use crate::{persist, OxideError};
+use chrono::{DateTime, Utc};
+use rusqlite::Transaction;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+const METADATA_SUFFIX: &str = "_metadata.json";
+
+pub(super) fn import_json_state(tx: &Transaction, oxide_dir: &Path) -> Result<(), OxideError> {
+    let library_file = oxide_dir.join("library.json");
+    if let Some(library) = read_or_skip::<GameLibrary>(&library_file) {
+        // Edits made before the import still count as made when the file was written
+        write_library(tx, &library, modified_at(&library_file))?;
+    }
+
+    let sync_dir = oxide_dir.join("Sync");
+    for (file, key) in [
+        ("status.json", SYNC_STATUS_KEY),
+        ("base.json", SYNC_BASE_KEY),
+        ("conflicts.json", SYNC_CONFLICTS_KEY),
+        ("queue.json", SYNC_QUEUE_KEY),
+    ] {
+        if let Some(value) = read_or_skip::<Value>(&sync_dir.join(file)) {
+            write_state(tx, key, &value)?;
+        }
+    }
+
+    let mods_root = oxide_dir.join("Mods");
+    if mods_root.exists() {
+        for game_dir in fs::read_dir(&mods_root)? {
+            let game_dir = game_dir?.path();
+            let Some(game_id) = game_dir.file_name().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if !game_dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&game_dir)? {
+                let path = entry?.path();
+                let is_metadata = path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|name| name.ends_with(METADATA_SUFFIX));
+                if !path.is_file() || !is_metadata {
+                    continue;
+                }
+                if let Some(descriptor) = read_or_skip::<ModDescriptor>(&path) {
+                    write_mod(tx, game_id, &descriptor, modified_at(&path))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_or_skip<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    persist::read_json_blocking(path).unwrap_or_else(|e| {
+        eprintln!("Skipped importing {}: {}", path.display(), e);
+        None
+    })
+}
+
+fn modified_at(path: &Path) -> DateTime<Utc> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open, read_library, read_state, LIBRARY_WRITTEN_KEY};
+    use super::*;
+    use crate::sync::FieldTimes;
+    use chrono::TimeZone;
+    use rusqlite::Connection;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn write_json(path: &Path, value: &Value) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, serde_json::to_vec_pretty(value).unwrap()).unwrap();
+    }
+
+    fn game(id: &str, title: &str) -> Value {
+        json!({
+            "id": id,
+            "title": title,
+            "slug": id,
+            "media": { "coverImage": "", "screenshots": [], "iconUrl": "", "title": "" },
+            "pricing": { "basePrice": 10.0, "currentPrice": 10.0 },
+            "details": { "platforms": [], "genres": [], "tags": [] },
+            "stats": { "rating": 4.0, "reviewCount": 0, "downloadCount": 0, "wishlistCount": 0 },
+            "features": {
+                "controller": false, "cloudPlay": false, "offline": true, "multiPlayer": false,
+                "singlePlayer": true, "achievements": false, "workshop": false
+            }
+        })
+    }
+
+    fn descriptor(game_id: &str, mod_id: &str) -> Value {
+        json!({
+            "mod_id": mod_id,
+            "game_id": game_id,
+            "dependencies": [],
+            "description": "",
+            "version": "1.0.0",
+            "author": "someone"
+        })
+    }
+
+    /// Game and mod id of every installed mod, with when it was installed
+    fn mods(db: &Connection) -> Vec<(String, String, DateTime<Utc>)> {
+        let mut statement = db
+            .prepare("SELECT game_id, mod_id, installed_at FROM mods ORDER BY game_id, mod_id")
+            .unwrap();
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn imports_legacy_json_state_into_a_fresh_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let oxide_dir = dir.path();
+        let edited = Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap();
+        write_json(
+            &oxide_dir.join("library.json"),
+            &json!({
+                "games": [game("b", "Beta"), game("a", "Alpha")],
+                "modified": { "a": { "title": edited } }
+            }),
+        );
+        write_json(
+            &oxide_dir.join("Sync/status.json"),
+            &json!({ "last_sync": "Never", "status": "Not synced" }),
+        );
+        write_json(&oxide_dir.join("Sync/conflicts.json"), &json!([]));
+        // Damaged beyond recovery, skipped rather than keeping the app from starting
+        fs::write(oxide_dir.join("Sync/queue.json"), "{\"ops\": [").unwrap();
+        write_json(
+            &oxide_dir.join("Mods/game-1/texture-pack_metadata.json"),
+            &descriptor("game-1", "texture-pack"),
+        );
+        write_json(
+            &oxide_dir.join("Mods/game-2/ui_metadata.json"),
+            &descriptor("game-2", "ui"),
+        );
+        // Mod files and other JSON next to the metadata are not descriptors
+        write_json(&oxide_dir.join("Mods/game-2/ui/config.json"), &json!({}));
+        write_json(&oxide_dir.join("Mods/game-2/rules.json"), &json!({}));
+
+        let db = open(oxide_dir).unwrap();
+        assert!(oxide_dir.join(super::super::DATABASE_FILE).exists());
+
+        let library = read_library(&db).unwrap();
+        let ids: Vec<&str> = library.games.iter().map(|game| game.id.as_str()).collect();
+        assert_eq!(ids, ["b", "a"]);
+        assert_eq!(library.games[1].title, "Alpha");
+        let expected: BTreeMap<String, FieldTimes> = BTreeMap::from([(
+            "a".to_string(),
+            BTreeMap::from([("title".to_string(), edited)]),
+        )]);
+        assert_eq!(library.modified, expected);
+        // The library counts as written when its file was
+        let written_at: DateTime<Utc> = read_state(&db, LIBRARY_WRITTEN_KEY).unwrap().unwrap();
+        assert_eq!(written_at, modified_at(&oxide_dir.join("library.json")));
+
+        let status: Value = read_state(&db, SYNC_STATUS_KEY).unwrap().unwrap();
+        assert_eq!(status["status"], "Not synced");
+        let conflicts: Value = read_state(&db, SYNC_CONFLICTS_KEY).unwrap().unwrap();
+        assert_eq!(conflicts, json!([]));
+        assert!(read_state::<Value>(&db, SYNC_QUEUE_KEY).unwrap().is_none());
+        assert!(read_state::<Value>(&db, SYNC_BASE_KEY).unwrap().is_none());
+
+        let installed: Vec<(String, String)> = mods(&db)
+            .into_iter()
+            .map(|(game_id, mod_id, _)| (game_id, mod_id))
+            .collect();
+        assert_eq!(
+            installed,
+            [
+                ("game-1".to_string(), "texture-pack".to_string()),
+                ("game-2".to_string(), "ui".to_string())
+            ]
+        );
+        let installed_at = mods(&db)[0].2;
+        assert_eq!(
+            installed_at,
+            modified_at(&oxide_dir.join("Mods/game-1/texture-pack_metadata.json"))
+        );
+
+        // Only a new database imports, the files are left for older versions
+        drop(db);
+        fs::remove_file(oxide_dir.join("Mods/game-2/ui_metadata.json")).unwrap();
+        write_json(&oxide_dir.join("library.json"), &json!({ "games": [] }));
+        let db = open(oxide_dir).unwrap();
+        assert_eq!(read_library(&db).unwrap().games.len(), 2);
+        assert_eq!(mods(&db).len(), 2);
+        assert!(oxide_dir.join("Sync/status.json").exists());
+    }
+}
@@ -0,0 +1,124 @@
+//! Schema versions of the database
+//!
+//! The version a database is at is kept in `PRAGMA user_version`, the number of
+//! `MIGRATIONS` applied to it. Migrations are only ever appended, never edited.
+
+use crate::OxideError;
+use rusqlite::Transaction;
+
+const MIGRATIONS: &[&str] = &[
+    // 1: library, installs, mods, play sessions and sync state
+    "CREATE TABLE library_games (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        game TEXT NOT NULL
+    );
+    CREATE TABLE library_field_times (
+        game_id TEXT NOT NULL,
+        field TEXT NOT NULL,
+        modified_at TEXT NOT NULL,
+        PRIMARY KEY (game_id, field)
+    );
+    CREATE TABLE installs (
+        game_id TEXT PRIMARY KEY,
+        install_dir TEXT NOT NULL,
+        artifact TEXT NOT NULL,
+        installed_at TEXT NOT NULL
+    );
+    CREATE TABLE mods (
+        game_id TEXT NOT NULL,
+        mod_id TEXT NOT NULL,
+        descriptor TEXT NOT NULL,
+        installed_at TEXT NOT NULL,
+        PRIMARY KEY (game_id, mod_id)
+    );
+    CREATE TABLE play_sessions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        game_id TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        ended_at TEXT
+    );
+    CREATE INDEX play_sessions_by_game ON play_sessions (game_id, started_at);
+    CREATE TABLE sync_state (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+];
+
+/// Bring the schema up to date, returning the version the database was at
+///
+/// A new database is at version 0.
+pub(super) fn migrate(tx: &Transaction) -> Result<u32, OxideError> {
+    let version: u32 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let latest = MIGRATIONS.len() as u32;
+    if version > latest {
+        return Err(OxideError::General(format!(
+            "Database schema version {} is newer than this version of Oxide supports ({})",
+            version, latest
+        )));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        tx.execute_batch(migration)?;
+    }
+    tx.pragma_update(None, "user_version", latest)?;
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn tables(connection: &Connection) -> Vec<String> {
+        let mut statement = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")
+            .unwrap();
+        let names = statement
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        names
+    }
+
+    #[test]
+    fn migrates_a_fresh_database() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let tx = connection.transaction().unwrap();
+        assert_eq!(migrate(&tx).unwrap(), 0);
+        tx.commit().unwrap();
+
+        let version: u32 = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len() as u32);
+        assert_eq!(
+            tables(&connection),
+            [
+                "installs",
+                "library_field_times",
+                "library_games",
+                "mods",
+                "play_sessions",
+                "sync_state"
+            ]
+        );
+
+        // Up to date, nothing runs again
+        let tx = connection.transaction().unwrap();
+        assert_eq!(migrate(&tx).unwrap(), MIGRATIONS.len() as u32);
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn refuses_a_newer_database() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() as u32 + 1)
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+        assert!(migrate(&tx).is_err());
+        assert!(tables(&tx).is_empty());
+    }
+}
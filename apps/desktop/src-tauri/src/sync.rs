@@ -1,22 +1,80 @@
-use crate::{
-    models::{Game, GameLibrary},
-    OxideError, API_BASE_URL,
-};
-use reqwest;
+pub mod backend;
+pub mod crypto;
+pub mod merge;
+pub mod queue;
+pub mod saves;
+pub mod scheduler;
+
+use crate::store::{self, SYNC_BASE_KEY, SYNC_CONFLICTS_KEY, SYNC_STATUS_KEY};
+use crate::{models::Game, OxideError};
+use backend::SyncBackend;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use tokio::fs;
 
+/// When each changed field of a game was last modified, keyed by field path
+///
+/// Nested fields are joined with dots, the empty path stands for the whole game.
+pub type FieldTimes = BTreeMap<String, DateTime<Utc>>;
+
+/// Key the library is stored under in the sync backend
+pub(crate) const LIBRARY_KEY: &str = "library.json";
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SyncStatus {
     last_sync: String,
     status: String,
+    /// Syncs waiting for the cloud to be reachable, see `queue`
+    #[serde(default)]
+    pending_ops: usize,
+    #[serde(default)]
+    last_error: Option<String>,
+    #[serde(default)]
+    next_retry: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GameLibrary {
     pub games: Vec<Game>,
+    /// Field modification times per game id, used to settle conflicting edits
+    #[serde(default)]
+    pub modified: BTreeMap<String, FieldTimes>,
+}
+
+/// The library as it was after the last successful sync
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SyncBase {
+    synced_at: Option<DateTime<Utc>>,
+    library: GameLibrary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictSide {
+    Local,
+    Remote,
+}
+
+/// A field both this machine and the cloud changed since the last sync
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    /// `<game id>:<field path>`
+    pub id: String,
+    pub game_id: String,
+    /// Dotted field path, `None` when one side deleted the game
+    pub field: Option<String>,
+    /// Values before the edits and on each side, `None` when absent
+    pub base: Option<Value>,
+    pub local: Option<Value>,
+    pub remote: Option<Value>,
+    pub local_modified: Option<DateTime<Utc>>,
+    pub remote_modified: Option<DateTime<Utc>>,
+    /// Side whose value the merge kept, the most recently modified one
+    pub applied: ConflictSide,
+    pub detected_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,145 +85,305 @@ pub struct GameMetadata {
     pub installed: bool,
     pub last_played: Option<String>,
     pub play_time: u64, // in seconds
+    /// Save path patterns, see `saves`
     pub save_files: Vec<String>,
 }
 
 /// Synchronize game library with cloud
+///
+/// When the cloud cannot be reached the sync is queued rather than failing,
+/// `get_sync_status` tells when it is retried. Other failures are returned.
 pub async fn sync_library() -> Result<String, OxideError> {
-    let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-        OxideError::FileOperationError("Could not determine home directory".to_string())
-    })?;
-    let sync_dir = home_dir.join(".Oxide").join("Sync");
+    let sync_dir = crate::oxide_dir()?.join("Sync");
     if !sync_dir.exists() {
-        fs::create_dir_all(&sync_dir)
-            .await
-            .map_err(|e| OxideError::FileOperationError(format!("Failed to create sync dir: {}", e)))?;
+        fs::create_dir_all(&sync_dir).await.map_err(|e| {
+            OxideError::FileOperationError(format!("Failed to create sync dir: {}", e))
+        })?;
     }
 
-    let local_library = load_local_library().await?;
-    let remote_library = download_remote_library().await?;
-    let merged_library = merge_libraries(local_library, remote_library).await?;
+    match queue::attempt(queue::SyncOp::Library, push_library()).await? {
+        Some(0) => Ok("Library synced successfully".to_string()),
+        Some(conflict_count) => Ok(format!(
+            "Library synced with {} conflicting changes to review",
+            conflict_count
+        )),
+        None => Ok("Sync failed, library changes are queued and will be retried".to_string()),
+    }
+}
+
+/// Merge the local library with the cloud copy, returning the conflict count
+///
+/// Nothing is written locally before the upload went through, so a failed
+/// attempt leaves the library as the user edited it for the retry.
+async fn push_library() -> Result<usize, OxideError> {
+    push_library_to(backend::open_backend().await?.as_ref()).await
+}
+
+async fn push_library_to(backend: &dyn SyncBackend) -> Result<usize, OxideError> {
+    let base = load_sync_base().await?;
+    let mut local_library = load_local_library().await?;
+    let edited_at = library_modified_at().await?.unwrap_or_else(Utc::now);
+    merge::stamp_local_changes(&mut local_library, &base.library, base.synced_at, edited_at)?;
 
-    save_local_library(&merged_library).await?;
-    upload_library_changes(&merged_library).await?;
-    update_sync_status().await?;
+    // Nothing uploaded yet, the local library is all there is
+    let remote_library = download_remote_library(backend)
+        .await?
+        .unwrap_or_else(|| local_library.clone());
+    let outcome = merge::merge_libraries(&base.library, &local_library, &remote_library)?;
 
-    Ok("Library synced successfully".to_string())
+    upload_library_changes(backend, &outcome.library).await?;
+    save_local_library(&outcome.library).await?;
+    save_sync_base(SyncBase {
+        synced_at: Some(Utc::now()),
+        library: outcome.library,
+    })
+    .await?;
+
+    let conflict_count = outcome.conflicts.len();
+    if conflict_count > 0 {
+        let mut conflicts = get_sync_conflicts().await?;
+        for conflict in outcome.conflicts {
+            conflicts.retain(|existing| existing.id != conflict.id);
+            conflicts.push(conflict);
+        }
+        save_sync_conflicts(&conflicts).await?;
+    }
+    update_sync_status(conflict_count).await?;
+    Ok(conflict_count)
+}
+
+/// Get conflicts found while syncing that the user has not resolved yet
+pub async fn get_sync_conflicts() -> Result<Vec<SyncConflict>, OxideError> {
+    Ok(store::blocking(|| store::get_state(SYNC_CONFLICTS_KEY))
+        .await?
+        .unwrap_or_default())
+}
+
+/// Keep one side of a conflict, returning the conflicts left
+///
+/// The chosen value is written to the local library as a new edit, so the next
+/// sync carries it to the cloud.
+pub async fn resolve_sync_conflict(
+    conflict_id: &str,
+    choice: ConflictSide,
+) -> Result<Vec<SyncConflict>, OxideError> {
+    // A sync running meanwhile would overwrite the library written here
+    let _guard = queue::lock().await;
+    let mut conflicts = get_sync_conflicts().await?;
+    let position = conflicts
+        .iter()
+        .position(|conflict| conflict.id == conflict_id)
+        .ok_or_else(|| OxideError::NotFound(format!("Sync conflict {}", conflict_id)))?;
+    let conflict = conflicts.remove(position);
+    let value = match choice {
+        ConflictSide::Local => conflict.local,
+        ConflictSide::Remote => conflict.remote,
+    };
+
+    let mut library = load_local_library().await?;
+    let index = library
+        .games
+        .iter()
+        .position(|game| game.id == conflict.game_id);
+    let now = Utc::now();
+    match (&conflict.field, index) {
+        (None, index) => {
+            // The whole game was kept or deleted, older field times no longer apply
+            library.modified.remove(&conflict.game_id);
+            match (value, index) {
+                (Some(value), Some(index)) => library.games[index] = serde_json::from_value(value)?,
+                (Some(value), None) => library.games.push(serde_json::from_value(value)?),
+                (None, Some(index)) => {
+                    library.games.remove(index);
+                }
+                (None, None) => {}
+            }
+            library
+                .modified
+                .entry(conflict.game_id.clone())
+                .or_default()
+                .insert(String::new(), now);
+        }
+        (Some(field), Some(index)) => {
+            let mut game = serde_json::to_value(&library.games[index])?;
+            merge::set_path(&mut game, field, value)?;
+            library.games[index] = serde_json::from_value(game)?;
+            library
+                .modified
+                .entry(conflict.game_id.clone())
+                .or_default()
+                .insert(field.clone(), now);
+        }
+        (Some(_), None) => {
+            return Err(OxideError::NotFound(format!(
+                "Game {} is no longer in the library",
+                conflict.game_id
+            )))
+        }
+    }
+
+    save_local_library(&library).await?;
+    save_sync_conflicts(&conflicts).await?;
+    Ok(conflicts)
 }
 
 /// Get current sync status
 pub async fn get_sync_status() -> Result<SyncStatus, OxideError> {
-    let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-        OxideError::FileOperationError("Could not determine home directory".to_string())
-    })?;
-    let status_file = home_dir.join(".Oxide").join("Sync").join("status.json");
-    if status_file.exists() {
-        let content = fs::read_to_string(status_file).await.map_err(|e| {
-            OxideError::FileOperationError(format!("Failed to read sync status: {}", e))
-        })?;
-        serde_json::from_str(&content).map_err(|e| {
-            OxideError::FileOperationError(format!("Failed to parse sync status: {}", e))
-        })
-    } else {
-        Ok(SyncStatus {
-            last_sync: "Never".to_string(),
-            status: "Not synced".to_string(),
-        })
+    let stored = store::blocking(|| store::get_state(SYNC_STATUS_KEY)).await?;
+    let mut status = stored.unwrap_or_else(|| SyncStatus {
+        last_sync: "Never".to_string(),
+        status: "Not synced".to_string(),
+        pending_ops: 0,
+        last_error: None,
+        next_retry: None,
+    });
+
+    let queue = queue::get_sync_queue().await?;
+    status.pending_ops = queue.ops.len();
+    if status.pending_ops > 0 {
+        status.status = format!("{} changes waiting to sync", status.pending_ops);
+        status.last_error = queue.last_error;
+        status.next_retry = queue.next_retry;
     }
+    Ok(status)
+}
+
+/// Retry queued syncs now instead of waiting for their backoff
+pub async fn retry_pending_sync() -> Result<SyncStatus, OxideError> {
+    queue::retry_sync_queue(true).await?;
+    get_sync_status().await
 }
 
 /// Load local game library
 async fn load_local_library() -> Result<GameLibrary, OxideError> {
-    let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-        OxideError::FileOperationError("Could not determine home directory".to_string())
-    })?;
-    let library_file = home_dir.join(".Oxide").join("library.json");
-    if library_file.exists() {
-        let content = fs::read_to_string(library_file).await.map_err(|e| {
-            OxideError::FileOperationError(format!("Failed to read local library: {}", e))
-        })?;
-        serde_json::from_str(&content).map_err(|e| {
-            OxideError::FileOperationError(format!("Failed to parse local library: {}", e))
-        })
-    } else {
-        Ok(GameLibrary { games: vec![] })
-    }
+    store::blocking(store::load_library).await
+}
+
+/// When the local library was last written, if it ever was
+pub(crate) async fn library_modified_at() -> Result<Option<DateTime<Utc>>, OxideError> {
+    store::blocking(store::library_written_at).await
+}
+
+/// Whether the library was written since the last sync
+pub(crate) async fn has_local_changes() -> Result<bool, OxideError> {
+    let synced_at = load_sync_base().await?.synced_at;
+    Ok(match (library_modified_at().await?, synced_at) {
+        (Some(modified), Some(synced_at)) => modified > synced_at,
+        (Some(_), None) => true,
+        (None, _) => false,
+    })
 }
 
 /// Save local game library
 async fn save_local_library(library: &GameLibrary) -> Result<(), OxideError> {
-    let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-        OxideError::FileOperationError("Could not determine home directory".to_string())
-    })?;
-    let library_file = home_dir.join(".Oxide").join("library.json");
-    let content = serde_json::to_string_pretty(library).map_err(|e| {
+    let library = library.clone();
+    store::blocking(move || store::save_library(&library)).await
+}
+
+/// Upload the merged library to the sync backend
+///
+/// Backend errors are returned as they are, the queue tells from them whether
+/// the cloud was out of reach.
+async fn upload_library_changes(
+    backend: &dyn SyncBackend,
+    library: &GameLibrary,
+) -> Result<(), OxideError> {
+    let content = serde_json::to_vec(library).map_err(|e| {
         OxideError::FileOperationError(format!("Failed to serialize library: {}", e))
     })?;
-    fs::write(library_file, content)
-        .await
-        .map_err(|e| OxideError::FileOperationError(format!("Failed to write local library: {}", e)))
-}
-
-/// Upload library changes to cloud (placeholder implementation)
-async fn upload_library_changes(_library: &GameLibrary) -> Result<(), OxideError> {
-    // In a real app, you would post the changes to your backend
-    let _sync_url = format!("{}/sync/upload", API_BASE_URL); // Replace with actual URL
-                                                            // let client = reqwest::Client::new();
-                                                            // client.post(sync_url).json(library).send().await?;
-    Ok(())
-}
-
-/// Download remote library changes (placeholder implementation)
-async fn download_remote_library() -> Result<GameLibrary, OxideError> {
-    let sync_url = format!("{}/sync/download", API_BASE_URL); // Replace with actual URL
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&sync_url)
-        .send()
-        .await
-        .map_err(|e| OxideError::General(format!("Failed to download remote library: {}", e)))?;
-
-    if response.status().is_success() {
-        response
-            .json::<GameLibrary>()
-            .await
-            .map_err(|e| OxideError::General(format!("Failed to parse remote library: {}", e)))
-    } else {
-        // Return an empty library if the server returns an error (e.g., 404 Not Found)
-        Ok(GameLibrary { games: vec![] })
-    }
+    backend.put(LIBRARY_KEY, content).await
 }
 
-/// Merge local and remote libraries
-async fn merge_libraries(local: GameLibrary, remote: GameLibrary) -> Result<GameLibrary, OxideError> {
-    // This is a simplistic merge strategy: remote takes precedence.
-    // A real implementation would be more sophisticated.
-    let mut merged_games = remote.games.clone();
-    for local_game in local.games {
-        if !merged_games.iter().any(|g| g.id == local_game.id) {
-            merged_games.push(local_game);
-        }
-    }
-    Ok(GameLibrary {
-        games: merged_games,
-    })
+/// Download the library other machines uploaded
+///
+/// `None` when nothing was uploaded yet. Any other failure is an error: merging
+/// against an empty library would read as every game deleted remotely.
+async fn download_remote_library(
+    backend: &dyn SyncBackend,
+) -> Result<Option<GameLibrary>, OxideError> {
+    let content = backend.get(LIBRARY_KEY).await?;
+    content
+        .map(|content| serde_json::from_slice(&content))
+        .transpose()
+        .map_err(|e| OxideError::General(format!("Failed to parse remote library: {}", e)))
+}
+
+fn sync_dir() -> Result<PathBuf, OxideError> {
+    Ok(crate::oxide_dir()?.join("Sync"))
+}
+
+/// Load the library as of the last sync, empty before the first one
+async fn load_sync_base() -> Result<SyncBase, OxideError> {
+    Ok(store::blocking(|| store::get_state(SYNC_BASE_KEY))
+        .await?
+        .unwrap_or_default())
+}
+
+async fn save_sync_base(base: SyncBase) -> Result<(), OxideError> {
+    store::blocking(move || store::set_state(SYNC_BASE_KEY, &base)).await
+}
+
+async fn save_sync_conflicts(conflicts: &[SyncConflict]) -> Result<(), OxideError> {
+    let conflicts = conflicts.to_vec();
+    store::blocking(move || store::set_state(SYNC_CONFLICTS_KEY, &conflicts)).await
 }
 
 /// Update sync status
-async fn update_sync_status() -> Result<(), OxideError> {
-    let home_dir = tauri::api::path::home_dir().ok_or_else(|| {
-        OxideError::FileOperationError("Could not determine home directory".to_string())
-    })?;
-    let status_file = home_dir.join(".Oxide").join("Sync").join("status.json");
+async fn update_sync_status(conflict_count: usize) -> Result<(), OxideError> {
     let status = SyncStatus {
         last_sync: chrono::Utc::now().to_rfc3339(),
-        status: "Synced".to_string(),
+        status: if conflict_count > 0 {
+            format!("Synced with {} conflicts", conflict_count)
+        } else {
+            "Synced".to_string()
+        },
+        pending_ops: 0,
+        last_error: None,
+        next_retry: None,
     };
-    let content = serde_json::to_string_pretty(&status).map_err(|e| {
-        OxideError::FileOperationError(format!("Failed to serialize sync status: {}", e))
-    })?;
-    fs::write(status_file, content)
-        .await
-        .map_err(|e| OxideError::FileOperationError(format!("Failed to write sync status: {}", e)))
+    store::blocking(move || store::set_state(SYNC_STATUS_KEY, &status)).await
 }
 
+#[cfg(test)]
+mod tests {
+    use super::backend::{FolderBackend, WebDavBackend};
+    use super::queue::SyncOp;
+    use super::*;
+
+    async fn library_queued() -> bool {
+        queue::get_sync_queue()
+            .await
+            .unwrap()
+            .ops
+            .iter()
+            .any(|queued| queued.op == SyncOp::Library)
+    }
+
+    #[tokio::test]
+    async fn unreachable_backend_leaves_the_library_queued() {
+        let dir = tempfile::tempdir().unwrap();
+        // A network share that is not mounted leaves a plain file or nothing behind
+        let not_mounted = dir.path().join("share");
+        std::fs::write(&not_mounted, "").unwrap();
+        let unreachable: [Box<dyn SyncBackend>; 2] = [
+            Box::new(FolderBackend::new(not_mounted).unwrap()),
+            Box::new(WebDavBackend::new("http://127.0.0.1:1", None).unwrap()),
+        ];
+        for backend in unreachable {
+            let attempted = queue::attempt(SyncOp::Library, push_library_to(backend.as_ref()))
+                .await
+                .unwrap();
+            assert!(attempted.is_none(), "{}", backend.describe());
+            assert!(library_queued().await, "{}", backend.describe());
+        }
+
+        // A remote library that does not parse would fail every retry
+        let cloud = tempfile::tempdir().unwrap();
+        std::fs::write(cloud.path().join(LIBRARY_KEY), "not json").unwrap();
+        let backend = FolderBackend::new(cloud.path().to_path_buf()).unwrap();
+        assert!(queue::attempt(SyncOp::Library, push_library_to(&backend))
+            .await
+            .is_err());
+        assert!(!library_queued().await);
+    }
+}
@@ -0,0 +1,336 @@
+//! Storage the library and saves are synced through
+//!
+//! A backend stores opaque blobs under `/` separated keys such as
+//! `library.json` or `saves/<game id>/history.json`; the sync code decides what
+//! goes in them. Which backend is used is a setting kept in
+//! `~/.Oxide/Sync/backend.json`, the Oxide sync service unless the user picked
+//! a folder, a WebDAV server or an S3-compatible bucket. The file holds the
+//! WebDAV password or S3 secret key, so only the current user can read it and
+//! the config handed out by `get_sync_backend` leaves them out. With encryption
+//! on, `open_backend` encrypts blobs before they reach the backend, see `crypto`.
+
+mod folder;
+mod s3;
+mod webdav;
+
+pub use folder::FolderBackend;
+pub use s3::S3Backend;
+pub use webdav::WebDavBackend;
+
+use crate::{persist, OxideError, API_BASE_URL};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "backend.json";
+
+#[async_trait]
+pub trait SyncBackend: Send + Sync {
+    /// Contents stored under `key`, `None` when nothing is
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, OxideError>;
+
+    /// Store `data` under `key`, replacing what was there
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), OxideError>;
+
+    /// Remove what is stored under `key`, if anything
+    async fn delete(&self, key: &str) -> Result<(), OxideError>;
+
+    /// Where the backend stores data, for status messages
+    fn describe(&self) -> String;
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncBackendConfig {
+    /// The Oxide sync service
+    #[default]
+    Oxide,
+    /// A local or network folder, e.g. one a file sync client mirrors
+    Folder { path: PathBuf },
+    WebDav {
+        /// Collection data is stored below, e.g. `https://dav.example.com/oxide/`
+        url: String,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    S3 {
+        /// e.g. `https://s3.eu-west-1.amazonaws.com` or `http://localhost:9000`
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+        /// Key prefix, for sharing a bucket with other data
+        #[serde(default)]
+        prefix: String,
+        /// Address the bucket in the path rather than the host name, as MinIO expects
+        #[serde(default = "default_path_style")]
+        path_style: bool,
+    },
+}
+
+fn default_path_style() -> bool {
+    true
+}
+
+impl SyncBackendConfig {
+    pub fn open(&self) -> Result<Box<dyn SyncBackend>, OxideError> {
+        Ok(match self {
+            SyncBackendConfig::Oxide => {
+                Box::new(WebDavBackend::new(&format!("{}/sync", API_BASE_URL), None)?)
+            }
+            SyncBackendConfig::Folder { path } => Box::new(FolderBackend::new(path.clone())?),
+            SyncBackendConfig::WebDav {
+                url,
+                username,
+                password,
+            } => {
+                let credentials = username
+                    .clone()
+                    .map(|username| (username, password.clone().unwrap_or_default()));
+                Box::new(WebDavBackend::new(url, credentials)?.with_collections())
+            }
+            SyncBackendConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key_id,
+                secret_access_key,
+                prefix,
+                path_style,
+            } => Box::new(S3Backend::new(
+                endpoint,
+                bucket,
+                region,
+                access_key_id,
+                secret_access_key,
+                prefix,
+                *path_style,
+            )?),
+        })
+    }
+}
+
+impl SyncBackendConfig {
+    /// The config without its password or secret key, for showing to the user
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        match &mut config {
+            SyncBackendConfig::WebDav { password, .. } => *password = None,
+            SyncBackendConfig::S3 {
+                secret_access_key, ..
+            } => secret_access_key.clear(),
+            SyncBackendConfig::Oxide | SyncBackendConfig::Folder { .. } => {}
+        }
+        config
+    }
+
+    /// Fill in a secret left out of a redacted config from `stored`
+    ///
+    /// The stored secret is only taken for the same server and user, a secret
+    /// must not follow the config to another server.
+    fn with_secret_from(mut self, stored: &SyncBackendConfig) -> Self {
+        match (&mut self, stored) {
+            (
+                SyncBackendConfig::WebDav {
+                    url,
+                    username,
+                    password: password @ None,
+                },
+                SyncBackendConfig::WebDav {
+                    url: stored_url,
+                    username: stored_username,
+                    password: stored_password,
+                },
+            ) if url == stored_url && username == stored_username => {
+                *password = stored_password.clone();
+            }
+            (
+                SyncBackendConfig::S3 {
+                    endpoint,
+                    bucket,
+                    access_key_id,
+                    secret_access_key,
+                    ..
+                },
+                SyncBackendConfig::S3 {
+                    endpoint: stored_endpoint,
+                    bucket: stored_bucket,
+                    access_key_id: stored_access_key_id,
+                    secret_access_key: stored_secret_access_key,
+                    ..
+                },
+            ) if secret_access_key.is_empty()
+                && endpoint == stored_endpoint
+                && bucket == stored_bucket
+                && access_key_id == stored_access_key_id =>
+            {
+                *secret_access_key = stored_secret_access_key.clone();
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+/// Reject keys that could escape the backend's root
+pub(crate) fn check_key(key: &str) -> Result<(), OxideError> {
+    let valid = !key.is_empty()
+        && !key.starts_with('/')
+        && !key.contains('\\')
+        && key
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(OxideError::General(format!("Invalid sync key {}", key)))
+    }
+}
+
+fn config_path() -> Result<PathBuf, OxideError> {
+    Ok(crate::oxide_dir()?.join("Sync").join(CONFIG_FILE))
+}
+
+async fn load_config() -> Result<SyncBackendConfig, OxideError> {
+    Ok(persist::read_json(&config_path()?)
+        .await?
+        .unwrap_or_default())
+}
+
+/// Get the configured sync backend, without its password or secret key
+pub async fn get_sync_backend() -> Result<SyncBackendConfig, OxideError> {
+    Ok(load_config().await?.redacted())
+}
+
+/// Choose the sync backend, after checking it can be reached
+///
+/// A config as `get_sync_backend` returns it keeps the stored secret.
+pub async fn set_sync_backend(config: SyncBackendConfig) -> Result<String, OxideError> {
+    let config = config.with_secret_from(&load_config().await?);
+    let backend = config.open()?;
+    backend.get(CONFIG_FILE).await.map_err(|e| {
+        OxideError::General(format!("Could not reach {}: {}", backend.describe(), e))
+    })?;
+
+    persist::write_json_private(&config_path()?, &config).await?;
+    Ok(format!("Syncing through {}", backend.describe()))
+}
+
+/// Open the configured sync backend as is, blobs are stored the way they are put
+pub(crate) async fn open_plain_backend() -> Result<Box<dyn SyncBackend>, OxideError> {
+    load_config().await?.open()
+}
+
+/// Open the configured sync backend, encrypting when encryption is on
+pub async fn open_backend() -> Result<Box<dyn SyncBackend>, OxideError> {
+    super::crypto::encrypt_backend(open_plain_backend().await?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s3(endpoint: &str, secret: &str) -> SyncBackendConfig {
+        SyncBackendConfig::S3 {
+            endpoint: endpoint.to_string(),
+            bucket: "oxide".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "key".to_string(),
+            secret_access_key: secret.to_string(),
+            prefix: String::new(),
+            path_style: true,
+        }
+    }
+
+    fn webdav(url: &str, password: Option<&str>) -> SyncBackendConfig {
+        SyncBackendConfig::WebDav {
+            url: url.to_string(),
+            username: Some("user".to_string()),
+            password: password.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        assert_eq!(
+            webdav("https://dav.example.com/", Some("hunter2")).redacted(),
+            webdav("https://dav.example.com/", None)
+        );
+        assert_eq!(
+            s3("https://s3.example.com", "secret").redacted(),
+            s3("https://s3.example.com", "")
+        );
+    }
+
+    #[test]
+    fn keeps_stored_secret_for_the_same_server_only() {
+        let stored = webdav("https://dav.example.com/", Some("hunter2"));
+        assert_eq!(stored.redacted().with_secret_from(&stored), stored);
+        assert_eq!(
+            webdav("https://other.example.com/", None).with_secret_from(&stored),
+            webdav("https://other.example.com/", None)
+        );
+        assert_eq!(
+            webdav("https://dav.example.com/", Some("new")).with_secret_from(&stored),
+            webdav("https://dav.example.com/", Some("new"))
+        );
+
+        let stored = s3("https://s3.example.com", "secret");
+        assert_eq!(stored.redacted().with_secret_from(&stored), stored);
+        assert_eq!(
+            s3("https://evil.example.com", "").with_secret_from(&stored),
+            s3("https://evil.example.com", "")
+        );
+    }
+
+    #[tokio::test]
+    async fn opens_a_folder_from_its_config() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = SyncBackendConfig::Folder {
+            path: root.path().to_path_buf(),
+        }
+        .open()
+        .unwrap();
+
+        backend.put("library.json", b"[]".to_vec()).await.unwrap();
+        assert_eq!(
+            std::fs::read(root.path().join("library.json")).unwrap(),
+            b"[]"
+        );
+        assert_eq!(
+            backend.get("library.json").await.unwrap().as_deref(),
+            Some(&b"[]"[..])
+        );
+        assert!(backend.get("../library.json").await.is_err());
+        backend.delete("library.json").await.unwrap();
+        assert_eq!(backend.get("library.json").await.unwrap(), None);
+
+        assert!(SyncBackendConfig::Folder {
+            path: PathBuf::from("relative")
+        }
+        .open()
+        .is_err());
+    }
+
+    #[test]
+    fn rejects_keys_that_escape_the_root() {
+        for key in ["library.json", "saves/game/1.zip", "a.b/c..d"] {
+            assert!(check_key(key).is_ok(), "{} should be accepted", key);
+        }
+        for key in [
+            "",
+            "/etc/passwd",
+            "a/../b",
+            "..",
+            "a/./b",
+            "a//b",
+            "a/",
+            "a\\b",
+        ] {
+            assert!(check_key(key).is_err(), "{} should be rejected", key);
+        }
+    }
+}
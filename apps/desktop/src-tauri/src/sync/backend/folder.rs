@@ -0,0 +1,152 @@
+//! Sync through a local or network folder
+
+use super::{check_key, SyncBackend};
+use crate::OxideError;
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::fs;
+
+pub struct FolderBackend {
+    root: PathBuf,
+}
+
+impl FolderBackend {
+    pub fn new(root: PathBuf) -> Result<Self, OxideError> {
+        if !root.is_absolute() {
+            return Err(OxideError::General(format!(
+                "Sync folder {} must be an absolute path",
+                root.display()
+            )));
+        }
+        Ok(FolderBackend { root })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, OxideError> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+
+    /// An error of the folder itself, e.g. a network share that is not mounted,
+    /// which unlike an error of the local disk is worth retrying
+    fn failed(&self, e: std::io::Error) -> OxideError {
+        OxideError::Backend(format!("{}: {}", self.describe(), e))
+    }
+}
+
+#[async_trait]
+impl SyncBackend for FolderBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, OxideError> {
+        match fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(self.failed(e)),
+        }
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), OxideError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| self.failed(e))?;
+        }
+        // Other machines read the folder while it syncs, they must never see half a file.
+        // The suffix is appended, keys differing in extension must not share one.
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        fs::write(&partial, data)
+            .await
+            .map_err(|e| self.failed(e))?;
+        fs::rename(&partial, &path)
+            .await
+            .map_err(|e| self.failed(e))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), OxideError> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(self.failed(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("folder {}", self.root.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_blobs_under_keys() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = FolderBackend::new(root.path().to_path_buf()).unwrap();
+
+        assert_eq!(backend.get("saves/a/history.json").await.unwrap(), None);
+        backend
+            .put("saves/a/history.json", b"first".to_vec())
+            .await
+            .unwrap();
+        backend
+            .put("saves/a/history.bin", b"second".to_vec())
+            .await
+            .unwrap();
+        backend
+            .put("saves/a/history.json", b"replaced".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(
+            backend
+                .get("saves/a/history.json")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(&b"replaced"[..])
+        );
+        assert_eq!(
+            backend.get("saves/a/history.bin").await.unwrap().as_deref(),
+            Some(&b"second"[..])
+        );
+
+        // Nothing is left behind by the writes
+        let mut files: Vec<String> = std::fs::read_dir(root.path().join("saves/a"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["history.bin", "history.json"]);
+
+        backend.delete("saves/a/history.json").await.unwrap();
+        backend.delete("saves/a/history.json").await.unwrap();
+        assert_eq!(backend.get("saves/a/history.json").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_keys_outside_the_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = FolderBackend::new(root.path().join("sync")).unwrap();
+        std::fs::write(root.path().join("outside"), "secret").unwrap();
+
+        for key in [
+            "../outside",
+            "a/../../outside",
+            "/outside",
+            "a\\..\\b",
+            "",
+            "a//b",
+            "./a",
+        ] {
+            assert!(
+                backend.get(key).await.is_err(),
+                "{} should be rejected",
+                key
+            );
+            assert!(backend.put(key, Vec::new()).await.is_err());
+            assert!(backend.delete(key).await.is_err());
+        }
+        assert!(root.path().join("outside").exists());
+        assert!(FolderBackend::new(PathBuf::from("relative")).is_err());
+    }
+}
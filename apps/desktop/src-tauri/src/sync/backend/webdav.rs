@@ -0,0 +1,186 @@
+//! Sync through a WebDAV server, or any HTTP server storing what is PUT to it
+
+use super::{check_key, SyncBackend};
+use crate::OxideError;
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+
+pub struct WebDavBackend {
+    client: Client,
+    /// Ends with `/`
+    base_url: String,
+    credentials: Option<(String, String)>,
+    /// Create missing parent collections with MKCOL, plain HTTP stores need not
+    create_collections: bool,
+}
+
+impl WebDavBackend {
+    pub fn new(url: &str, credentials: Option<(String, String)>) -> Result<Self, OxideError> {
+        let parsed = reqwest::Url::parse(url)
+            .map_err(|e| OxideError::General(format!("Invalid sync URL {}: {}", url, e)))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(OxideError::General(format!(
+                "Sync URL {} must use http or https",
+                url
+            )));
+        }
+        Ok(WebDavBackend {
+            client: Client::new(),
+            base_url: format!("{}/", url.trim_end_matches('/')),
+            credentials,
+            create_collections: false,
+        })
+    }
+
+    pub fn with_collections(mut self) -> Self {
+        self.create_collections = true;
+        self
+    }
+
+    fn request(&self, method: Method, key: &str) -> Result<RequestBuilder, OxideError> {
+        check_key(key)?;
+        Ok(self.request_path(method, &encode_key(key)))
+    }
+
+    /// Request for an encoded path below the base URL
+    fn request_path(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path));
+        match &self.credentials {
+            Some((username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        }
+    }
+
+    /// Create the collections above `key` that do not exist yet
+    async fn create_parents(&self, key: &str) -> Result<(), OxideError> {
+        let mkcol = Method::from_bytes(b"MKCOL").expect("MKCOL is a valid method");
+        let encoded = encode_key(key);
+        let parts: Vec<&str> = encoded.split('/').collect();
+        for depth in 1..parts.len() {
+            let collection = format!("{}/", parts[..depth].join("/"));
+            let status = self
+                .request_path(mkcol.clone(), &collection)
+                .send()
+                .await?
+                .status();
+            // 405 means the collection exists already
+            if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(OxideError::General(format!(
+                    "Failed to create WebDAV collection {}: {}",
+                    collection, status
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Percent-encode each part of a key for use in a URL path
+pub(super) fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|part| {
+            part.bytes()
+                .map(|byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                        (byte as char).to_string()
+                    }
+                    _ => format!("%{:02X}", byte),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[async_trait]
+impl SyncBackend for WebDavBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, OxideError> {
+        let response = self.request(Method::GET, key)?.send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), OxideError> {
+        let response = self
+            .request(Method::PUT, key)?
+            .body(data.clone())
+            .send()
+            .await?;
+        // WebDAV answers 409 when the parent collection is missing
+        if self.create_collections && response.status() == StatusCode::CONFLICT {
+            self.create_parents(key).await?;
+            self.request(Method::PUT, key)?
+                .body(data)
+                .send()
+                .await?
+                .error_for_status()?;
+            return Ok(());
+        }
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), OxideError> {
+        let response = self.request(Method::DELETE, key)?.send().await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        self.base_url.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_each_part_of_a_key() {
+        assert_eq!(encode_key("library.json"), "library.json");
+        assert_eq!(
+            encode_key("saves/my game/ä#1?.zip"),
+            "saves/my%20game/%C3%A4%231%3F.zip"
+        );
+    }
+
+    #[test]
+    fn builds_requests_below_the_base_url() {
+        let backend = WebDavBackend::new(
+            "https://dav.example.com/oxide//",
+            Some(("user".to_string(), "hunter2".to_string())),
+        )
+        .unwrap();
+        let request = backend
+            .request(Method::PUT, "saves/my game/history.json")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.method(), Method::PUT);
+        assert_eq!(
+            request.url().as_str(),
+            "https://dav.example.com/oxide/saves/my%20game/history.json"
+        );
+        // Basic auth of user:hunter2
+        assert_eq!(request.headers()["authorization"], "Basic dXNlcjpodW50ZXIy");
+
+        let anonymous = WebDavBackend::new("http://localhost:8080", None).unwrap();
+        let request = anonymous
+            .request(Method::GET, "library.json")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.url().as_str(), "http://localhost:8080/library.json");
+        assert!(!request.headers().contains_key("authorization"));
+
+        assert!(anonymous.request(Method::GET, "../library.json").is_err());
+        assert!(WebDavBackend::new("ftp://dav.example.com/", None).is_err());
+        assert!(WebDavBackend::new("not a url", None).is_err());
+    }
+}
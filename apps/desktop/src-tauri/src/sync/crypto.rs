@@ -0,0 +1,970 @@
+//! End-to-end encryption of everything synced
+//!
+//! Blobs are encrypted with XChaCha20-Poly1305 before they reach the sync
+//! backend, so the server only ever stores ciphertext. Data keys live in a
+//! keyring sealed under a random master key, and the master key is sealed twice:
+//! under a key derived from the user's passphrase with Argon2id, and under a
+//! recovery key. The sealed keys are stored unencrypted next to the data as
+//! `keys.json`, which is how another machine unlocks with the same passphrase.
+//! An unlocked machine keeps the master key in `~/.Oxide/Sync/encryption.json`.
+//!
+//! Rotating adds a data key new blobs are written with. Older keys stay in the
+//! keyring, so blobs written before remain readable.
+//!
+//! An encrypted blob is `OXE1`, the little-endian `u32` length of a JSON header,
+//! the header and the ciphertext. The header names the data key, the nonce and
+//! the storage key the blob was written under, and is authenticated with the
+//! contents, so the server can neither alter it nor pass one blob off as another.
+
+use super::backend::SyncBackend;
+use crate::OxideError;
+use argon2::{Algorithm, Argon2, Params, Version};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Utc};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Storage key of the sealed keys
+pub const KEYS_KEY: &str = "keys.json";
+const LOCAL_FILE: &str = "encryption.json";
+const MAGIC: &[u8; 4] = b"OXE1";
+const ALGORITHM: &str = "xchacha20poly1305";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const MIN_PASSPHRASE_LEN: usize = 8;
+/// Argon2id cost, OWASP's recommended minimum is 19 MiB with 2 passes
+const KDF_MEMORY_KIB: u32 = 64 * 1024;
+const KDF_ITERATIONS: u32 = 3;
+const KDF_PARALLELISM: u32 = 1;
+/// Bounds on the cost read from `keys.json`, which the sync backend could have
+/// altered: too cheap would weaken a new passphrase key, too costly exhausts memory
+const KDF_MEMORY_KIB_RANGE: RangeInclusive<u32> = 19 * 1024..=1024 * 1024;
+const KDF_ITERATIONS_RANGE: RangeInclusive<u32> = 2..=16;
+const KDF_PARALLELISM_RANGE: RangeInclusive<u32> = 1..=16;
+const KDF_MIN_SALT_LEN: usize = 16;
+/// Associated data binding each sealed box to its purpose
+const MASTER_KEY_AAD: &[u8] = b"oxide sync master key";
+const KEYRING_AAD: &[u8] = b"oxide sync keyring";
+
+type SecretKey = [u8; KEY_LEN];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KdfParams {
+    /// Base64 encoded
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedBox {
+    /// Base64 encoded
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Contents of `keys.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFile {
+    version: u32,
+    kdf: KdfParams,
+    /// Master key sealed under the passphrase key
+    passphrase: SealedBox,
+    /// Master key sealed under the recovery key
+    recovery: SealedBox,
+    /// `Keyring` sealed under the master key
+    keyring: SealedBox,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Keyring {
+    /// Id of the key new blobs are encrypted with
+    current: String,
+    keys: BTreeMap<String, DataKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DataKey {
+    /// Base64 encoded
+    key: String,
+    created_at: DateTime<Utc>,
+}
+
+/// Contents of `encryption.json`, the unlocked master key of this machine
+#[derive(Serialize, Deserialize)]
+struct LocalKeys {
+    master_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BlobHeader {
+    version: u32,
+    algorithm: String,
+    key_id: String,
+    /// Base64 encoded
+    nonce: String,
+    /// Storage key the blob was written under
+    storage_key: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EncryptionStatus {
+    /// Whether data in the sync backend is encrypted
+    pub enabled: bool,
+    /// Whether this machine holds the keys to read it
+    pub unlocked: bool,
+    pub current_key_id: Option<String>,
+    pub current_key_created_at: Option<DateTime<Utc>>,
+    /// Data keys in the keyring, including retired ones still needed for old blobs
+    pub key_count: usize,
+}
+
+/// A backend whose blobs are encrypted with the keyring's keys
+pub struct EncryptedBackend {
+    inner: Box<dyn SyncBackend>,
+    keyring: Keyring,
+}
+
+impl EncryptedBackend {
+    fn encrypt(&self, storage_key: &str, data: Vec<u8>) -> Result<Vec<u8>, OxideError> {
+        let key = data_key(&self.keyring, &self.keyring.current)?;
+        let nonce = random_bytes::<NONCE_LEN>();
+        let header = serde_json::to_vec(&BlobHeader {
+            version: 1,
+            algorithm: ALGORITHM.to_string(),
+            key_id: self.keyring.current.clone(),
+            nonce: BASE64.encode(nonce),
+            storage_key: storage_key.to_string(),
+        })?;
+
+        let mut blob = MAGIC.to_vec();
+        blob.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        blob.extend_from_slice(&header);
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &data,
+                    aad: &blob,
+                },
+            )
+            .map_err(|_| OxideError::Encryption(format!("Failed to encrypt {}", storage_key)))?;
+        blob.extend_from_slice(&ciphertext);
+        Ok(blob)
+    }
+
+    fn decrypt(&self, storage_key: &str, blob: &[u8]) -> Result<Vec<u8>, OxideError> {
+        if !is_encrypted(blob) {
+            return Err(OxideError::Encryption(format!(
+                "{} in the sync backend is not encrypted",
+                storage_key
+            )));
+        }
+        let header_end = blob
+            .get(4..8)
+            .map(|len| 8 + u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .filter(|&end| end <= blob.len())
+            .ok_or_else(|| OxideError::Encryption(format!("{} is truncated", storage_key)))?;
+        let header: BlobHeader = serde_json::from_slice(&blob[8..header_end])?;
+        if header.storage_key != storage_key || header.algorithm != ALGORITHM {
+            return Err(OxideError::Encryption(format!(
+                "{} holds data written for {}",
+                storage_key, header.storage_key
+            )));
+        }
+
+        let key = data_key(&self.keyring, &header.key_id)?;
+        let nonce = decode_fixed::<NONCE_LEN>(&header.nonce)?;
+        XChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &blob[header_end..],
+                    aad: &blob[..header_end],
+                },
+            )
+            .map_err(|_| {
+                OxideError::Encryption(format!(
+                    "{} failed authentication, it was altered or damaged",
+                    storage_key
+                ))
+            })
+    }
+}
+
+#[async_trait]
+impl SyncBackend for EncryptedBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, OxideError> {
+        match self.inner.get(key).await? {
+            Some(blob) => Ok(Some(self.decrypt(key, &blob)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), OxideError> {
+        let blob = self.encrypt(key, data)?;
+        self.inner.put(key, blob).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), OxideError> {
+        self.inner.delete(key).await
+    }
+
+    fn describe(&self) -> String {
+        format!("{} (end-to-end encrypted)", self.inner.describe())
+    }
+}
+
+fn is_encrypted(blob: &[u8]) -> bool {
+    blob.starts_with(MAGIC)
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn decode_fixed<const N: usize>(encoded: &str) -> Result<[u8; N], OxideError> {
+    BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| OxideError::Encryption("Malformed key material".to_string()))
+}
+
+fn new_key_id() -> String {
+    random_bytes::<8>()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn data_key(keyring: &Keyring, key_id: &str) -> Result<SecretKey, OxideError> {
+    let key = keyring.keys.get(key_id).ok_or_else(|| {
+        OxideError::Encryption(format!("Data key {} is not in the keyring", key_id))
+    })?;
+    decode_fixed(&key.key)
+}
+
+fn seal(key: &SecretKey, plaintext: &[u8], aad: &[u8]) -> Result<SealedBox, OxideError> {
+    let nonce = random_bytes::<NONCE_LEN>();
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| OxideError::Encryption("Failed to seal key".to_string()))?;
+    Ok(SealedBox {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+/// Open a sealed box, `None` when the key is wrong or the box was altered
+fn unseal(key: &SecretKey, sealed: &SealedBox, aad: &[u8]) -> Result<Option<Vec<u8>>, OxideError> {
+    let nonce = decode_fixed::<NONCE_LEN>(&sealed.nonce)?;
+    let ciphertext = BASE64
+        .decode(&sealed.ciphertext)
+        .map_err(|_| OxideError::Encryption("Malformed key material".to_string()))?;
+    Ok(XChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad,
+            },
+        )
+        .ok())
+}
+
+fn new_kdf_params() -> KdfParams {
+    KdfParams {
+        salt: BASE64.encode(random_bytes::<16>()),
+        memory_kib: KDF_MEMORY_KIB,
+        iterations: KDF_ITERATIONS,
+        parallelism: KDF_PARALLELISM,
+    }
+}
+
+/// Derive the key a passphrase seals the master key with
+///
+/// Argon2 takes tens of MiB and a good fraction of a second, so it runs off the
+/// async runtime.
+async fn derive_key(passphrase: &str, params: &KdfParams) -> Result<SecretKey, OxideError> {
+    let salt = BASE64
+        .decode(&params.salt)
+        .map_err(|_| OxideError::Encryption("Malformed key derivation salt".to_string()))?;
+    check_kdf_params(params, &salt)?;
+    let params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(KEY_LEN),
+    )
+    .map_err(|e| OxideError::Encryption(format!("Invalid key derivation parameters: {}", e)))?;
+
+    let passphrase = passphrase.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut key = [0u8; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| OxideError::Encryption(format!("Failed to derive key: {}", e)))?;
+        Ok(key)
+    })
+    .await
+    .map_err(|e| OxideError::General(format!("Key derivation task failed: {}", e)))?
+}
+
+/// Reject key derivation parameters outside the bounds this version accepts
+fn check_kdf_params(params: &KdfParams, salt: &[u8]) -> Result<(), OxideError> {
+    let valid = KDF_MEMORY_KIB_RANGE.contains(&params.memory_kib)
+        && KDF_ITERATIONS_RANGE.contains(&params.iterations)
+        && KDF_PARALLELISM_RANGE.contains(&params.parallelism)
+        && salt.len() >= KDF_MIN_SALT_LEN;
+    if valid {
+        Ok(())
+    } else {
+        Err(OxideError::Encryption(format!(
+            "{} asks for unsupported key derivation parameters ({} KiB, {} passes, {} lanes, {} byte salt)",
+            KEYS_KEY,
+            params.memory_kib,
+            params.iterations,
+            params.parallelism,
+            salt.len()
+        )))
+    }
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), OxideError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(OxideError::Encryption(format!(
+            "The passphrase must be at least {} characters long",
+            MIN_PASSPHRASE_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Recovery key as users write it down: 64 hex digits in groups of 4
+fn format_recovery_key(key: &SecretKey) -> String {
+    key.chunks(2)
+        .map(|pair| format!("{:02X}{:02X}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn parse_recovery_key(text: &str) -> Result<SecretKey, OxideError> {
+    let digits: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect();
+    let invalid = || OxideError::Encryption("That is not a valid recovery key".to_string());
+    if digits.len() != KEY_LEN * 2 || !digits.is_ascii() {
+        return Err(invalid());
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+fn local_keys_path() -> Result<PathBuf, OxideError> {
+    Ok(crate::oxide_dir()?.join("Sync").join(LOCAL_FILE))
+}
+
+/// Write a file only the current user can read
+async fn write_private(path: &Path, contents: &[u8]) -> Result<(), OxideError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, contents).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+    }
+    Ok(())
+}
+
+async fn load_master_key() -> Result<Option<SecretKey>, OxideError> {
+    let path = local_keys_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let local: LocalKeys = serde_json::from_str(&fs::read_to_string(&path).await?)?;
+    Ok(Some(decode_fixed(&local.master_key)?))
+}
+
+async fn save_master_key(master_key: &SecretKey) -> Result<(), OxideError> {
+    let local = LocalKeys {
+        master_key: BASE64.encode(master_key),
+    };
+    write_private(&local_keys_path()?, &serde_json::to_vec_pretty(&local)?).await
+}
+
+async fn load_key_file(backend: &dyn SyncBackend) -> Result<Option<KeyFile>, OxideError> {
+    match backend.get(KEYS_KEY).await? {
+        Some(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        None => Ok(None),
+    }
+}
+
+async fn save_key_file(backend: &dyn SyncBackend, key_file: &KeyFile) -> Result<(), OxideError> {
+    backend
+        .put(KEYS_KEY, serde_json::to_vec_pretty(key_file)?)
+        .await
+}
+
+fn open_keyring(key_file: &KeyFile, master_key: &SecretKey) -> Result<Keyring, OxideError> {
+    let keyring = unseal(master_key, &key_file.keyring, KEYRING_AAD)?.ok_or_else(|| {
+        OxideError::Encryption(
+            "The keys of this machine do not match the sync backend, unlock it again".to_string(),
+        )
+    })?;
+    Ok(serde_json::from_slice(&keyring)?)
+}
+
+fn seal_keyring(keyring: &Keyring, master_key: &SecretKey) -> Result<SealedBox, OxideError> {
+    seal(master_key, &serde_json::to_vec(keyring)?, KEYRING_AAD)
+}
+
+/// Master key the passphrase unseals
+async fn unlock_with_passphrase(
+    key_file: &KeyFile,
+    passphrase: &str,
+) -> Result<SecretKey, OxideError> {
+    let passphrase_key = derive_key(passphrase, &key_file.kdf).await?;
+    let master_key = unseal(&passphrase_key, &key_file.passphrase, MASTER_KEY_AAD)?
+        .ok_or_else(|| OxideError::Encryption("Wrong passphrase".to_string()))?;
+    master_key
+        .try_into()
+        .map_err(|_| OxideError::Encryption("Malformed key material".to_string()))
+}
+
+/// Master key the recovery key unseals
+fn unlock_with_recovery_key(
+    key_file: &KeyFile,
+    recovery_key: &SecretKey,
+) -> Result<SecretKey, OxideError> {
+    unseal(recovery_key, &key_file.recovery, MASTER_KEY_AAD)?
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| OxideError::Encryption("Wrong recovery key".to_string()))
+}
+
+/// Seal the master key under a new passphrase, with fresh derivation parameters
+async fn set_passphrase(
+    key_file: &mut KeyFile,
+    master_key: &SecretKey,
+    passphrase: &str,
+) -> Result<(), OxideError> {
+    key_file.kdf = new_kdf_params();
+    key_file.passphrase = seal(
+        &derive_key(passphrase, &key_file.kdf).await?,
+        master_key,
+        MASTER_KEY_AAD,
+    )?;
+    Ok(())
+}
+
+/// Seal the master key under a new recovery key, which is returned
+fn set_recovery_key(
+    key_file: &mut KeyFile,
+    master_key: &SecretKey,
+) -> Result<SecretKey, OxideError> {
+    let recovery_key = random_bytes::<KEY_LEN>();
+    key_file.recovery = seal(&recovery_key, master_key, MASTER_KEY_AAD)?;
+    Ok(recovery_key)
+}
+
+/// Add a data key to the keyring and make it current, returning the new keyring
+fn rotate_keyring(key_file: &mut KeyFile, master_key: &SecretKey) -> Result<Keyring, OxideError> {
+    let mut keyring = open_keyring(key_file, master_key)?;
+    let key_id = new_key_id();
+    keyring.keys.insert(
+        key_id.clone(),
+        DataKey {
+            key: BASE64.encode(random_bytes::<KEY_LEN>()),
+            created_at: Utc::now(),
+        },
+    );
+    keyring.current = key_id;
+    key_file.keyring = seal_keyring(&keyring, master_key)?;
+    Ok(keyring)
+}
+
+async fn require_key_file(backend: &dyn SyncBackend) -> Result<KeyFile, OxideError> {
+    load_key_file(backend).await?.ok_or_else(|| {
+        OxideError::Encryption(format!(
+            "Encryption is not turned on for {}",
+            backend.describe()
+        ))
+    })
+}
+
+/// Wrap a backend so blobs are encrypted, when encryption is turned on for it
+///
+/// Fails rather than falling back to plaintext when this machine has turned
+/// encryption on but cannot read the keys.
+pub(crate) async fn encrypt_backend(
+    backend: Box<dyn SyncBackend>,
+) -> Result<Box<dyn SyncBackend>, OxideError> {
+    let key_file = load_key_file(backend.as_ref()).await?;
+    let master_key = load_master_key().await?;
+    match (key_file, master_key) {
+        (None, None) => Ok(backend),
+        (None, Some(_)) => Err(OxideError::Encryption(format!(
+            "{} has no encryption keys, turn encryption on again to keep syncing",
+            backend.describe()
+        ))),
+        (Some(_), None) => Err(OxideError::Encryption(
+            "Synced data is encrypted, unlock it with your passphrase first".to_string(),
+        )),
+        (Some(key_file), Some(master_key)) => {
+            let keyring = open_keyring(&key_file, &master_key)?;
+            Ok(Box::new(EncryptedBackend {
+                inner: backend,
+                keyring,
+            }))
+        }
+    }
+}
+
+/// Get whether synced data is encrypted and readable on this machine
+pub async fn get_sync_encryption() -> Result<EncryptionStatus, OxideError> {
+    let backend = super::backend::open_plain_backend().await?;
+    let Some(key_file) = load_key_file(backend.as_ref()).await? else {
+        return Ok(EncryptionStatus::default());
+    };
+    let mut status = EncryptionStatus {
+        enabled: true,
+        ..EncryptionStatus::default()
+    };
+    if let Some(master_key) = load_master_key().await? {
+        if let Ok(keyring) = open_keyring(&key_file, &master_key) {
+            status.unlocked = true;
+            status.key_count = keyring.keys.len();
+            status.current_key_created_at =
+                keyring.keys.get(&keyring.current).map(|key| key.created_at);
+            status.current_key_id = Some(keyring.current);
+        }
+    }
+    Ok(status)
+}
+
+/// Turn on encryption, returning the recovery key to show the user once
+///
+/// Data already in the backend is encrypted in place.
+pub async fn enable_sync_encryption(passphrase: &str) -> Result<String, OxideError> {
+    check_passphrase(passphrase)?;
+    // A sync meanwhile could write plaintext after the data was encrypted in place
+    let _guard = super::queue::lock().await;
+    let backend = super::backend::open_plain_backend().await?;
+    if load_key_file(backend.as_ref()).await?.is_some() {
+        return Err(OxideError::Encryption(
+            "Encryption is already on, unlock it with your passphrase instead".to_string(),
+        ));
+    }
+
+    let master_key = random_bytes::<KEY_LEN>();
+    let recovery_key = random_bytes::<KEY_LEN>();
+    let kdf = new_kdf_params();
+    let key_id = new_key_id();
+    let keyring = Keyring {
+        current: key_id.clone(),
+        keys: BTreeMap::from([(
+            key_id,
+            DataKey {
+                key: BASE64.encode(random_bytes::<KEY_LEN>()),
+                created_at: Utc::now(),
+            },
+        )]),
+    };
+    let key_file = KeyFile {
+        version: 1,
+        passphrase: seal(
+            &derive_key(passphrase, &kdf).await?,
+            &master_key,
+            MASTER_KEY_AAD,
+        )?,
+        kdf,
+        recovery: seal(&recovery_key, &master_key, MASTER_KEY_AAD)?,
+        keyring: seal_keyring(&keyring, &master_key)?,
+    };
+
+    // Existing data is read before the keys appear, as nothing can read it after
+    let stored = stored_plaintext(backend.as_ref()).await?;
+    save_key_file(backend.as_ref(), &key_file).await?;
+    save_master_key(&master_key).await?;
+
+    let encrypted = EncryptedBackend {
+        inner: backend,
+        keyring,
+    };
+    for (key, data) in stored {
+        encrypted.put(&key, data).await?;
+    }
+    Ok(format_recovery_key(&recovery_key))
+}
+
+/// Everything in the backend the sync code wrote, still unencrypted
+async fn stored_plaintext(backend: &dyn SyncBackend) -> Result<Vec<(String, Vec<u8>)>, OxideError> {
+    let mut keys = vec![super::LIBRARY_KEY.to_string()];
+    keys.extend(super::saves::stored_keys(backend).await?);
+
+    let mut stored = Vec::new();
+    for key in keys {
+        if let Some(data) = backend.get(&key).await? {
+            if !is_encrypted(&data) {
+                stored.push((key, data));
+            }
+        }
+    }
+    Ok(stored)
+}
+
+/// Unlock encrypted sync data on this machine
+pub async fn unlock_sync_encryption(passphrase: &str) -> Result<EncryptionStatus, OxideError> {
+    let backend = super::backend::open_plain_backend().await?;
+    let key_file = require_key_file(backend.as_ref()).await?;
+    let master_key = unlock_with_passphrase(&key_file, passphrase).await?;
+    open_keyring(&key_file, &master_key)?;
+    save_master_key(&master_key).await?;
+    get_sync_encryption().await
+}
+
+/// Unlock with the recovery key after the passphrase was forgotten, setting a new one
+pub async fn recover_sync_encryption(
+    recovery_key: &str,
+    new_passphrase: &str,
+) -> Result<EncryptionStatus, OxideError> {
+    check_passphrase(new_passphrase)?;
+    let recovery_key = parse_recovery_key(recovery_key)?;
+    let backend = super::backend::open_plain_backend().await?;
+    let mut key_file = require_key_file(backend.as_ref()).await?;
+
+    let master_key = unlock_with_recovery_key(&key_file, &recovery_key)?;
+    set_passphrase(&mut key_file, &master_key, new_passphrase).await?;
+    save_key_file(backend.as_ref(), &key_file).await?;
+    save_master_key(&master_key).await?;
+    get_sync_encryption().await
+}
+
+/// Change the passphrase, the data and recovery key stay as they are
+pub async fn change_sync_passphrase(
+    current_passphrase: &str,
+    new_passphrase: &str,
+) -> Result<(), OxideError> {
+    check_passphrase(new_passphrase)?;
+    let backend = super::backend::open_plain_backend().await?;
+    let mut key_file = require_key_file(backend.as_ref()).await?;
+    let master_key = unlock_with_passphrase(&key_file, current_passphrase).await?;
+    set_passphrase(&mut key_file, &master_key, new_passphrase).await?;
+    save_key_file(backend.as_ref(), &key_file).await
+}
+
+/// Start encrypting with a new data key, blobs written before stay readable
+pub async fn rotate_sync_key() -> Result<EncryptionStatus, OxideError> {
+    // Syncs write with the current key, the keyring must not change under them
+    let _guard = super::queue::lock().await;
+    let backend = super::backend::open_plain_backend().await?;
+    let mut key_file = require_key_file(backend.as_ref()).await?;
+    let master_key = load_master_key().await?.ok_or_else(|| {
+        OxideError::Encryption("Unlock synced data before rotating keys".to_string())
+    })?;
+
+    rotate_keyring(&mut key_file, &master_key)?;
+    save_key_file(backend.as_ref(), &key_file).await?;
+    get_sync_encryption().await
+}
+
+/// Write a new recovery key to a file, the previous one stops working
+pub async fn export_recovery_key(
+    passphrase: &str,
+    destination: &str,
+) -> Result<String, OxideError> {
+    let backend = super::backend::open_plain_backend().await?;
+    let mut key_file = require_key_file(backend.as_ref()).await?;
+    let master_key = unlock_with_passphrase(&key_file, passphrase).await?;
+
+    let recovery_key = set_recovery_key(&mut key_file, &master_key)?;
+    let destination = PathBuf::from(destination);
+    let contents = format!(
+        "Oxide sync recovery key\nCreated {}\n\n{}\n",
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        format_recovery_key(&recovery_key)
+    );
+    // The file goes first, a key that was never written down must not replace the old one
+    write_private(&destination, contents.as_bytes()).await?;
+    save_key_file(backend.as_ref(), &key_file).await?;
+
+    Ok(format!(
+        "Recovery key written to {}, earlier recovery keys no longer work",
+        destination.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::backend::FolderBackend;
+    use super::*;
+
+    fn keyring() -> Keyring {
+        let key_id = new_key_id();
+        Keyring {
+            current: key_id.clone(),
+            keys: BTreeMap::from([(
+                key_id,
+                DataKey {
+                    key: BASE64.encode(random_bytes::<KEY_LEN>()),
+                    created_at: Utc::now(),
+                },
+            )]),
+        }
+    }
+
+    fn backend_with(root: &Path, keyring: Keyring) -> EncryptedBackend {
+        EncryptedBackend {
+            inner: Box::new(FolderBackend::new(root.to_path_buf()).unwrap()),
+            keyring,
+        }
+    }
+
+    fn backend(root: &Path) -> EncryptedBackend {
+        backend_with(root, keyring())
+    }
+
+    /// Keys as `enable_sync_encryption` stores them, under unknown passphrase and recovery keys
+    fn key_file(master_key: &SecretKey, keyring: &Keyring) -> KeyFile {
+        KeyFile {
+            version: 1,
+            kdf: new_kdf_params(),
+            passphrase: seal(&random_bytes::<KEY_LEN>(), master_key, MASTER_KEY_AAD).unwrap(),
+            recovery: seal(&random_bytes::<KEY_LEN>(), master_key, MASTER_KEY_AAD).unwrap(),
+            keyring: seal_keyring(keyring, master_key).unwrap(),
+        }
+    }
+
+    /// The JSON header of a blob and the offset of the ciphertext
+    fn header(blob: &[u8]) -> (serde_json::Value, usize) {
+        let end = 8 + u32::from_le_bytes(blob[4..8].try_into().unwrap()) as usize;
+        (serde_json::from_slice(&blob[8..end]).unwrap(), end)
+    }
+
+    fn with_header(blob: &[u8], header: &serde_json::Value) -> Vec<u8> {
+        let (_, end) = self::header(blob);
+        let header = serde_json::to_vec(header).unwrap();
+        let mut altered = MAGIC.to_vec();
+        altered.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        altered.extend_from_slice(&header);
+        altered.extend_from_slice(&blob[end..]);
+        altered
+    }
+
+    #[test]
+    fn seals_and_unseals() {
+        let key = random_bytes::<KEY_LEN>();
+        let sealed = seal(&key, b"master key", MASTER_KEY_AAD).unwrap();
+        assert_eq!(
+            unseal(&key, &sealed, MASTER_KEY_AAD).unwrap().as_deref(),
+            Some(&b"master key"[..])
+        );
+
+        // A wrong key or a box sealed for another purpose does not open
+        let other = random_bytes::<KEY_LEN>();
+        assert_eq!(unseal(&other, &sealed, MASTER_KEY_AAD).unwrap(), None);
+        assert_eq!(unseal(&key, &sealed, KEYRING_AAD).unwrap(), None);
+
+        let mut altered = sealed.clone();
+        let mut ciphertext = BASE64.decode(&altered.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        altered.ciphertext = BASE64.encode(ciphertext);
+        assert_eq!(unseal(&key, &altered, MASTER_KEY_AAD).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn encrypts_blobs() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = backend(root.path());
+        backend
+            .put("library.json", b"{\"games\":[]}".to_vec())
+            .await
+            .unwrap();
+
+        let stored = std::fs::read(root.path().join("library.json")).unwrap();
+        assert!(is_encrypted(&stored));
+        assert!(!stored.windows(7).any(|window| window == b"\"games\""));
+        assert_eq!(
+            backend.get("library.json").await.unwrap().as_deref(),
+            Some(&b"{\"games\":[]}"[..])
+        );
+    }
+
+    #[test]
+    fn rejects_altered_blobs() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = backend(root.path());
+        let blob = backend
+            .encrypt("library.json", b"library".to_vec())
+            .unwrap();
+        assert_eq!(backend.decrypt("library.json", &blob).unwrap(), b"library");
+
+        // Passed off as another blob
+        assert!(backend.decrypt("saves/a/history.json", &blob).is_err());
+
+        // Header rewritten to match the key it is served under
+        let (mut header, _) = header(&blob);
+        header["storage_key"] = "saves/a/history.json".into();
+        let altered = with_header(&blob, &header);
+        assert!(backend
+            .decrypt("saves/a/history.json", &altered)
+            .unwrap_err()
+            .to_string()
+            .contains("failed authentication"));
+
+        // Header bytes changed without changing its meaning
+        let (header, end) = self::header(&blob);
+        let reformatted = serde_json::to_vec_pretty(&header).unwrap();
+        let mut altered = MAGIC.to_vec();
+        altered.extend_from_slice(&(reformatted.len() as u32).to_le_bytes());
+        altered.extend_from_slice(&reformatted);
+        altered.extend_from_slice(&blob[end..]);
+        assert!(backend.decrypt("library.json", &altered).is_err());
+
+        // Ciphertext flipped, truncated or not encrypted at all
+        let mut flipped = blob.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(backend.decrypt("library.json", &flipped).is_err());
+        assert!(backend.decrypt("library.json", &blob[..6]).is_err());
+        assert!(backend.decrypt("library.json", b"{}").is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_kdf_params_out_of_bounds() {
+        let params = new_kdf_params();
+        let salt = BASE64.decode(&params.salt).unwrap();
+        assert!(check_kdf_params(&params, &salt).is_ok());
+
+        for altered in [
+            KdfParams {
+                memory_kib: 8,
+                ..params.clone()
+            },
+            KdfParams {
+                memory_kib: u32::MAX,
+                ..params.clone()
+            },
+            KdfParams {
+                iterations: 1,
+                ..params.clone()
+            },
+            KdfParams {
+                iterations: 1_000_000,
+                ..params.clone()
+            },
+            KdfParams {
+                parallelism: 0,
+                ..params.clone()
+            },
+            KdfParams {
+                salt: BASE64.encode([0u8; 4]),
+                ..params.clone()
+            },
+        ] {
+            assert!(derive_key("correct horse", &altered).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn blobs_written_before_rotation_stay_readable() {
+        let root = tempfile::tempdir().unwrap();
+        let master_key = random_bytes::<KEY_LEN>();
+        let before = keyring();
+        let mut key_file = key_file(&master_key, &before);
+        let old = backend_with(root.path(), before.clone());
+        old.put("library.json", b"before".to_vec()).await.unwrap();
+
+        let after = rotate_keyring(&mut key_file, &master_key).unwrap();
+        assert_ne!(after.current, before.current);
+        assert_eq!(after.keys.len(), 2);
+        // Other machines read the rotated keyring from the key file
+        let opened = open_keyring(&key_file, &master_key).unwrap();
+        assert_eq!(opened.current, after.current);
+
+        let new = backend_with(root.path(), opened);
+        assert_eq!(
+            new.get("library.json").await.unwrap().as_deref(),
+            Some(&b"before"[..])
+        );
+        new.put("saves/a/history.json", b"after".to_vec())
+            .await
+            .unwrap();
+        let blob = std::fs::read(root.path().join("saves/a/history.json")).unwrap();
+        assert_eq!(header(&blob).0["key_id"], after.current.as_str());
+        assert_eq!(
+            new.get("saves/a/history.json").await.unwrap().as_deref(),
+            Some(&b"after"[..])
+        );
+        // A machine that missed the rotation cannot read what was written since
+        assert!(old.get("saves/a/history.json").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn recovery_key_unlocks_without_the_passphrase() {
+        let root = tempfile::tempdir().unwrap();
+        let master_key = random_bytes::<KEY_LEN>();
+        let keyring = keyring();
+        let mut key_file = key_file(&master_key, &keyring);
+        set_passphrase(&mut key_file, &master_key, "forgotten passphrase")
+            .await
+            .unwrap();
+        backend_with(root.path(), keyring)
+            .put("library.json", b"library".to_vec())
+            .await
+            .unwrap();
+
+        // Exporting again replaces the earlier recovery key
+        let replaced = set_recovery_key(&mut key_file, &master_key).unwrap();
+        let recovery_key = set_recovery_key(&mut key_file, &master_key).unwrap();
+        assert!(unlock_with_recovery_key(&key_file, &replaced).is_err());
+        let written_down = format_recovery_key(&recovery_key);
+
+        let recovered =
+            unlock_with_recovery_key(&key_file, &parse_recovery_key(&written_down).unwrap())
+                .unwrap();
+        assert_eq!(recovered, master_key);
+        let backend = backend_with(root.path(), open_keyring(&key_file, &recovered).unwrap());
+        assert_eq!(
+            backend.get("library.json").await.unwrap().as_deref(),
+            Some(&b"library"[..])
+        );
+
+        // Recovering sets a new passphrase, the old one stops working
+        set_passphrase(&mut key_file, &recovered, "new passphrase")
+            .await
+            .unwrap();
+        assert_eq!(
+            unlock_with_passphrase(&key_file, "new passphrase")
+                .await
+                .unwrap(),
+            master_key
+        );
+        assert!(unlock_with_passphrase(&key_file, "forgotten passphrase")
+            .await
+            .is_err());
+    }
+}
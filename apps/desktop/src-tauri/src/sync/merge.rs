@@ -0,0 +1,407 @@
+//! Three-way merge of game libraries
+//!
+//! Every game is compared field by field, as its JSON representation, against
+//! the base snapshot taken at the last sync. A field changed on one side only
+//! takes that side's value. A field both sides changed differently is a
+//! conflict: the side that changed it last wins for now and a conflict record
+//! lets the user review or override the choice. Objects are merged key by key,
+//! arrays and scalars are single fields. A deleted game is a change of the
+//! whole game, the field path `""`.
+
+use super::{ConflictSide, FieldTimes, GameLibrary, SyncConflict};
+use crate::models::Game;
+use crate::OxideError;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+#[derive(Debug)]
+pub struct MergeOutcome {
+    pub library: GameLibrary,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// Merge local and remote libraries against the library both started from
+pub fn merge_libraries(
+    base: &GameLibrary,
+    local: &GameLibrary,
+    remote: &GameLibrary,
+) -> Result<MergeOutcome, OxideError> {
+    let base_games = games_by_id(base)?;
+    let local_games = games_by_id(local)?;
+    let remote_games = games_by_id(remote)?;
+
+    // Local order first, then games only the remote side has
+    let mut ids = Vec::new();
+    let mut seen = HashSet::new();
+    for game in local.games.iter().chain(&remote.games).chain(&base.games) {
+        if seen.insert(game.id.as_str()) {
+            ids.push(game.id.as_str());
+        }
+    }
+
+    let detected_at = Utc::now();
+    let mut library = GameLibrary::default();
+    let mut conflicts = Vec::new();
+    for id in ids {
+        let mut merger = Merger {
+            game_id: id,
+            local_times: local.modified.get(id),
+            remote_times: remote.modified.get(id),
+            detected_at,
+            conflicts: &mut conflicts,
+        };
+        let merged = merger.merge(
+            "",
+            base_games.get(id),
+            local_games.get(id),
+            remote_games.get(id),
+        );
+
+        if let Some(merged) = merged {
+            library.games.push(serde_json::from_value::<Game>(merged)?);
+            let mut times = local.modified.get(id).cloned().unwrap_or_default();
+            for (path, time) in remote.modified.get(id).into_iter().flatten() {
+                let entry = times.entry(path.clone()).or_insert(*time);
+                *entry = (*entry).max(*time);
+            }
+            if !times.is_empty() {
+                library.modified.insert(id.to_string(), times);
+            }
+        }
+    }
+
+    Ok(MergeOutcome { library, conflicts })
+}
+
+/// Stamp fields of `local` that differ from `base` and were not stamped since
+///
+/// Edits to the library carry no times of their own, `edited_at` stands in for
+/// them, usually the time the library was last written.
+pub fn stamp_local_changes(
+    local: &mut GameLibrary,
+    base: &GameLibrary,
+    synced_at: Option<DateTime<Utc>>,
+    edited_at: DateTime<Utc>,
+) -> Result<(), OxideError> {
+    let base_games = games_by_id(base)?;
+    let local_games = games_by_id(local)?;
+
+    let ids: BTreeSet<&String> = base_games.keys().chain(local_games.keys()).collect();
+    for id in ids {
+        let mut paths = Vec::new();
+        changed_paths("", base_games.get(id), local_games.get(id), &mut paths);
+        if paths.is_empty() {
+            continue;
+        }
+
+        let times = local.modified.entry(id.clone()).or_default();
+        for path in paths {
+            let stale = times
+                .get(&path)
+                .is_none_or(|time| synced_at.is_some_and(|synced| *time <= synced));
+            if stale {
+                times.insert(path, edited_at);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Paths of the leaf fields that differ between two versions of a game
+fn changed_paths(path: &str, base: Option<&Value>, current: Option<&Value>, out: &mut Vec<String>) {
+    if let (Some(Value::Object(base)), Some(Value::Object(current))) = (base, current) {
+        let keys: BTreeSet<&String> = base.keys().chain(current.keys()).collect();
+        for key in keys {
+            changed_paths(&join(path, key), base.get(key), current.get(key), out);
+        }
+    } else if base != current {
+        out.push(path.to_string());
+    }
+}
+
+/// Set or remove the value at a field path of a game
+pub fn set_path(game: &mut Value, path: &str, value: Option<Value>) -> Result<(), OxideError> {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let last = parts.pop().unwrap_or_default();
+
+    let mut target = game;
+    for part in parts {
+        target = target
+            .as_object_mut()
+            .ok_or_else(|| OxideError::General(format!("Field {} is not an object", path)))?
+            .entry(part)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    let object = target
+        .as_object_mut()
+        .ok_or_else(|| OxideError::General(format!("Field {} is not an object", path)))?;
+    match value {
+        Some(value) => {
+            object.insert(last.to_string(), value);
+        }
+        None => {
+            object.remove(last);
+        }
+    }
+    Ok(())
+}
+
+fn games_by_id(library: &GameLibrary) -> Result<BTreeMap<String, Value>, OxideError> {
+    library
+        .games
+        .iter()
+        .map(|game| Ok((game.id.clone(), serde_json::to_value(game)?)))
+        .collect()
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+struct Merger<'a> {
+    game_id: &'a str,
+    local_times: Option<&'a FieldTimes>,
+    remote_times: Option<&'a FieldTimes>,
+    detected_at: DateTime<Utc>,
+    conflicts: &'a mut Vec<SyncConflict>,
+}
+
+impl Merger<'_> {
+    fn merge(
+        &mut self,
+        path: &str,
+        base: Option<&Value>,
+        local: Option<&Value>,
+        remote: Option<&Value>,
+    ) -> Option<Value> {
+        if let (Some(Value::Object(local)), Some(Value::Object(remote))) = (local, remote) {
+            let base = match base {
+                Some(Value::Object(base)) => Some(base),
+                _ => None,
+            };
+            let keys: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+            let mut merged = Map::new();
+            for key in keys {
+                let value = self.merge(
+                    &join(path, key),
+                    base.and_then(|base| base.get(key)),
+                    local.get(key),
+                    remote.get(key),
+                );
+                if let Some(value) = value {
+                    merged.insert(key.clone(), value);
+                }
+            }
+            return Some(Value::Object(merged));
+        }
+
+        if local == remote || remote == base {
+            return local.cloned();
+        }
+        if local == base {
+            return remote.cloned();
+        }
+
+        // Both sides changed the field, the later change wins and local wins ties
+        let local_modified = modified_at(self.local_times, path);
+        let remote_modified = modified_at(self.remote_times, path);
+        let applied = if remote_modified > local_modified {
+            ConflictSide::Remote
+        } else {
+            ConflictSide::Local
+        };
+        self.conflicts.push(SyncConflict {
+            id: format!("{}:{}", self.game_id, path),
+            game_id: self.game_id.to_string(),
+            field: (!path.is_empty()).then(|| path.to_string()),
+            base: base.cloned(),
+            local: local.cloned(),
+            remote: remote.cloned(),
+            local_modified,
+            remote_modified,
+            applied,
+            detected_at: self.detected_at,
+        });
+
+        match applied {
+            ConflictSide::Local => local.cloned(),
+            ConflictSide::Remote => remote.cloned(),
+        }
+    }
+}
+
+/// When a field last changed
+///
+/// A stamp on an enclosing field counts for it, and so does the latest stamp on
+/// a field inside it: editing a game's title is a change of the whole game.
+fn modified_at(times: Option<&FieldTimes>, path: &str) -> Option<DateTime<Utc>> {
+    let times = times?;
+    let inner = times
+        .iter()
+        .filter(|(stamped, _)| path.is_empty() || stamped.starts_with(&format!("{}.", path)))
+        .map(|(_, time)| *time)
+        .max();
+
+    let mut path = path;
+    let enclosing = loop {
+        if let Some(time) = times.get(path) {
+            break Some(*time);
+        }
+        if path.is_empty() {
+            break None;
+        }
+        path = path.rsplit_once('.').map_or("", |(parent, _)| parent);
+    };
+    enclosing.max(inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn game(id: &str, title: &str) -> Game {
+        serde_json::from_value(json!({
+            "id": id,
+            "title": title,
+            "slug": id,
+            "media": { "coverImage": "", "screenshots": [], "iconUrl": "", "title": "" },
+            "pricing": { "basePrice": 10.0, "currentPrice": 10.0 },
+            "details": { "platforms": [], "genres": [], "tags": [] },
+            "stats": { "rating": 4.0, "reviewCount": 0, "downloadCount": 0, "wishlistCount": 0 },
+            "features": {
+                "controller": false, "cloudPlay": false, "offline": true, "multiPlayer": false,
+                "singlePlayer": true, "achievements": false, "workshop": false
+            }
+        }))
+        .unwrap()
+    }
+
+    fn library(games: Vec<Game>) -> GameLibrary {
+        GameLibrary {
+            games,
+            ..GameLibrary::default()
+        }
+    }
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap()
+    }
+
+    fn stamp(library: &mut GameLibrary, id: &str, path: &str, time: DateTime<Utc>) {
+        library
+            .modified
+            .entry(id.to_string())
+            .or_default()
+            .insert(path.to_string(), time);
+    }
+
+    #[test]
+    fn takes_edits_made_on_one_side() {
+        let base = library(vec![game("a", "Alpha")]);
+        let mut local_game = game("a", "Alpha Remastered");
+        local_game.stats.rating = 4.0;
+        let mut remote_game = game("a", "Alpha");
+        remote_game.description = Some("Now with a description".to_string());
+
+        let outcome = merge_libraries(
+            &base,
+            &library(vec![local_game]),
+            &library(vec![remote_game, game("b", "Beta")]),
+        )
+        .unwrap();
+
+        assert!(outcome.conflicts.is_empty());
+        let ids: Vec<&str> = outcome
+            .library
+            .games
+            .iter()
+            .map(|g| g.id.as_str())
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+        let merged = &outcome.library.games[0];
+        assert_eq!(merged.title, "Alpha Remastered");
+        assert_eq!(
+            merged.description.as_deref(),
+            Some("Now with a description")
+        );
+    }
+
+    #[test]
+    fn later_edit_wins_a_conflict() {
+        let base = library(vec![game("a", "Alpha")]);
+        let mut local = library(vec![game("a", "Local")]);
+        let mut remote = library(vec![game("a", "Remote")]);
+        stamp(&mut local, "a", "title", at(1));
+        stamp(&mut remote, "a", "title", at(2));
+
+        let outcome = merge_libraries(&base, &local, &remote).unwrap();
+        assert_eq!(outcome.library.games[0].title, "Remote");
+        assert_eq!(outcome.conflicts.len(), 1);
+        let conflict = &outcome.conflicts[0];
+        assert_eq!(conflict.id, "a:title");
+        assert_eq!(conflict.field.as_deref(), Some("title"));
+        assert_eq!(conflict.base, Some(json!("Alpha")));
+        assert_eq!(conflict.applied, ConflictSide::Remote);
+        assert_eq!(outcome.library.modified["a"]["title"], at(2));
+
+        // Local wins ties
+        stamp(&mut remote, "a", "title", at(1));
+        let outcome = merge_libraries(&base, &local, &remote).unwrap();
+        assert_eq!(outcome.library.games[0].title, "Local");
+        assert_eq!(outcome.conflicts[0].applied, ConflictSide::Local);
+    }
+
+    #[test]
+    fn merges_deleted_games() {
+        let base = library(vec![game("a", "Alpha"), game("b", "Beta")]);
+
+        // Deleted on one side and untouched on the other
+        let outcome = merge_libraries(
+            &base,
+            &library(vec![game("b", "Beta")]),
+            &library(vec![game("a", "Alpha"), game("b", "Beta")]),
+        )
+        .unwrap();
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(outcome.library.games.len(), 1);
+        assert_eq!(outcome.library.games[0].id, "b");
+
+        // Deleted remotely after it was edited locally
+        let mut local = library(vec![game("a", "Alpha Edited"), game("b", "Beta")]);
+        let mut remote = library(vec![game("b", "Beta")]);
+        stamp(&mut local, "a", "title", at(3));
+        stamp(&mut remote, "a", "", at(2));
+        let outcome = merge_libraries(&base, &local, &remote).unwrap();
+        assert_eq!(outcome.conflicts.len(), 1);
+        let conflict = &outcome.conflicts[0];
+        assert_eq!(conflict.field, None);
+        assert!(conflict.remote.is_none());
+        assert_eq!(conflict.applied, ConflictSide::Local);
+        assert_eq!(outcome.library.games[0].title, "Alpha Edited");
+    }
+
+    #[test]
+    fn stamps_only_unstamped_local_changes() {
+        let base = library(vec![game("a", "Alpha"), game("b", "Beta")]);
+        let mut edited = game("a", "Alpha Edited");
+        edited.stats.rating = 5.0;
+        let mut local = library(vec![edited]);
+        // Stamped after the last sync, kept
+        stamp(&mut local, "a", "title", at(5));
+        // Stamped before the last sync, a new edit since
+        stamp(&mut local, "a", "stats.rating", at(1));
+
+        stamp_local_changes(&mut local, &base, Some(at(4)), at(6)).unwrap();
+        assert_eq!(local.modified["a"]["title"], at(5));
+        assert_eq!(local.modified["a"]["stats.rating"], at(6));
+        assert_eq!(local.modified["a"].len(), 2);
+        assert_eq!(local.modified["b"][""], at(6));
+    }
+}
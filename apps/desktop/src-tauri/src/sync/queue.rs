@@ -0,0 +1,295 @@
+//! Log of sync operations waiting for the cloud
+//!
+//! A sync is written to the `store` before it runs and removed once it went
+//! through, so a sync that failed, e.g. while offline, is not lost. Failed
+//! operations are retried with exponential backoff, by the `scheduler`, and as
+//! soon as any sync succeeds again. Only failures to reach the cloud are
+//! queued, a sync failing for any other reason would fail again on every retry
+//! and is reported instead. Operations are state based, running one twice is
+//! harmless.
+
+use super::saves;
+use crate::store::{self, SYNC_QUEUE_KEY};
+use crate::OxideError;
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Delay before the first retry, doubled with every failure after it
+const FIRST_RETRY_SECS: i64 = 30;
+const MAX_RETRY_SECS: i64 = 60 * 60;
+
+/// Held while the queue is read, written or replayed, so syncs run one at a time
+/// and cannot interleave their writes to the library
+static QUEUE_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncOp {
+    /// Merge the library with the cloud copy
+    Library,
+    /// Sync the saves of a game
+    Saves { game_id: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedOp {
+    #[serde(flatten)]
+    pub op: SyncOp,
+    pub queued_at: DateTime<Utc>,
+    /// Failed attempts to run it so far
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncQueue {
+    /// Oldest first, the order they are replayed in
+    pub ops: Vec<QueuedOp>,
+    /// Failures in a row, the backoff grows with them
+    pub failures: u32,
+    pub last_error: Option<String>,
+    pub next_retry: Option<DateTime<Utc>>,
+}
+
+impl SyncQueue {
+    fn push(&mut self, op: &SyncOp) {
+        if !self.ops.iter().any(|queued| queued.op == *op) {
+            self.ops.push(QueuedOp {
+                op: op.clone(),
+                queued_at: Utc::now(),
+                attempts: 0,
+            });
+        }
+    }
+
+    fn succeeded(&mut self, op: &SyncOp) {
+        self.ops.retain(|queued| queued.op != *op);
+        self.failures = 0;
+        self.last_error = None;
+        self.next_retry = None;
+    }
+
+    /// Drop an operation retrying would not help
+    fn abandoned(&mut self, op: &SyncOp) {
+        self.ops.retain(|queued| queued.op != *op);
+    }
+
+    fn failed(&mut self, op: &SyncOp, error: &OxideError) {
+        if let Some(queued) = self.ops.iter_mut().find(|queued| queued.op == *op) {
+            queued.attempts += 1;
+        }
+        self.failures += 1;
+        self.last_error = Some(error.to_string());
+        self.next_retry = Some(Utc::now() + backoff(self.failures));
+    }
+
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        !self.ops.is_empty() && self.next_retry.is_none_or(|at| at <= now)
+    }
+
+    /// Whether operations wait for their backoff to run out
+    pub fn backing_off(&self, now: DateTime<Utc>) -> bool {
+        !self.ops.is_empty() && self.next_retry.is_some_and(|at| at > now)
+    }
+}
+
+/// Delay before retrying after `failures` failures in a row
+fn backoff(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    let secs = (FIRST_RETRY_SECS << doublings).min(MAX_RETRY_SECS);
+    // Spread the retries of machines that lost the connection together
+    let jitter = rand::thread_rng().gen_range(0..=secs / 10);
+    Duration::seconds(secs + jitter)
+}
+
+/// Whether an error means the cloud could not be reached, so a retry may succeed
+fn is_transport_error(error: &OxideError) -> bool {
+    match error {
+        // Answers the server will give again, e.g. denied credentials, are not
+        OxideError::Http(e) => e.status().is_none_or(|status| {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        }),
+        // A network folder that is not mounted, local disk errors are not retried
+        OxideError::Backend(_) => true,
+        _ => false,
+    }
+}
+
+/// Get the operations waiting to be synced
+pub async fn get_sync_queue() -> Result<SyncQueue, OxideError> {
+    let _guard = QUEUE_LOCK.lock().await;
+    load_queue().await
+}
+
+/// Wait until no sync runs, for changes to the library outside of one
+pub(crate) async fn lock() -> MutexGuard<'static, ()> {
+    QUEUE_LOCK.lock().await
+}
+
+/// Queue `op` without running it, for the next retry to pick up
+pub(crate) async fn enqueue(op: SyncOp) -> Result<(), OxideError> {
+    let _guard = QUEUE_LOCK.lock().await;
+    let mut queue = load_queue().await?;
+    queue.push(&op);
+    save_queue(&queue).await
+}
+
+/// Queue `op` and run it, `None` when the cloud was out of reach and it stays
+/// queued for a retry
+///
+/// `run` performs the operation. When it succeeds, operations that piled up
+/// while the cloud was unreachable are replayed right away. Any other failure
+/// is returned and the operation dropped from the queue.
+pub(crate) async fn attempt<T>(
+    op: SyncOp,
+    run: impl Future<Output = Result<T, OxideError>>,
+) -> Result<Option<T>, OxideError> {
+    let _guard = QUEUE_LOCK.lock().await;
+    let mut queue = load_queue().await?;
+    queue.push(&op);
+    save_queue(&queue).await?;
+
+    let result = match run.await {
+        Ok(value) => {
+            queue.succeeded(&op);
+            replay(&mut queue).await;
+            Some(value)
+        }
+        Err(e) if is_transport_error(&e) => {
+            queue.failed(&op, &e);
+            None
+        }
+        Err(e) => {
+            queue.abandoned(&op);
+            save_queue(&queue).await?;
+            return Err(e);
+        }
+    };
+    save_queue(&queue).await?;
+    Ok(result)
+}
+
+/// Replay queued operations, only once their backoff ran out unless `force`
+pub async fn retry_sync_queue(force: bool) -> Result<SyncQueue, OxideError> {
+    let _guard = QUEUE_LOCK.lock().await;
+    let mut queue = load_queue().await?;
+    if !queue.ops.is_empty() && (force || queue.is_due(Utc::now())) {
+        replay(&mut queue).await;
+        save_queue(&queue).await?;
+    }
+    Ok(queue)
+}
+
+/// Run queued operations in order, stopping at the first the cloud did not take
+///
+/// An operation failing for another reason is dropped, it would block the rest.
+async fn replay(queue: &mut SyncQueue) {
+    while let Some(queued) = queue.ops.first().cloned() {
+        let result = match &queued.op {
+            SyncOp::Library => super::push_library().await.map(drop),
+            SyncOp::Saves { game_id } => saves::push_saves(game_id).await.map(drop),
+        };
+        match result {
+            Ok(()) => queue.succeeded(&queued.op),
+            Err(e) if is_transport_error(&e) => {
+                queue.failed(&queued.op, &e);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Dropped queued sync {:?}: {}", queued.op, e);
+                queue.abandoned(&queued.op);
+                queue.last_error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+async fn load_queue() -> Result<SyncQueue, OxideError> {
+    Ok(store::blocking(|| store::get_state(SYNC_QUEUE_KEY))
+        .await?
+        .unwrap_or_default())
+}
+
+async fn save_queue(queue: &SyncQueue) -> Result<(), OxideError> {
+    let queue = queue.clone();
+    store::blocking(move || store::set_state(SYNC_QUEUE_KEY, &queue)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_backoff(failures: u32, secs: i64) {
+        for _ in 0..20 {
+            let delay = backoff(failures).num_seconds();
+            assert!(
+                (secs..=secs + secs / 10).contains(&delay),
+                "{} failures waited {}s, expected {}s plus jitter",
+                failures,
+                delay,
+                secs
+            );
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        assert_backoff(0, FIRST_RETRY_SECS);
+        assert_backoff(1, FIRST_RETRY_SECS);
+        assert_backoff(2, 2 * FIRST_RETRY_SECS);
+        assert_backoff(3, 4 * FIRST_RETRY_SECS);
+        assert_backoff(7, 64 * FIRST_RETRY_SECS);
+        assert_backoff(8, MAX_RETRY_SECS);
+        assert_backoff(u32::MAX, MAX_RETRY_SECS);
+    }
+
+    #[test]
+    fn retries_only_when_due() {
+        let now = Utc::now();
+        let mut queue = SyncQueue::default();
+        assert!(!queue.is_due(now));
+
+        let op = SyncOp::Saves {
+            game_id: "a".to_string(),
+        };
+        queue.push(&op);
+        queue.push(&op);
+        assert_eq!(queue.ops.len(), 1);
+        assert!(queue.is_due(now));
+
+        let error = OxideError::Io(std::io::Error::other("offline"));
+        queue.failed(&op, &error);
+        queue.failed(&op, &error);
+        assert_eq!(queue.ops[0].attempts, 2);
+        assert_eq!(queue.failures, 2);
+        assert!(queue.backing_off(now));
+        assert!(!queue.is_due(now));
+        assert!(queue.is_due(now + Duration::seconds(2 * MAX_RETRY_SECS)));
+
+        queue.succeeded(&op);
+        assert!(queue.ops.is_empty());
+        assert_eq!(queue.failures, 0);
+        assert_eq!(queue.next_retry, None);
+    }
+
+    #[test]
+    fn queues_only_transport_errors() {
+        assert!(is_transport_error(&OxideError::Backend(
+            "folder /mnt/share: unreachable".to_string()
+        )));
+        // The local disk being full will not change by retrying
+        assert!(!is_transport_error(&OxideError::Io(std::io::Error::other(
+            "No space left on device"
+        ))));
+        assert!(!is_transport_error(&OxideError::Encryption(
+            "Wrong passphrase".to_string()
+        )));
+        assert!(!is_transport_error(&OxideError::General(
+            "Invalid sync key".to_string()
+        )));
+        let json = serde_json::from_str::<SyncQueue>("{").unwrap_err();
+        assert!(!is_transport_error(&OxideError::Json(json)));
+    }
+}
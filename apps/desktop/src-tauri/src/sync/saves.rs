@@ -0,0 +1,1183 @@
+//! Cloud saves: snapshots of each game's save files, synced between machines
+//!
+//! Save files are found through path patterns from the game's manifest,
+//! `oxide-game.json` in its install directory, unless the user set their own.
+//! A pattern starts with a placeholder for a well known directory and may use
+//! `*`, `?` and `**` wildcards, e.g. `{documents}/My Games/Foo/*.sav`; a pattern
+//! naming a directory takes everything below it. Files are stored under their
+//! pattern form, so a snapshot restores on a machine whose directories differ.
+//!
+//! A snapshot is taken before and after every session and kept as a zip under
+//! `~/.Oxide/Saves/<game id>`, the newest `keep_versions` stay restorable, and
+//! uploaded through the sync backend under `saves/<game id>`. Syncing compares
+//! the newest local and remote snapshots with the saves both sides last agreed
+//! on. When both moved on, as after two machines played offline, neither is
+//! overwritten until the user picks one. A sync that cannot reach the cloud
+//! is queued and retried, see `queue`.
+
+use super::backend::{open_backend, SyncBackend};
+use super::queue::{self, SyncOp};
+use super::scheduler::{self, SyncTrigger};
+use super::ConflictSide;
+use crate::game_downloader::installed_game_dir;
+use crate::store::{self, PlaySession};
+use crate::{persist, OxideError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const MANIFEST_FILE: &str = "oxide-game.json";
+const HISTORY_FILE: &str = "history.json";
+const SETTINGS_FILE: &str = "settings.json";
+const DEFAULT_KEEP_VERSIONS: usize = 10;
+
+/// The part of a game's manifest cloud saves read
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameManifest {
+    #[serde(default)]
+    save_paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveSyncSettings {
+    /// Snapshots kept per game, locally and in the cloud
+    #[serde(default = "default_keep_versions")]
+    pub keep_versions: usize,
+}
+
+impl Default for SaveSyncSettings {
+    fn default() -> Self {
+        SaveSyncSettings {
+            keep_versions: DEFAULT_KEEP_VERSIONS,
+        }
+    }
+}
+
+fn default_keep_versions() -> usize {
+    DEFAULT_KEEP_VERSIONS
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotReason {
+    BeforeSession,
+    AfterSession,
+    Manual,
+    /// The saves a restore or download replaced
+    BeforeRestore,
+    Restored,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveFile {
+    /// Pattern form of the path, e.g. `{documents}/My Games/Foo/slot1.sav`
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveSnapshot {
+    pub id: String,
+    pub game_id: String,
+    pub created_at: DateTime<Utc>,
+    pub reason: SnapshotReason,
+    /// Machine the snapshot was taken on
+    pub machine: String,
+    pub files: Vec<SaveFile>,
+    /// Hash of every file's path and contents, equal for equal saves
+    pub hash: String,
+}
+
+/// Saves that changed on this machine and in the cloud since they last agreed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveConflict {
+    pub game_id: String,
+    pub local: SaveSnapshot,
+    pub remote: SaveSnapshot,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SaveHistory {
+    /// Patterns set by the user, they replace the manifest's
+    #[serde(default)]
+    pub save_paths: Vec<String>,
+    /// Oldest first
+    #[serde(default)]
+    pub snapshots: Vec<SaveSnapshot>,
+    /// Hash of the saves this machine and the cloud last agreed on
+    #[serde(default)]
+    pub synced_hash: Option<String>,
+    #[serde(default)]
+    pub conflict: Option<SaveConflict>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveSyncOutcome {
+    /// Neither side has saves for the game
+    NoSaves,
+    UpToDate,
+    Uploaded,
+    Downloaded,
+    /// Both sides changed, see `get_save_conflicts`
+    Conflict,
+    /// The cloud could not be reached, the sync is queued and retried later
+    Queued,
+}
+
+/// A save path pattern split at its first wildcard
+struct SavePattern {
+    /// Pattern form of the literal part, e.g. `{documents}/My Games/Foo`
+    prefix: String,
+    /// Directory or file the literal part names on this machine
+    root: PathBuf,
+    /// Wildcard components matched below `root`
+    rest: Vec<String>,
+}
+
+fn saves_dir() -> Result<PathBuf, OxideError> {
+    Ok(crate::oxide_dir()?.join("Saves"))
+}
+
+fn game_saves_dir(game_id: &str) -> Result<PathBuf, OxideError> {
+    if game_id.is_empty() || game_id.contains(['/', '\\']) || game_id.starts_with('.') {
+        return Err(OxideError::General(format!("Invalid game id {}", game_id)));
+    }
+    Ok(saves_dir()?.join(game_id))
+}
+
+/// Whether `id` has the shape `create_snapshot` gives ids, `<time>-<8 hex digits>`
+///
+/// Ids of remote snapshots end up in file names, anything else is rejected.
+fn valid_snapshot_id(id: &str) -> bool {
+    let Some((time, hash)) = id.split_once('-') else {
+        return false;
+    };
+    let time = time.as_bytes();
+    time.len() == 18
+        && time[8] == b'T'
+        && time
+            .iter()
+            .enumerate()
+            .all(|(i, c)| i == 8 || c.is_ascii_digit())
+        && hash.len() == 8
+        && hash.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn snapshot_path(game_id: &str, snapshot_id: &str) -> Result<PathBuf, OxideError> {
+    if !valid_snapshot_id(snapshot_id) {
+        return Err(OxideError::General(format!(
+            "Invalid save snapshot id {}",
+            snapshot_id
+        )));
+    }
+    Ok(game_saves_dir(game_id)?
+        .join("snapshots")
+        .join(format!("{}.zip", snapshot_id)))
+}
+
+fn load_history(game_id: &str) -> Result<SaveHistory, OxideError> {
+    let path = game_saves_dir(game_id)?.join(HISTORY_FILE);
+    Ok(persist::read_json_blocking(&path)?.unwrap_or_default())
+}
+
+fn save_history(game_id: &str, history: &SaveHistory) -> Result<(), OxideError> {
+    persist::write_json_blocking(&game_saves_dir(game_id)?.join(HISTORY_FILE), history)
+}
+
+fn load_settings() -> Result<SaveSyncSettings, OxideError> {
+    let path = saves_dir()?.join(SETTINGS_FILE);
+    Ok(persist::read_json_blocking(&path)?.unwrap_or_default())
+}
+
+/// Name snapshots are labelled with, so users can tell machines apart
+fn machine_name() -> String {
+    ["COMPUTERNAME", "HOSTNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Directory a pattern placeholder stands for on this machine
+fn placeholder_dir(name: &str, game_id: &str) -> Result<PathBuf, OxideError> {
+    let dir = match name {
+        "install" => Some(installed_game_dir(game_id)?),
+        "home" => crate::home_dir().ok(),
+        "documents" => dirs::document_dir(),
+        "data" => dirs::data_dir(),
+        "local_data" => dirs::data_local_dir(),
+        "config" => dirs::config_dir(),
+        _ => {
+            return Err(OxideError::General(format!(
+                "Unknown save path placeholder {{{}}}",
+                name
+            )))
+        }
+    };
+    dir.ok_or_else(|| {
+        OxideError::FileOperationError(format!("Could not determine the {{{}}} directory", name))
+    })
+}
+
+/// Components of a pattern form path, the first one a placeholder
+fn split_pattern(pattern: &str) -> Result<(String, Vec<String>), OxideError> {
+    let normalized = pattern.replace('\\', "/");
+    let mut components = normalized
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".");
+    let placeholder = components
+        .next()
+        .and_then(|first| first.strip_prefix('{')?.strip_suffix('}'))
+        .ok_or_else(|| {
+            OxideError::General(format!(
+                "Save path {} must start with a placeholder such as {{documents}}",
+                pattern
+            ))
+        })?
+        .to_string();
+    let components: Vec<String> = components.map(str::to_string).collect();
+    if components.iter().any(|component| component == "..") {
+        return Err(OxideError::General(format!(
+            "Save path {} must not contain ..",
+            pattern
+        )));
+    }
+    Ok((placeholder, components))
+}
+
+fn parse_pattern(pattern: &str, game_id: &str) -> Result<SavePattern, OxideError> {
+    let (placeholder, components) = split_pattern(pattern)?;
+    let literal = components
+        .iter()
+        .position(|component| component.contains(['*', '?']))
+        .unwrap_or(components.len());
+
+    let mut root = placeholder_dir(&placeholder, game_id)?;
+    let mut prefix = format!("{{{}}}", placeholder);
+    for component in &components[..literal] {
+        root.push(component);
+        prefix.push('/');
+        prefix.push_str(component);
+    }
+    Ok(SavePattern {
+        prefix,
+        root,
+        rest: components[literal..].to_vec(),
+    })
+}
+
+/// Path on this machine of a file stored under its pattern form
+fn expand_path(path: &str, game_id: &str) -> Result<PathBuf, OxideError> {
+    let (placeholder, components) = split_pattern(path)?;
+    let mut expanded = placeholder_dir(&placeholder, game_id)?;
+    expanded.extend(components);
+    Ok(expanded)
+}
+
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| wildcard_match(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && wildcard_match(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && wildcard_match(rest, &name[1..]),
+    }
+}
+
+fn components_match(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| components_match(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(name, path)| {
+            let first: Vec<char> = first.chars().collect();
+            let name: Vec<char> = name.chars().collect();
+            wildcard_match(&first, &name) && components_match(rest, path)
+        }),
+    }
+}
+
+/// Whether a file stored under its pattern form is one of the save files `patterns` match
+///
+/// Mirrors `find_save_files`: a pattern without wildcards also takes every file
+/// below the directory it names.
+fn pattern_covers(patterns: &[String], path: &str) -> bool {
+    let Ok((placeholder, components)) = split_pattern(path) else {
+        return false;
+    };
+    let components: Vec<&str> = components.iter().map(String::as_str).collect();
+    patterns.iter().any(|pattern| {
+        let Ok((pattern_placeholder, pattern)) = split_pattern(pattern) else {
+            return false;
+        };
+        if pattern_placeholder != placeholder {
+            return false;
+        }
+        let literal = !pattern
+            .iter()
+            .any(|component| component.contains(['*', '?']));
+        components_match(&pattern, &components)
+            || literal
+                && components.len() > pattern.len()
+                && pattern.iter().zip(&components).all(|(a, b)| a == b)
+    })
+}
+
+/// Files below `dir`, as `/` separated paths relative to it
+fn list_files(dir: &Path, relative: &str, out: &mut Vec<String>) -> Result<(), OxideError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(&entry.path(), &path, out)?;
+        } else if file_type.is_file() {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Save files on this machine, keyed by their pattern form
+fn find_save_files(
+    game_id: &str,
+    patterns: &[String],
+) -> Result<BTreeMap<String, PathBuf>, OxideError> {
+    let mut files = BTreeMap::new();
+    for pattern in patterns {
+        let pattern = parse_pattern(pattern, game_id)?;
+        if pattern.rest.is_empty() && pattern.root.is_file() {
+            files.insert(pattern.prefix, pattern.root);
+            continue;
+        }
+        if !pattern.root.is_dir() {
+            continue;
+        }
+
+        let mut found = Vec::new();
+        list_files(&pattern.root, "", &mut found)?;
+        for relative in found {
+            let components: Vec<&str> = relative.split('/').collect();
+            if pattern.rest.is_empty() || components_match(&pattern.rest, &components) {
+                files.insert(
+                    format!("{}/{}", pattern.prefix, relative),
+                    pattern.root.join(&relative),
+                );
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn save_patterns(game_id: &str, history: &SaveHistory) -> Result<Vec<String>, OxideError> {
+    if !history.save_paths.is_empty() {
+        return Ok(history.save_paths.clone());
+    }
+    let manifest_path = installed_game_dir(game_id)?.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        return Ok(Vec::new());
+    }
+    let manifest: GameManifest = serde_json::from_str(&fs::read_to_string(&manifest_path)?)?;
+    Ok(manifest.save_paths)
+}
+
+/// Zip the given save files into a new snapshot
+fn create_snapshot(
+    game_id: &str,
+    files: &BTreeMap<String, PathBuf>,
+    reason: SnapshotReason,
+) -> Result<SaveSnapshot, OxideError> {
+    let mut contents = Vec::new();
+    let mut hasher = Sha256::new();
+    for (path, file) in files {
+        let data = fs::read(file)?;
+        let sha256 = format!("{:x}", Sha256::digest(&data));
+        hasher.update(format!("{}:{}\n", path, sha256).as_bytes());
+        contents.push((
+            SaveFile {
+                path: path.clone(),
+                size: data.len() as u64,
+                sha256,
+            },
+            data,
+        ));
+    }
+
+    let hash = format!("{:x}", hasher.finalize());
+    let created_at = Utc::now();
+    let id = format!("{}-{}", created_at.format("%Y%m%dT%H%M%S%3f"), &hash[..8]);
+    let archive = snapshot_path(game_id, &id)?;
+    if let Some(parent) = archive.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut writer = ZipWriter::new(fs::File::create(&archive)?);
+    for (file, data) in &contents {
+        writer.start_file(file.path.as_str(), SimpleFileOptions::default())?;
+        writer.write_all(data)?;
+    }
+    writer.finish()?;
+
+    Ok(SaveSnapshot {
+        id,
+        game_id: game_id.to_string(),
+        created_at,
+        reason,
+        machine: machine_name(),
+        files: contents.into_iter().map(|(file, _)| file).collect(),
+        hash,
+    })
+}
+
+/// Replace the save files on this machine with a snapshot's
+///
+/// Files the patterns match that the snapshot does not have are removed, they
+/// were created after it was taken.
+fn restore_snapshot(snapshot: &SaveSnapshot, patterns: &[String]) -> Result<(), OxideError> {
+    let game_id = &snapshot.game_id;
+    let mut archive = ZipArchive::new(fs::File::open(snapshot_path(game_id, &snapshot.id)?)?)?;
+
+    // Read everything first, a damaged snapshot must not leave saves half restored
+    let mut contents = Vec::new();
+    for file in &snapshot.files {
+        // Snapshots from the cloud are not trusted to write outside the save paths
+        if !pattern_covers(patterns, &file.path) {
+            return Err(OxideError::General(format!(
+                "Snapshot {} contains {}, which is not one of the game's save paths",
+                snapshot.id, file.path
+            )));
+        }
+        let mut data = Vec::new();
+        archive.by_name(&file.path)?.read_to_end(&mut data)?;
+        if format!("{:x}", Sha256::digest(&data)) != file.sha256 {
+            return Err(OxideError::General(format!(
+                "Snapshot {} is damaged: {} does not match its hash",
+                snapshot.id, file.path
+            )));
+        }
+        contents.push((expand_path(&file.path, game_id)?, data));
+    }
+
+    let kept: HashSet<&str> = snapshot
+        .files
+        .iter()
+        .map(|file| file.path.as_str())
+        .collect();
+    for (path, file) in find_save_files(game_id, patterns)? {
+        if !kept.contains(path.as_str()) {
+            fs::remove_file(file)?;
+        }
+    }
+    for (path, data) in contents {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+    }
+    Ok(())
+}
+
+/// Drop the oldest snapshots beyond `keep`, except ones a pending conflict or sync needs
+fn prune_snapshots(history: &mut SaveHistory, keep: usize) -> Result<(), OxideError> {
+    let mut needed: HashSet<String> = HashSet::new();
+    if let Some(conflict) = &history.conflict {
+        needed.insert(conflict.local.id.clone());
+        needed.insert(conflict.remote.id.clone());
+    }
+    if let Some(synced) = history
+        .synced_hash
+        .as_ref()
+        .and_then(|hash| history.snapshots.iter().rev().find(|s| &s.hash == hash))
+    {
+        needed.insert(synced.id.clone());
+    }
+    if let Some(head) = history.snapshots.last() {
+        needed.insert(head.id.clone());
+    }
+
+    let mut excess = history.snapshots.len().saturating_sub(keep.max(1));
+    let mut kept = Vec::new();
+    for snapshot in history.snapshots.drain(..) {
+        if excess > 0 && !needed.contains(&snapshot.id) {
+            excess -= 1;
+            let archive = snapshot_path(&snapshot.game_id, &snapshot.id)?;
+            if archive.exists() {
+                fs::remove_file(archive)?;
+            }
+        } else {
+            kept.push(snapshot);
+        }
+    }
+    history.snapshots = kept;
+    Ok(())
+}
+
+/// Snapshot the saves into `history` unless they match its newest snapshot
+fn snapshot_into(
+    game_id: &str,
+    history: &mut SaveHistory,
+    reason: SnapshotReason,
+) -> Result<Option<SaveSnapshot>, OxideError> {
+    let files = find_save_files(game_id, &save_patterns(game_id, history)?)?;
+    if files.is_empty() && history.snapshots.is_empty() {
+        return Ok(None);
+    }
+
+    let snapshot = create_snapshot(game_id, &files, reason)?;
+    if let Some(head) = history.snapshots.last() {
+        if head.hash == snapshot.hash {
+            fs::remove_file(snapshot_path(game_id, &snapshot.id)?)?;
+            return Ok(Some(head.clone()));
+        }
+    }
+    history.snapshots.push(snapshot.clone());
+    prune_snapshots(history, load_settings()?.keep_versions)?;
+    Ok(Some(snapshot))
+}
+
+/// Run snapshot work, zipping and unzipping save files, off the async runtime
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, OxideError> + Send + 'static,
+) -> Result<T, OxideError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| OxideError::General(format!("Save snapshot task failed: {}", e)))?
+}
+
+fn remote_key(game_id: &str, file: &str) -> String {
+    format!("saves/{}/{}", game_id, file)
+}
+
+/// Snapshots in the cloud, oldest first
+async fn fetch_remote_snapshots(
+    backend: &dyn SyncBackend,
+    game_id: &str,
+) -> Result<Vec<SaveSnapshot>, OxideError> {
+    let key = remote_key(game_id, HISTORY_FILE);
+    let snapshots: Vec<SaveSnapshot> = match backend.get(&key).await? {
+        Some(content) => serde_json::from_slice(&content)?,
+        None => return Ok(Vec::new()),
+    };
+    if let Some(invalid) = snapshots
+        .iter()
+        .find(|snapshot| snapshot.game_id != game_id || !valid_snapshot_id(&snapshot.id))
+    {
+        return Err(OxideError::General(format!(
+            "Cloud save history of {} lists an invalid snapshot {} of {}",
+            game_id, invalid.id, invalid.game_id
+        )));
+    }
+    Ok(snapshots)
+}
+
+async fn download_snapshot(
+    backend: &dyn SyncBackend,
+    snapshot: &SaveSnapshot,
+) -> Result<(), OxideError> {
+    let archive = snapshot_path(&snapshot.game_id, &snapshot.id)?;
+    if archive.exists() {
+        return Ok(());
+    }
+    let data = backend
+        .get(&remote_key(
+            &snapshot.game_id,
+            &format!("{}.zip", snapshot.id),
+        ))
+        .await?
+        .ok_or_else(|| {
+            OxideError::NotFound(format!(
+                "Save snapshot {} in {}",
+                snapshot.id,
+                backend.describe()
+            ))
+        })?;
+    if let Some(parent) = archive.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(archive, data)?;
+    Ok(())
+}
+
+async fn upload_snapshot(
+    backend: &dyn SyncBackend,
+    snapshot: &SaveSnapshot,
+    mut remote: Vec<SaveSnapshot>,
+) -> Result<(), OxideError> {
+    let game_id = &snapshot.game_id;
+    let data = fs::read(snapshot_path(game_id, &snapshot.id)?)?;
+    backend
+        .put(&remote_key(game_id, &format!("{}.zip", snapshot.id)), data)
+        .await?;
+
+    remote.push(snapshot.clone());
+    let excess = remote
+        .len()
+        .saturating_sub(load_settings()?.keep_versions.max(1));
+    let dropped: Vec<SaveSnapshot> = remote.drain(..excess).collect();
+    backend
+        .put(
+            &remote_key(game_id, HISTORY_FILE),
+            serde_json::to_vec(&remote)?,
+        )
+        .await?;
+    // Only once the history no longer lists them
+    for snapshot in dropped {
+        backend
+            .delete(&remote_key(game_id, &format!("{}.zip", snapshot.id)))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Storage keys of the saves this machine synced, snapshots before their history
+pub(crate) async fn stored_keys(backend: &dyn SyncBackend) -> Result<Vec<String>, OxideError> {
+    let dir = saves_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut keys = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let game_id = entry.file_name().to_string_lossy().to_string();
+        for snapshot in fetch_remote_snapshots(backend, &game_id).await? {
+            keys.push(remote_key(&game_id, &format!("{}.zip", snapshot.id)));
+        }
+        keys.push(remote_key(&game_id, HISTORY_FILE));
+    }
+    Ok(keys)
+}
+
+/// Make a remote snapshot the current saves, keeping the replaced ones restorable
+async fn adopt_remote(
+    backend: &dyn SyncBackend,
+    game_id: &str,
+    history: &mut SaveHistory,
+    remote: &SaveSnapshot,
+) -> Result<(), OxideError> {
+    download_snapshot(backend, remote).await?;
+    let (game_id, remote_snapshot, mut taken) =
+        (game_id.to_string(), remote.clone(), std::mem::take(history));
+    *history = blocking(move || {
+        snapshot_into(&game_id, &mut taken, SnapshotReason::BeforeRestore)?;
+        restore_snapshot(&remote_snapshot, &save_patterns(&game_id, &taken)?)?;
+        Ok(taken)
+    })
+    .await?;
+    if !history.snapshots.iter().any(|s| s.id == remote.id) {
+        history.snapshots.push(remote.clone());
+    }
+    history.synced_hash = Some(remote.hash.clone());
+    history.conflict = None;
+    prune_snapshots(history, load_settings()?.keep_versions)
+}
+
+/// Get the save path patterns of a game, the user's or else the manifest's
+pub async fn get_save_paths(game_id: &str) -> Result<Vec<String>, OxideError> {
+    save_patterns(game_id, &load_history(game_id)?)
+}
+
+/// Set the save path patterns of a game, an empty list goes back to the manifest's
+pub async fn set_save_paths(game_id: &str, patterns: Vec<String>) -> Result<(), OxideError> {
+    for pattern in &patterns {
+        split_pattern(pattern)?;
+    }
+    let mut history = load_history(game_id)?;
+    history.save_paths = patterns;
+    save_history(game_id, &history)
+}
+
+/// List the restorable snapshots of a game, newest first
+pub async fn list_save_snapshots(game_id: &str) -> Result<Vec<SaveSnapshot>, OxideError> {
+    let mut snapshots = load_history(game_id)?.snapshots;
+    snapshots.reverse();
+    Ok(snapshots)
+}
+
+/// Snapshot a game's saves, `None` when it has none
+pub async fn take_save_snapshot(
+    game_id: &str,
+    reason: SnapshotReason,
+) -> Result<Option<SaveSnapshot>, OxideError> {
+    let game_id = game_id.to_string();
+    blocking(move || {
+        let mut history = load_history(&game_id)?;
+        let snapshot = snapshot_into(&game_id, &mut history, reason)?;
+        save_history(&game_id, &history)?;
+        Ok(snapshot)
+    })
+    .await
+}
+
+/// Put the saves of an earlier snapshot back, the current saves are snapshotted first
+pub async fn restore_save_snapshot(
+    game_id: &str,
+    snapshot_id: &str,
+) -> Result<Option<SaveSnapshot>, OxideError> {
+    let (game_id, snapshot_id) = (game_id.to_string(), snapshot_id.to_string());
+    blocking(move || {
+        let mut history = load_history(&game_id)?;
+        let snapshot = history
+            .snapshots
+            .iter()
+            .find(|s| s.id == snapshot_id)
+            .cloned()
+            .ok_or_else(|| OxideError::NotFound(format!("Save snapshot {}", snapshot_id)))?;
+
+        snapshot_into(&game_id, &mut history, SnapshotReason::BeforeRestore)?;
+        restore_snapshot(&snapshot, &save_patterns(&game_id, &history)?)?;
+        let restored = snapshot_into(&game_id, &mut history, SnapshotReason::Restored)?;
+        save_history(&game_id, &history)?;
+        Ok(restored)
+    })
+    .await
+}
+
+/// Sync a game's saves with the cloud
+pub async fn sync_saves(game_id: &str) -> Result<SaveSyncOutcome, OxideError> {
+    let op = SyncOp::Saves {
+        game_id: game_id.to_string(),
+    };
+    Ok(queue::attempt(op, push_saves(game_id))
+        .await?
+        .unwrap_or(SaveSyncOutcome::Queued))
+}
+
+/// Sync a game's saves, failing when the cloud cannot be reached
+pub(crate) async fn push_saves(game_id: &str) -> Result<SaveSyncOutcome, OxideError> {
+    push_saves_to(open_backend().await?.as_ref(), game_id).await
+}
+
+async fn push_saves_to(
+    backend: &dyn SyncBackend,
+    game_id: &str,
+) -> Result<SaveSyncOutcome, OxideError> {
+    let mut history = load_history(game_id)?;
+    let local = history.snapshots.last().cloned();
+    let remote_snapshots = fetch_remote_snapshots(backend, game_id).await?;
+    let remote = remote_snapshots.last().cloned();
+
+    let synced = history.synced_hash.as_ref();
+    let local_changed = local.as_ref().is_some_and(|l| Some(&l.hash) != synced);
+    let remote_changed = remote.as_ref().is_some_and(|r| Some(&r.hash) != synced);
+
+    let outcome = match (local, remote) {
+        (None, None) => SaveSyncOutcome::NoSaves,
+        (Some(local), Some(remote)) if local.hash == remote.hash => {
+            history.synced_hash = Some(local.hash);
+            history.conflict = None;
+            SaveSyncOutcome::UpToDate
+        }
+        (Some(local), Some(remote)) if local_changed && remote_changed => {
+            let unchanged = history
+                .conflict
+                .as_ref()
+                .is_some_and(|c| c.local.id == local.id && c.remote.id == remote.id);
+            if !unchanged {
+                download_snapshot(backend, &remote).await?;
+                history.conflict = Some(SaveConflict {
+                    game_id: game_id.to_string(),
+                    local,
+                    remote,
+                    detected_at: Utc::now(),
+                });
+            }
+            SaveSyncOutcome::Conflict
+        }
+        (Some(local), _) if local_changed => {
+            upload_snapshot(backend, &local, remote_snapshots).await?;
+            history.synced_hash = Some(local.hash);
+            history.conflict = None;
+            SaveSyncOutcome::Uploaded
+        }
+        (_, Some(remote)) if remote_changed => {
+            adopt_remote(backend, game_id, &mut history, &remote).await?;
+            SaveSyncOutcome::Downloaded
+        }
+        _ => SaveSyncOutcome::UpToDate,
+    };
+
+    save_history(game_id, &history)?;
+    Ok(outcome)
+}
+
+/// Get the games whose saves changed on this machine and in the cloud
+pub async fn get_save_conflicts() -> Result<Vec<SaveConflict>, OxideError> {
+    let dir = saves_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut conflicts = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let game_id = entry.file_name().to_string_lossy().to_string();
+        if let Some(conflict) = load_history(&game_id)?.conflict {
+            conflicts.push(conflict);
+        }
+    }
+    conflicts.sort_by(|a, b| a.game_id.cmp(&b.game_id));
+    Ok(conflicts)
+}
+
+/// Keep this machine's or the cloud's saves of a conflicted game
+///
+/// The other side's saves stay in the snapshot history.
+pub async fn resolve_save_conflict(
+    game_id: &str,
+    choice: ConflictSide,
+) -> Result<SaveSyncOutcome, OxideError> {
+    // A sync running meanwhile would overwrite the history written here
+    let _guard = queue::lock().await;
+    resolve_save_conflict_with(open_backend().await?.as_ref(), game_id, choice).await
+}
+
+async fn resolve_save_conflict_with(
+    backend: &dyn SyncBackend,
+    game_id: &str,
+    choice: ConflictSide,
+) -> Result<SaveSyncOutcome, OxideError> {
+    let mut history = load_history(game_id)?;
+    let conflict = history
+        .conflict
+        .take()
+        .ok_or_else(|| OxideError::NotFound(format!("Save conflict for game {}", game_id)))?;
+
+    let outcome = match choice {
+        ConflictSide::Local => {
+            // Saves played since the conflict was found are what this machine has now
+            let local = history.snapshots.last().cloned().unwrap_or(conflict.local);
+            let remote_snapshots = fetch_remote_snapshots(backend, game_id).await?;
+            upload_snapshot(backend, &local, remote_snapshots).await?;
+            history.synced_hash = Some(local.hash);
+            SaveSyncOutcome::Uploaded
+        }
+        ConflictSide::Remote => {
+            adopt_remote(backend, game_id, &mut history, &conflict.remote).await?;
+            SaveSyncOutcome::Downloaded
+        }
+    };
+
+    save_history(game_id, &history)?;
+    Ok(outcome)
+}
+
+/// Snapshot a game's saves before it is launched and start recording play time
+pub async fn begin_game_session(game_id: &str) -> Result<Option<SaveSnapshot>, OxideError> {
+    let snapshot = take_save_snapshot(game_id, SnapshotReason::BeforeSession).await?;
+    let id = game_id.to_string();
+    store::blocking(move || store::start_play_session(&id)).await?;
+    Ok(snapshot)
+}
+
+/// Snapshot a game's saves after it exits and sync them
+///
+/// The library is synced shortly after too, for play time and the like.
+pub async fn end_game_session(game_id: &str) -> Result<SaveSyncOutcome, OxideError> {
+    let id = game_id.to_string();
+    store::blocking(move || store::end_play_session(&id)).await?;
+    take_save_snapshot(game_id, SnapshotReason::AfterSession).await?;
+    let outcome = sync_saves(game_id).await;
+    scheduler::request_sync(SyncTrigger::SessionEnded);
+    outcome
+}
+
+/// Get the recorded sessions of a game, most recent first
+pub async fn get_play_sessions(game_id: &str) -> Result<Vec<PlaySession>, OxideError> {
+    let id = game_id.to_string();
+    store::blocking(move || store::play_sessions(&id)).await
+}
+
+/// Get the cloud save settings
+pub async fn get_save_sync_settings() -> Result<SaveSyncSettings, OxideError> {
+    load_settings()
+}
+
+/// Set how many snapshots are kept per game
+pub async fn set_save_versions_kept(keep_versions: usize) -> Result<SaveSyncSettings, OxideError> {
+    let settings = SaveSyncSettings {
+        keep_versions: keep_versions.max(1),
+    };
+    persist::write_json_blocking(&saves_dir()?.join(SETTINGS_FILE), &settings)?;
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::backend::FolderBackend;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        wildcard_match(&pattern, &name)
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("*.sav", "slot1.sav"));
+        assert!(matches("*.sav", ".sav"));
+        assert!(matches("slot?.sav", "slot1.sav"));
+        assert!(matches("*", ""));
+        assert!(!matches("slot?.sav", "slot.sav"));
+        assert!(!matches("*.sav", "slot1.sav.bak"));
+        assert!(!matches("Slot*", "slot1"));
+    }
+
+    #[test]
+    fn accepts_only_snapshot_ids_it_creates() {
+        assert!(valid_snapshot_id("20240101T120000123-0a1b2c3d"));
+        for id in [
+            "",
+            "20240101T120000123",
+            "20240101T120000123-0A1B2C3D",
+            "20240101T120000123-0a1b2c3",
+            "20240101X120000123-0a1b2c3d",
+            "2024010T1120000123-0a1b2c3d",
+            "../../../etc/passwd-0a1b2c3d",
+            "20240101T120000123-0a1b2c3d/..",
+        ] {
+            assert!(!valid_snapshot_id(id), "{} should be rejected", id);
+        }
+    }
+
+    #[test]
+    fn covers_files_the_patterns_match() {
+        let saves = patterns(&["{documents}/My Games/Foo/*.sav"]);
+        assert!(pattern_covers(&saves, "{documents}/My Games/Foo/slot1.sav"));
+        assert!(!pattern_covers(
+            &saves,
+            "{documents}/My Games/Foo/config.ini"
+        ));
+        assert!(!pattern_covers(
+            &saves,
+            "{documents}/My Games/Foo/Sub/slot1.sav"
+        ));
+        assert!(!pattern_covers(&saves, "{data}/My Games/Foo/slot1.sav"));
+
+        let recursive = patterns(&["{data}/Foo/**/*.sav"]);
+        assert!(pattern_covers(&recursive, "{data}/Foo/slot.sav"));
+        assert!(pattern_covers(&recursive, "{data}/Foo/a/b/slot.sav"));
+
+        // A directory takes everything below it, but not itself
+        let directory = patterns(&["{home}/.foo"]);
+        assert!(pattern_covers(&directory, "{home}/.foo/saves/1.dat"));
+        assert!(!pattern_covers(&directory, "{home}/.foobar/1.dat"));
+    }
+
+    #[test]
+    fn covers_nothing_outside_the_save_paths() {
+        let saves = patterns(&["{documents}/Foo/*", "{home}/.foo"]);
+        for path in [
+            "{documents}/Foo/../../.bashrc",
+            "{home}/.foo/../.ssh/authorized_keys",
+            "/etc/passwd",
+            "{documents}",
+            "{unknown}/Foo/a",
+        ] {
+            assert!(!pattern_covers(&saves, path), "{} should be rejected", path);
+        }
+    }
+
+    /// Point a game's save paths at a folder in the test home, returning the folder
+    async fn save_folder(game_id: &str) -> PathBuf {
+        let dir = crate::home_dir().unwrap().join(game_id);
+        fs::create_dir_all(&dir).unwrap();
+        set_save_paths(game_id, vec![format!("{{home}}/{}/*.sav", game_id)])
+            .await
+            .unwrap();
+        dir
+    }
+
+    fn snapshot_archives(game_id: &str) -> usize {
+        let dir = game_saves_dir(game_id).unwrap().join("snapshots");
+        fs::read_dir(dir).map_or(0, |entries| entries.count())
+    }
+
+    #[tokio::test]
+    async fn offline_play_on_two_machines_conflicts() {
+        let game_id = "saves-conflict";
+        let cloud = tempfile::tempdir().unwrap();
+        let backend = FolderBackend::new(cloud.path().to_path_buf()).unwrap();
+        let slot = save_folder(game_id).await.join("slot1.sav");
+
+        fs::write(&slot, "start").unwrap();
+        take_save_snapshot(game_id, SnapshotReason::Manual)
+            .await
+            .unwrap();
+        assert_eq!(
+            push_saves_to(&backend, game_id).await.unwrap(),
+            SaveSyncOutcome::Uploaded
+        );
+        assert_eq!(
+            push_saves_to(&backend, game_id).await.unwrap(),
+            SaveSyncOutcome::UpToDate
+        );
+
+        // The other machine plays and uploads
+        fs::write(&slot, "other machine").unwrap();
+        let files = find_save_files(game_id, &get_save_paths(game_id).await.unwrap()).unwrap();
+        let other = create_snapshot(game_id, &files, SnapshotReason::AfterSession).unwrap();
+        let remote = fetch_remote_snapshots(&backend, game_id).await.unwrap();
+        upload_snapshot(&backend, &other, remote).await.unwrap();
+        fs::remove_file(snapshot_path(game_id, &other.id).unwrap()).unwrap();
+
+        // Meanwhile this machine played offline
+        fs::write(&slot, "this machine").unwrap();
+        let local = take_save_snapshot(game_id, SnapshotReason::AfterSession)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            push_saves_to(&backend, game_id).await.unwrap(),
+            SaveSyncOutcome::Conflict
+        );
+        assert_eq!(fs::read_to_string(&slot).unwrap(), "this machine");
+        // The cloud's side is downloaded, so it can be picked offline
+        assert!(snapshot_path(game_id, &other.id).unwrap().exists());
+        let conflicts = get_save_conflicts().await.unwrap();
+        let conflict = conflicts
+            .iter()
+            .find(|conflict| conflict.game_id == game_id)
+            .unwrap();
+        assert_eq!(conflict.local.id, local.id);
+        assert_eq!(conflict.remote.id, other.id);
+
+        // Syncing again leaves the pending conflict as it was
+        assert_eq!(
+            push_saves_to(&backend, game_id).await.unwrap(),
+            SaveSyncOutcome::Conflict
+        );
+        let history = load_history(game_id).unwrap();
+        assert_eq!(history.conflict.unwrap().detected_at, conflict.detected_at);
+        assert_eq!(
+            history.synced_hash.as_ref(),
+            Some(&history.snapshots[0].hash)
+        );
+    }
+
+    #[tokio::test]
+    async fn keeping_either_side_resolves_a_conflict() {
+        let game_id = "saves-resolve";
+        let cloud = tempfile::tempdir().unwrap();
+        let backend = FolderBackend::new(cloud.path().to_path_buf()).unwrap();
+        let slot = save_folder(game_id).await.join("slot1.sav");
+
+        fs::write(&slot, "start").unwrap();
+        take_save_snapshot(game_id, SnapshotReason::Manual)
+            .await
+            .unwrap();
+        push_saves_to(&backend, game_id).await.unwrap();
+
+        // The other machine plays and uploads
+        fs::write(&slot, "other machine").unwrap();
+        let files = find_save_files(game_id, &get_save_paths(game_id).await.unwrap()).unwrap();
+        let other = create_snapshot(game_id, &files, SnapshotReason::AfterSession).unwrap();
+        let remote = fetch_remote_snapshots(&backend, game_id).await.unwrap();
+        upload_snapshot(&backend, &other, remote).await.unwrap();
+        fs::remove_file(snapshot_path(game_id, &other.id).unwrap()).unwrap();
+
+        fs::write(&slot, "this machine").unwrap();
+        let local = take_save_snapshot(game_id, SnapshotReason::AfterSession)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            push_saves_to(&backend, game_id).await.unwrap(),
+            SaveSyncOutcome::Conflict
+        );
+
+        assert_eq!(
+            resolve_save_conflict_with(&backend, game_id, ConflictSide::Remote)
+                .await
+                .unwrap(),
+            SaveSyncOutcome::Downloaded
+        );
+        assert_eq!(fs::read_to_string(&slot).unwrap(), "other machine");
+        let history = load_history(game_id).unwrap();
+        assert!(history.conflict.is_none());
+        // This machine's saves stay restorable
+        assert!(history.snapshots.iter().any(|s| s.hash == local.hash));
+        assert!(
+            resolve_save_conflict_with(&backend, game_id, ConflictSide::Local)
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            push_saves_to(&backend, game_id).await.unwrap(),
+            SaveSyncOutcome::UpToDate
+        );
+
+        // Back to this machine's saves, which then win the next conflict
+        restore_save_snapshot(game_id, &local.id).await.unwrap();
+        assert_eq!(fs::read_to_string(&slot).unwrap(), "this machine");
+        assert_eq!(
+            push_saves_to(&backend, game_id).await.unwrap(),
+            SaveSyncOutcome::Uploaded
+        );
+        let remote = fetch_remote_snapshots(&backend, game_id).await.unwrap();
+        assert_eq!(remote.last().unwrap().hash, local.hash);
+    }
+
+    #[tokio::test]
+    async fn prunes_to_the_versions_kept() {
+        let game_id = "saves-prune";
+        let slot = save_folder(game_id).await.join("slot1.sav");
+
+        let mut taken = Vec::new();
+        for version in 0..5 {
+            fs::write(&slot, format!("version {}", version)).unwrap();
+            let snapshot = take_save_snapshot(game_id, SnapshotReason::Manual)
+                .await
+                .unwrap()
+                .unwrap();
+            taken.push(snapshot);
+        }
+        // Unchanged saves are not snapshotted again
+        let unchanged = take_save_snapshot(game_id, SnapshotReason::Manual)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(unchanged.id, taken[4].id);
+
+        let mut history = load_history(game_id).unwrap();
+        assert_eq!(history.snapshots.len(), 5);
+        history.synced_hash = Some(taken[1].hash.clone());
+        prune_snapshots(&mut history, 2).unwrap();
+        // The snapshot last synced and the newest are kept whatever the limit
+        let kept: Vec<&str> = history.snapshots.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(kept, [taken[1].id.as_str(), taken[4].id.as_str()]);
+        assert_eq!(snapshot_archives(game_id), 2);
+
+        history.synced_hash = None;
+        prune_snapshots(&mut history, 0).unwrap();
+        assert_eq!(history.snapshots.len(), 1);
+        assert_eq!(history.snapshots[0].id, taken[4].id);
+        assert_eq!(snapshot_archives(game_id), 1);
+    }
+}
@@ -0,0 +1,294 @@
+//! Background sync
+//!
+//! While the app runs the scheduler syncs the library when the app starts,
+//! every `interval_minutes`, and shortly after the library changed or a
+//! game session ended. Triggers arriving close together are debounced into a
+//! single sync, and syncs never overlap: the scheduler runs one at a time and
+//! shares the `queue` lock with syncs started by hand. It also retries queued
+//! syncs once their backoff runs out, and while backing off, new triggers only
+//! queue a sync instead of trying the cloud again.
+
+use super::queue::{self, SyncOp};
+use super::{has_local_changes, library_modified_at, sync_dir, sync_library};
+use crate::{persist, OxideError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::time::{self, Instant};
+
+const SCHEDULE_FILE: &str = "schedule.json";
+/// Quiet time after a change before it is synced
+const DEBOUNCE: Duration = Duration::from_secs(5);
+/// Longest a sync is put off by changes that keep coming
+const MAX_DELAY: Duration = Duration::from_secs(60);
+/// How often the library is checked for changes
+const POLL: Duration = Duration::from_secs(5);
+/// Longest syncing may hold up quitting the app
+const EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends triggers to the running scheduler, `None` until it started
+static TRIGGERS: Mutex<Option<UnboundedSender<SyncTrigger>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncTrigger {
+    AppStart,
+    Interval,
+    LibraryChanged,
+    SessionEnded,
+    AppExit,
+}
+
+impl SyncTrigger {
+    /// Whether more changes are likely to follow, so the sync waits for them
+    fn debounced(self) -> bool {
+        matches!(
+            self,
+            SyncTrigger::LibraryChanged | SyncTrigger::SessionEnded
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncSchedule {
+    /// Sync in the background, queued syncs are retried either way
+    pub enabled: bool,
+    pub interval_minutes: u64,
+}
+
+impl Default for SyncSchedule {
+    fn default() -> Self {
+        SyncSchedule {
+            enabled: true,
+            interval_minutes: 15,
+        }
+    }
+}
+
+/// A background sync that ran, passed to the scheduler's listener
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncRun {
+    pub trigger: SyncTrigger,
+    pub finished_at: DateTime<Utc>,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+/// A sync waiting for its trigger to settle
+struct PendingSync {
+    trigger: SyncTrigger,
+    since: Instant,
+    due: Instant,
+}
+
+impl PendingSync {
+    /// The sync pending once `trigger` arrives at `now`
+    fn add(pending: Option<PendingSync>, trigger: SyncTrigger, now: Instant) -> PendingSync {
+        match pending {
+            // Already due, more changes ride along
+            Some(pending) if !pending.trigger.debounced() => pending,
+            Some(pending) if trigger.debounced() => PendingSync {
+                due: (now + DEBOUNCE).min(pending.since + MAX_DELAY),
+                ..pending
+            },
+            pending => PendingSync {
+                trigger,
+                since: pending.map_or(now, |pending| pending.since),
+                due: if trigger.debounced() {
+                    now + DEBOUNCE
+                } else {
+                    now
+                },
+            },
+        }
+    }
+}
+
+/// Ask the running scheduler for a sync, ignored when background sync is off
+pub fn request_sync(trigger: SyncTrigger) {
+    if let Some(sender) = TRIGGERS.lock().ok().and_then(|triggers| triggers.clone()) {
+        let _ = sender.send(trigger);
+    }
+}
+
+/// Get when the library is synced in the background
+pub async fn get_sync_schedule() -> Result<SyncSchedule, OxideError> {
+    let schedule_file = sync_dir()?.join(SCHEDULE_FILE);
+    Ok(persist::read_json(&schedule_file)
+        .await?
+        .unwrap_or_default())
+}
+
+/// Set when the library is synced in the background, taking effect within seconds
+pub async fn set_sync_schedule(schedule: SyncSchedule) -> Result<SyncSchedule, OxideError> {
+    let schedule = SyncSchedule {
+        interval_minutes: schedule.interval_minutes.max(1),
+        ..schedule
+    };
+    persist::write_json(&sync_dir()?.join(SCHEDULE_FILE), &schedule).await?;
+    Ok(schedule)
+}
+
+/// Sync in the background for as long as the app runs
+///
+/// `on_sync` is called after every sync the scheduler ran.
+pub async fn run_scheduler(on_sync: impl Fn(&SyncRun) + Send + 'static) {
+    let (sender, mut triggers) = mpsc::unbounded_channel();
+    if let Ok(mut current) = TRIGGERS.lock() {
+        *current = Some(sender);
+    }
+
+    let mut last_run = Instant::now();
+    let mut pending = Some(PendingSync::add(None, SyncTrigger::AppStart, last_run));
+    let mut library_seen = library_modified_at().await.ok().flatten();
+    loop {
+        let schedule = get_sync_schedule().await.unwrap_or_else(|e| {
+            eprintln!("Failed to load sync schedule: {}", e);
+            SyncSchedule::default()
+        });
+        let interval = Duration::from_secs(schedule.interval_minutes.max(1) * 60);
+        let now = Instant::now();
+        if !schedule.enabled {
+            pending = None;
+        } else if now >= last_run + interval {
+            pending = Some(PendingSync::add(pending, SyncTrigger::Interval, now));
+        }
+
+        if let Some(due) = pending.as_ref().filter(|pending| pending.due <= now) {
+            let trigger = due.trigger;
+            pending = None;
+            let run = run_sync(trigger).await;
+            on_sync(&run);
+            last_run = Instant::now();
+            // The sync rewrote the library, that is not a change to sync
+            library_seen = library_modified_at().await.ok().flatten();
+            continue;
+        }
+
+        if let Err(e) = queue::retry_sync_queue(false).await {
+            eprintln!("Failed to retry queued syncs: {}", e);
+        }
+
+        let mut wake = now + POLL;
+        if let Some(pending) = &pending {
+            wake = wake.min(pending.due);
+        }
+        tokio::select! {
+            Some(trigger) = triggers.recv() => {
+                if schedule.enabled {
+                    pending = Some(PendingSync::add(pending, trigger, Instant::now()));
+                }
+            }
+            _ = time::sleep_until(wake) => {}
+        }
+
+        let modified = library_modified_at().await.ok().flatten();
+        if schedule.enabled && modified != library_seen {
+            library_seen = modified;
+            if has_local_changes().await.unwrap_or(false) {
+                pending = Some(PendingSync::add(
+                    pending,
+                    SyncTrigger::LibraryChanged,
+                    Instant::now(),
+                ));
+            }
+        }
+    }
+}
+
+/// Sync what changed before the app quits, giving up after a few seconds
+pub async fn sync_on_exit() {
+    if !get_sync_schedule()
+        .await
+        .is_ok_and(|schedule| schedule.enabled)
+    {
+        return;
+    }
+    match time::timeout(EXIT_TIMEOUT, run_sync(SyncTrigger::AppExit)).await {
+        Ok(SyncRun {
+            error: Some(error), ..
+        }) => eprintln!("Failed to sync before exiting: {}", error),
+        Ok(_) => {}
+        Err(_) => eprintln!("Sync before exiting timed out"),
+    }
+}
+
+async fn run_sync(trigger: SyncTrigger) -> SyncRun {
+    let result = match queue::get_sync_queue().await {
+        // The cloud failed recently, leave it to the retry instead
+        Ok(queue) if queue.backing_off(Utc::now()) => queue::enqueue(SyncOp::Library)
+            .await
+            .map(|()| "Sync queued until the next retry".to_string()),
+        Ok(_) => sync_library().await,
+        Err(e) => Err(e),
+    };
+    let (message, error) = match result {
+        Ok(message) => (Some(message), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    SyncRun {
+        trigger,
+        finished_at: Utc::now(),
+        message,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn debounces_changes() {
+        let start = Instant::now();
+        let pending = PendingSync::add(None, SyncTrigger::LibraryChanged, start);
+        assert_eq!(pending.trigger, SyncTrigger::LibraryChanged);
+        assert_eq!(pending.due, start + DEBOUNCE);
+
+        // Each change pushes the sync back
+        let pending = PendingSync::add(Some(pending), SyncTrigger::SessionEnded, start + secs(3));
+        assert_eq!(pending.due, start + secs(3) + DEBOUNCE);
+        assert_eq!(pending.since, start);
+        assert_eq!(pending.trigger, SyncTrigger::LibraryChanged);
+    }
+
+    #[test]
+    fn changes_that_keep_coming_wait_at_most_max_delay() {
+        let start = Instant::now();
+        let mut pending = PendingSync::add(None, SyncTrigger::LibraryChanged, start);
+        let mut now = start;
+        while now < start + MAX_DELAY + secs(30) {
+            now += secs(2);
+            pending = PendingSync::add(Some(pending), SyncTrigger::LibraryChanged, now);
+            assert!(pending.due <= start + MAX_DELAY);
+        }
+        assert_eq!(pending.due, start + MAX_DELAY);
+    }
+
+    #[test]
+    fn immediate_triggers_absorb_debounced_ones() {
+        let start = Instant::now();
+
+        // A change waiting to settle is synced right away with the interval's sync
+        let pending = PendingSync::add(None, SyncTrigger::LibraryChanged, start);
+        let pending = PendingSync::add(Some(pending), SyncTrigger::Interval, start + secs(1));
+        assert_eq!(pending.trigger, SyncTrigger::Interval);
+        assert_eq!(pending.due, start + secs(1));
+        assert_eq!(pending.since, start);
+
+        // Changes after a due sync ride along without delaying it
+        let pending = PendingSync::add(None, SyncTrigger::AppStart, start);
+        let pending = PendingSync::add(Some(pending), SyncTrigger::LibraryChanged, start + secs(1));
+        assert_eq!(pending.trigger, SyncTrigger::AppStart);
+        assert_eq!(pending.due, start);
+        let pending = PendingSync::add(Some(pending), SyncTrigger::AppExit, start + secs(2));
+        assert_eq!(pending.trigger, SyncTrigger::AppStart);
+        assert_eq!(pending.due, start);
+    }
+}